@@ -1,8 +1,19 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use depman::registry::{IndexPackage, Registries};
+use depman::sbom::SbomFormat;
+use depman::server::RegistryServer;
+use depman::signing::SecretKey;
+use depman::Workflow;
 use lazy_static::lazy_static;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
 use std::thread;
-use util::model::{Generator, Language};
+use util::context::ProjectContext;
+use util::model::{
+    FeatureSelection, Generator, Language, ProjectOverrides, TargetSelection, TestFramework,
+};
 use util::parse_language;
+use util::testing::TestOptions;
 
 lazy_static! {
     pub static ref AVAILABLE_THREADS: usize = {
@@ -45,6 +56,13 @@ pub enum NewSubcmd {
         /// The name of the folder to generate the project in (defaults to the project name)
         #[arg(short, long)]
         folder: Option<String>,
+
+        /// Scaffolds `tests/` with an example test using this framework
+        #[arg(long, value_enum)]
+        test_framework: Option<TestFramework>,
+
+        #[command(flatten)]
+        overrides: ProjectOverrides,
     },
     /// Initializes a new CMake project in the current directory
     Init {
@@ -66,6 +84,13 @@ pub enum NewSubcmd {
         /// for example, `cpppm init example -l c99` would initialize a new c3pm project using the C99 standard
         #[arg(short, long, value_parser=parse_language, default_value = "cpp23", required = false)]
         language: Language,
+
+        /// Scaffolds `tests/` with an example test using this framework
+        #[arg(long, value_enum)]
+        test_framework: Option<TestFramework>,
+
+        #[command(flatten)]
+        overrides: ProjectOverrides,
     },
     /// Builds the c3pm project
     Build {
@@ -73,20 +98,333 @@ pub enum NewSubcmd {
         #[arg(short = 'j', long = "jobs", default_value_t = *AVAILABLE_THREADS)]
         jobs: usize,
 
-        /// The build config to use (e.g. Debug, RelWithDebInfo, Release)
-        #[arg(short = 'c', long = "config", default_value = "RelWithDebInfo")]
-        config: String,
-
-        /// The generator to build with
+        /// The generator to build with (overrides `generator` in `.cpppm.toml`)
         #[arg(short = 'g', required = false)]
         generator: Option<Generator>,
+
+        #[command(flatten)]
+        features: FeatureSelection,
+
+        #[command(flatten)]
+        overrides: ProjectOverrides,
+
+        #[command(flatten)]
+        targets: TargetSelection,
+    },
+    /// Builds the project's binary, then runs it with the given arguments
+    Run {
+        /// The number of threads to use for building
+        #[arg(short = 'j', long = "jobs", default_value_t = *AVAILABLE_THREADS)]
+        jobs: usize,
+
+        /// The binary to run (required when the project has several)
+        #[arg(long)]
+        bin: Option<String>,
+
+        /// The example to run instead of a binary
+        #[arg(long, conflicts_with = "bin")]
+        example: Option<String>,
+
+        /// The directory to run the binary in (defaults to the current directory)
+        #[arg(long, value_name = "DIR")]
+        working_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        features: FeatureSelection,
+
+        #[command(flatten)]
+        overrides: ProjectOverrides,
+
+        /// The arguments to pass to the binary, after `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Builds the tests in `tests/` and runs them with CTest
+    Test {
+        /// Only run the tests whose name matches this regex
+        filter: Option<String>,
+
+        /// The number of threads to use for building and running the tests
+        #[arg(short = 'j', long = "jobs", default_value_t = *AVAILABLE_THREADS)]
+        jobs: usize,
+
+        /// Stops tests running for longer than this many seconds, counting them as failed
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+
+        #[command(flatten)]
+        features: FeatureSelection,
+
+        #[command(flatten)]
+        overrides: ProjectOverrides,
+    },
+    /// Fetches the project's dependencies into `deps/`
+    Fetch {
+        /// Also fetch the dev-dependencies used by tests and benchmarks
+        #[arg(long)]
+        dev: bool,
+
+        #[command(flatten)]
+        features: FeatureSelection,
+
+        #[command(flatten)]
+        overrides: ProjectOverrides,
+    },
+    /// Adds a GitHub repository to the project's dependencies
+    Add {
+        /// The repository as `owner/repo`, optionally followed by `@revision` (defaults to the
+        /// tag of the newest release)
+        repository: String,
+
+        /// The name of the dependency (defaults to the repository name)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// The host of the repository, e.g. a GitHub Enterprise server (defaults to github.com)
+        #[arg(long)]
+        host: Option<String>,
+
+        /// Add it as a dev-dependency, only used by tests and benchmarks
+        #[arg(long)]
+        dev: bool,
+    },
+    /// Imports the dependencies declared in `vcpkg.json`, `conanfile.txt`, `CPMAddPackage` and
+    /// `FetchContent_Declare` calls, and git submodules into `.cpppm.toml`
+    ImportDeps {
+        /// Only report what would be imported, without changing `.cpppm.toml`
+        #[arg(long)]
+        dry_run: bool,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+    },
+    /// Packs the project's sources into a distributable `.tar.gz` with a SHA-256 checksum
+    Package {
+        /// Package even if files to package have uncommitted changes
+        #[arg(long)]
+        allow_dirty: bool,
+
+        #[command(flatten)]
+        signing: SigningArgs,
+    },
+    /// Packages the project and uploads it to a registry
+    Publish {
+        /// The registry in `[registries]` to publish to (defaults to `default`)
+        #[arg(long)]
+        registry: Option<String>,
+
+        /// The token to authenticate with (defaults to `$C3PM_REGISTRY_TOKEN`)
+        #[arg(long)]
+        token: Option<String>,
+
+        /// Publish even if files to package have uncommitted changes
+        #[arg(long)]
+        allow_dirty: bool,
+
+        #[command(flatten)]
+        signing: SigningArgs,
+    },
+    /// Searches the configured registries for packages
+    Search {
+        /// Matched against package names and descriptions
+        query: String,
+
+        /// Only search the registry with this name in `[registries]`
+        #[arg(long)]
+        registry: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+    },
+    /// Shows a registry package's versions, dependencies, license and repository
+    Info {
+        /// The name of the package
+        name: String,
+
+        /// Only look in the registry with this name in `[registries]`
+        #[arg(long)]
+        registry: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+    },
+    /// Writes the dependencies as plain CMake FetchContent calls pinned to `cpppm.lock`, so the
+    /// project builds without c3pm
+    ExportCmake {
+        #[command(flatten)]
+        features: FeatureSelection,
+
+        #[command(flatten)]
+        overrides: ProjectOverrides,
+    },
+    /// Writes a software bill of materials for the project and its fetched dependencies
+    Sbom {
+        #[arg(long, value_enum, default_value_t = SbomFormatArg::SpdxJson)]
+        format: SbomFormatArg,
+
+        /// Where to write the SBOM (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Also include the dev-dependencies used by tests and benchmarks
+        #[arg(long)]
+        dev: bool,
+
+        #[command(flatten)]
+        features: FeatureSelection,
+
+        #[command(flatten)]
+        overrides: ProjectOverrides,
+    },
+    /// Lists the licenses of the project's fetched dependencies
+    Licenses {
+        /// Also write the license and notice files of every dependency into a third-party
+        /// notices file
+        #[arg(long)]
+        notices: Option<PathBuf>,
+
+        /// Also include the dev-dependencies used by tests and benchmarks
+        #[arg(long)]
+        dev: bool,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+
+        #[command(flatten)]
+        features: FeatureSelection,
+
+        #[command(flatten)]
+        overrides: ProjectOverrides,
+    },
+    /// Checks the locked dependencies against an advisory database, failing when any of them
+    /// are vulnerable
+    Audit {
+        /// The advisory database, a directory or a git repository (defaults to
+        /// `$C3PM_ADVISORY_DB`, or `database` in `[audit]`)
+        #[arg(long)]
+        db: Option<String>,
+
+        /// An advisory id to ignore, on top of `ignore` in `[audit]`
+        #[arg(long)]
+        ignore: Vec<String>,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+    },
+    /// Manages c3pm registries
+    Registry {
+        #[command(subcommand)]
+        subcommand: RegistrySubcmd,
+    },
+    /// Manages package signing keys
+    Key {
+        #[command(subcommand)]
+        subcommand: KeySubcmd,
     },
     /// Cleans the c3pm project
-    Clean {},
+    Clean {
+        #[command(flatten)]
+        overrides: ProjectOverrides,
+    },
     /// Reconfigures the project
     Reconfigure {
         /// (optional) The generator to reconfigure with
         generator: Option<Generator>,
+
+        #[command(flatten)]
+        features: FeatureSelection,
+
+        #[command(flatten)]
+        overrides: ProjectOverrides,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct SigningArgs {
+    /// Sign the package's checksum
+    #[arg(long)]
+    pub sign: bool,
+
+    /// The secret key to sign with (defaults to `$C3PM_SIGNING_KEY`, or `signing.key` in the c3pm
+    /// config directory)
+    #[arg(long, requires = "sign")]
+    pub key: Option<PathBuf>,
+}
+
+impl SigningArgs {
+    fn load_key(&self) -> Result<Option<SecretKey>, String> {
+        if !self.sign {
+            return Ok(None);
+        }
+
+        let key_path = self
+            .key
+            .clone()
+            .unwrap_or_else(depman::signing::default_key_path);
+        SecretKey::load(&key_path)
+            .map(Some)
+            .map_err(|e| format!("{} (create a key with `cpppm key generate`)", e))
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeySubcmd {
+    /// Generates a key pair to sign packages with
+    Generate {
+        /// Where to write the secret key, the public key is written next to it with a `.pub`
+        /// extension (defaults to `$C3PM_SIGNING_KEY`, or `signing.key` in the c3pm config
+        /// directory)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Overwrite an existing key
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SbomFormatArg {
+    SpdxJson,
+    #[value(name = "cyclonedx-json")]
+    CycloneDxJson,
+}
+
+impl From<SbomFormatArg> for SbomFormat {
+    fn from(format: SbomFormatArg) -> Self {
+        match format {
+            SbomFormatArg::SpdxJson => SbomFormat::SpdxJson,
+            SbomFormatArg::CycloneDxJson => SbomFormat::CycloneDxJson,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RegistrySubcmd {
+    /// Serves a local registry from a directory, for testing and small private setups
+    Serve {
+        /// The directory holding the registry's index and archives (created if missing)
+        dir: PathBuf,
+
+        /// The address to listen on
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        address: String,
+
+        /// The token publishers must send (defaults to `$C3PM_REGISTRY_TOKEN`, publishing is
+        /// open to anyone without one)
+        #[arg(long)]
+        token: Option<String>,
+
+        /// The URL consumers reach the registry at, used in the index's archive URLs (defaults
+        /// to `http://{address}`)
+        #[arg(long)]
+        public_url: Option<String>,
     },
 }
 
@@ -99,22 +437,715 @@ pub fn cli() -> Result<(), String> {
             generator,
             language,
             folder,
-        } => util::create_new_project(name, generator, language, folder)?,
+            test_framework,
+            overrides,
+        } => util::create_new_project(
+            name,
+            generator,
+            language,
+            folder,
+            test_framework,
+            &overrides,
+        )?,
         NewSubcmd::Init {
             name,
             generator,
             language,
-        } => util::init_project_subcommand(name, generator, language),
+            test_framework,
+            overrides,
+        } => util::init_project_subcommand(name, generator, language, test_framework, &overrides),
         NewSubcmd::Build {
             jobs,
-            config,
             generator,
-        } => util::build_project(&jobs, &config, generator),
-        NewSubcmd::Clean {} => Ok(util::clean_project().expect("fuck")),
-        NewSubcmd::Reconfigure { generator } => {
-            util::reconfigure_project_subcommand(generator);
+            features,
+            overrides,
+            targets,
+        } => {
+            let context = ProjectContext::current(generator, &overrides)?;
+            fetch_dependencies(&context, Workflow::Build, &features)?;
+            prebuild_dependencies(&context, &features)?;
+            util::build_project(&context, &jobs, &features, &targets)
+        }
+        NewSubcmd::Run {
+            jobs,
+            bin,
+            example,
+            working_dir,
+            features,
+            overrides,
+            args,
+        } => {
+            let context = ProjectContext::current(None, &overrides)?;
+            fetch_dependencies(&context, Workflow::Build, &features)?;
+            prebuild_dependencies(&context, &features)?;
+            let status = util::run_project(
+                &context,
+                &jobs,
+                &features,
+                bin,
+                example,
+                working_dir.as_deref(),
+                &args,
+            )?;
+
+            // Scripts running `cpppm run` see the binary's exit code as if they had run it
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
 
             Ok(())
         }
+        NewSubcmd::Test {
+            filter,
+            jobs,
+            timeout,
+            features,
+            overrides,
+        } => {
+            let context = ProjectContext::current(None, &overrides)?;
+            // The test frameworks are dev-dependencies
+            fetch_dependencies(&context, Workflow::Test, &features)?;
+            prebuild_dependencies(&context, &features)?;
+            util::test_project(
+                &context,
+                &features,
+                &TestOptions {
+                    filter,
+                    jobs,
+                    timeout,
+                },
+            )
+        }
+        NewSubcmd::Fetch {
+            dev,
+            features,
+            overrides,
+        } => {
+            let context = ProjectContext::current(None, &overrides)?;
+
+            match dev {
+                true => fetch_dependencies(&context, Workflow::Test, &features),
+                false => fetch_dependencies(&context, Workflow::Build, &features),
+            }
+        }
+        NewSubcmd::Add {
+            repository,
+            name,
+            host,
+            dev,
+        } => add_dependency(&repository, name, host, dev),
+        NewSubcmd::ImportDeps { dry_run, format } => import_dependencies(dry_run, format),
+        NewSubcmd::Package {
+            allow_dirty,
+            signing,
+        } => package_project(allow_dirty, signing.load_key()?),
+        NewSubcmd::Publish {
+            registry,
+            token,
+            allow_dirty,
+            signing,
+        } => publish_project(registry, token, allow_dirty, signing.load_key()?),
+        NewSubcmd::Search {
+            query,
+            registry,
+            format,
+        } => search_registries(&query, registry.as_deref(), format),
+        NewSubcmd::Info {
+            name,
+            registry,
+            format,
+        } => show_package_info(&name, registry.as_deref(), format),
+        NewSubcmd::ExportCmake {
+            features,
+            overrides,
+        } => export_cmake(&ProjectContext::current(None, &overrides)?, &features),
+        NewSubcmd::Sbom {
+            format,
+            output,
+            dev,
+            features,
+            overrides,
+        } => write_sbom(
+            &ProjectContext::current(None, &overrides)?,
+            format.into(),
+            output,
+            dev,
+            &features,
+        ),
+        NewSubcmd::Licenses {
+            notices,
+            dev,
+            format,
+            features,
+            overrides,
+        } => show_licenses(
+            &ProjectContext::current(None, &overrides)?,
+            notices,
+            dev,
+            format,
+            &features,
+        ),
+        NewSubcmd::Audit { db, ignore, format } => audit_project(db.as_deref(), &ignore, format),
+        NewSubcmd::Registry {
+            subcommand:
+                RegistrySubcmd::Serve {
+                    dir,
+                    address,
+                    token,
+                    public_url,
+                },
+        } => {
+            let token = token.or_else(|| std::env::var(depman::server::TOKEN_VARIABLE).ok());
+
+            RegistryServer::new(&dir, token, public_url)
+                .and_then(|server| server.serve(&address))
+                .map_err(|e| e.to_string())
+        }
+        NewSubcmd::Key {
+            subcommand: KeySubcmd::Generate { output, force },
+        } => generate_key(output, force),
+        NewSubcmd::Clean { overrides } => {
+            let context = ProjectContext::current(None, &overrides)?;
+
+            util::clean_project(&context).map_err(|e| e.to_string())
+        }
+        NewSubcmd::Reconfigure {
+            generator,
+            features,
+            overrides,
+        } => {
+            let context = ProjectContext::current(generator, &overrides)?;
+
+            util::reconfigure_project_subcommand(&context, &features)
+        }
     }
 }
+
+fn fetch_dependencies(
+    context: &ProjectContext,
+    workflow: Workflow,
+    features: &FeatureSelection,
+) -> Result<(), String> {
+    depman::fetch_project_dependencies(context, workflow, features).map_err(|e| e.to_string())
+}
+
+fn add_dependency(
+    repository: &str,
+    name: Option<String>,
+    host: Option<String>,
+    dev: bool,
+) -> Result<(), String> {
+    let current_dir = util::get_current_path().map_err(|e| e.to_string())?;
+    let dependency = util::add_dependency(Path::new(&current_dir), repository, name, host, dev)?;
+
+    println!(
+        "Added {} ({}/{} at {})",
+        dependency.name,
+        dependency.repository.0,
+        dependency.repository.1,
+        dependency.revision.as_deref().unwrap_or_default()
+    );
+
+    Ok(())
+}
+
+fn import_dependencies(dry_run: bool, format: OutputFormat) -> Result<(), String> {
+    let current_dir = util::get_current_path().map_err(|e| e.to_string())?;
+    let report = util::import::import_dependencies(Path::new(&current_dir), dry_run)?;
+
+    if format == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?
+        );
+        return Ok(());
+    }
+
+    for warning in &report.warnings {
+        eprintln!("warning: {}", warning);
+    }
+
+    let verb = if dry_run { "Would import" } else { "Imported" };
+    for imported in &report.imported {
+        let dependency = &imported.dependency;
+        let source = match (&dependency.git, &dependency.url, dependency.system) {
+            (_, _, true) => "system".to_string(),
+            (Some(git), _, _) => git.clone(),
+            (None, Some(url), _) => url.clone(),
+            (None, None, _) => format!("{}/{}", dependency.repository.0, dependency.repository.1),
+        };
+        let revision = dependency
+            .revision
+            .as_deref()
+            .or(dependency.version.as_deref())
+            .map(|revision| format!(" at {}", revision))
+            .unwrap_or_default();
+        let dev = if imported.dev {
+            " as a dev-dependency"
+        } else {
+            ""
+        };
+
+        println!(
+            "{} {} ({}{}){} from {}",
+            verb, dependency.name, source, revision, dev, imported.origin
+        );
+    }
+
+    for existing in &report.existing {
+        println!(
+            "Skipped {} from {}, already declared",
+            existing.dependency.name, existing.origin
+        );
+    }
+
+    for unmapped in &report.unmapped {
+        println!(
+            "Could not import {} from {}: {}",
+            unmapped.name, unmapped.origin, unmapped.reason
+        );
+    }
+
+    if report.imported.is_empty() && report.existing.is_empty() && report.unmapped.is_empty() {
+        println!("No dependencies found to import");
+    }
+
+    Ok(())
+}
+
+fn package_project(allow_dirty: bool, signing_key: Option<SecretKey>) -> Result<(), String> {
+    let current_dir = util::get_current_path().map_err(|e| e.to_string())?;
+    let package = depman::package::package_project(
+        Path::new(&current_dir),
+        allow_dirty,
+        signing_key.as_ref(),
+    )
+    .map_err(|e| e.to_string())?;
+
+    println!(
+        "Packaged {} files into {}\nsha256: {}",
+        package.files.len() + 1,
+        package.archive_path.display(),
+        package.checksum
+    );
+
+    if let Some(signing_key) = &signing_key {
+        println!("signed by {}", signing_key.public_key().id());
+    }
+
+    Ok(())
+}
+
+fn publish_project(
+    registry: Option<String>,
+    token: Option<String>,
+    allow_dirty: bool,
+    signing_key: Option<SecretKey>,
+) -> Result<(), String> {
+    let current_dir = util::get_current_path().map_err(|e| e.to_string())?;
+    let package = depman::publish::publish_project(
+        Path::new(&current_dir),
+        registry.as_deref(),
+        token,
+        allow_dirty,
+        signing_key.as_ref(),
+    )
+    .map_err(|e| e.to_string())?;
+
+    println!(
+        "Published {}",
+        package
+            .archive_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+    );
+
+    Ok(())
+}
+
+fn export_cmake(context: &ProjectContext, features: &FeatureSelection) -> Result<(), String> {
+    let report = depman::export::export_cmake(context, features).map_err(|e| e.to_string())?;
+
+    for warning in &report.warnings {
+        eprintln!("warning: {}", warning);
+    }
+
+    println!(
+        "Exported {} dependencies to {}",
+        report.dependencies.len(),
+        report.path.display()
+    );
+
+    if report.patched_cmakelists {
+        println!("Added the include of the exported file to CMakeLists.txt");
+    }
+
+    Ok(())
+}
+
+fn write_sbom(
+    context: &ProjectContext,
+    format: SbomFormat,
+    output: Option<PathBuf>,
+    dev: bool,
+    features: &FeatureSelection,
+) -> Result<(), String> {
+    let sbom =
+        depman::sbom::generate_sbom(context, features, dev, format).map_err(|e| e.to_string())?;
+
+    match output {
+        Some(output) => {
+            std::fs::write(&output, format!("{}\n", sbom)).map_err(|e| e.to_string())?;
+            eprintln!("Wrote the SBOM to {}", output.display());
+        }
+        None => println!("{}", sbom),
+    }
+
+    Ok(())
+}
+
+fn show_licenses(
+    context: &ProjectContext,
+    notices: Option<PathBuf>,
+    dev: bool,
+    format: OutputFormat,
+    features: &FeatureSelection,
+) -> Result<(), String> {
+    let licenses =
+        depman::license::collect_licenses(context, features, dev).map_err(|e| e.to_string())?;
+
+    if let Some(notices) = &notices {
+        let name = context.config.get_name();
+
+        depman::license::write_notices(&context.root, name, &licenses, notices)
+            .map_err(|e| e.to_string())?;
+        eprintln!("Wrote the third-party notices to {}", notices.display());
+    }
+
+    if format == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&licenses).map_err(|e| e.to_string())?
+        );
+        return Ok(());
+    }
+
+    let rows = licenses
+        .iter()
+        .map(|dependency| {
+            let license = match (dependency.system, &dependency.detected.license) {
+                (true, _) => "(system)".to_string(),
+                (false, Some(license)) => license.clone(),
+                (false, None) => "unknown".to_string(),
+            };
+
+            [
+                dependency.name.clone(),
+                dependency
+                    .version
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+                license,
+                dependency
+                    .detected
+                    .files
+                    .iter()
+                    .map(|file| file.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            ]
+        })
+        .collect::<Vec<[String; 4]>>();
+
+    let header = ["NAME", "VERSION", "LICENSE", "FILES"].map(str::to_string);
+    let widths = (0..3)
+        .map(|column| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .map(|row| row[column].len())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<usize>>();
+
+    for row in std::iter::once(&header).chain(&rows) {
+        println!(
+            "{:<name$}  {:<version$}  {:<license$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            name = widths[0],
+            version = widths[1],
+            license = widths[2]
+        );
+    }
+
+    let violations = licenses
+        .iter()
+        .filter_map(|dependency| {
+            dependency
+                .violation
+                .as_ref()
+                .map(|violation| format!("{}: {}", dependency.name, violation))
+        })
+        .collect::<Vec<String>>();
+
+    if !violations.is_empty() {
+        println!(
+            "\nViolations of the `[licenses]` policy:\n  {}",
+            violations.join("\n  ")
+        );
+    }
+
+    Ok(())
+}
+
+fn audit_project(
+    database: Option<&str>,
+    ignore: &[String],
+    format: OutputFormat,
+) -> Result<(), String> {
+    let current_dir = util::get_current_path().map_err(|e| e.to_string())?;
+    let report = depman::audit::audit_project(Path::new(&current_dir), database, ignore)
+        .map_err(|e| e.to_string())?;
+
+    if format == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?
+        );
+    } else {
+        println!(
+            "Checked {} locked packages against {} advisories from {}",
+            report.packages, report.advisories, report.database
+        );
+
+        for finding in &report.vulnerabilities {
+            let advisory = &finding.advisory;
+            let version = finding
+                .version
+                .as_deref()
+                .or(finding.commit.as_deref())
+                .unwrap_or("-");
+
+            println!();
+            println!("{} {}: {}", finding.package, version, advisory.title);
+            println!("  advisory: {}", advisory.id);
+            println!(
+                "  severity: {}",
+                advisory
+                    .severity
+                    .map(|severity| severity.to_string())
+                    .unwrap_or_else(|| "unknown".to_string())
+            );
+            if !advisory.aliases.is_empty() {
+                println!("  aliases:  {}", advisory.aliases.join(", "));
+            }
+            match finding.patched.is_empty() {
+                true => println!("  fixed in: no fixed version yet"),
+                false => println!("  fixed in: {}", finding.patched.join(", ")),
+            }
+            if let Some(url) = &advisory.url {
+                println!("  url:      {}", url);
+            }
+        }
+
+        for finding in &report.unchecked {
+            eprintln!(
+                "warning: couldn't tell whether {} is affected by {}, it has no comparable version or commit",
+                finding.package, finding.advisory.id
+            );
+        }
+    }
+
+    match report.vulnerabilities.len() {
+        0 => {
+            if format == OutputFormat::Human {
+                println!("\nNo vulnerable dependencies found");
+            }
+
+            Ok(())
+        }
+        1 => Err("1 vulnerable dependency found".to_string()),
+        count => Err(format!("{} vulnerable dependencies found", count)),
+    }
+}
+
+fn generate_key(output: Option<PathBuf>, force: bool) -> Result<(), String> {
+    let key_path = output.unwrap_or_else(depman::signing::default_key_path);
+
+    if key_path.exists() && !force {
+        return Err(format!(
+            "{} already exists, pass `--force` to replace it",
+            key_path.display()
+        ));
+    }
+
+    let secret_key = SecretKey::generate().map_err(|e| e.to_string())?;
+    let public_path = secret_key.save(&key_path).map_err(|e| e.to_string())?;
+
+    println!(
+        "Generated key {}\nsecret key: {}\npublic key: {}",
+        secret_key.public_key().id(),
+        key_path.display(),
+        public_path.display()
+    );
+
+    Ok(())
+}
+
+/// A search result, as printed by `cpppm search --format json`
+#[derive(Serialize)]
+struct SearchResult<'a> {
+    name: &'a str,
+    version: Option<&'a str>,
+    description: Option<&'a str>,
+    registry: &'a str,
+}
+
+/// A package, as printed by `cpppm info --format json`
+#[derive(Serialize)]
+struct PackageInfo<'a> {
+    registry: &'a str,
+    #[serde(flatten)]
+    package: &'a IndexPackage,
+}
+
+fn open_registries() -> Result<Registries, String> {
+    let current_dir = util::get_current_path().map_err(|e| e.to_string())?;
+    let config = util::load_project_config(&Path::new(&current_dir).join(".cpppm.toml"))?;
+
+    Registries::open(&config.registries).map_err(|e| e.to_string())
+}
+
+fn search_registries(
+    query: &str,
+    registry: Option<&str>,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let results = open_registries()?
+        .search(registry, query)
+        .map_err(|e| e.to_string())?;
+
+    let results = results
+        .iter()
+        .map(|(registry, package)| SearchResult {
+            name: &package.name,
+            version: package.latest().map(|latest| latest.version.as_str()),
+            description: package.description.as_deref(),
+            registry,
+        })
+        .collect::<Vec<SearchResult>>();
+
+    if format == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&results).map_err(|e| e.to_string())?
+        );
+        return Ok(());
+    }
+
+    if results.is_empty() {
+        println!("No packages matching `{}` found", query);
+    }
+
+    let name_width = results
+        .iter()
+        .map(|result| result.name.len() + result.version.unwrap_or("-").len() + 5)
+        .max()
+        .unwrap_or_default();
+
+    for result in &results {
+        let entry = format!("{} = \"{}\"", result.name, result.version.unwrap_or("-"));
+
+        match result.description {
+            Some(description) => {
+                println!("{:<width$} # {}", entry, description, width = name_width)
+            }
+            None => println!("{}", entry),
+        }
+    }
+
+    Ok(())
+}
+
+fn show_package_info(
+    name: &str,
+    registry: Option<&str>,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let (registry, package) = open_registries()?
+        .find(registry, name)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Package `{}` not found", name))?;
+
+    if format == OutputFormat::Json {
+        let info = PackageInfo {
+            registry: &registry,
+            package: &package,
+        };
+
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&info).map_err(|e| e.to_string())?
+        );
+        return Ok(());
+    }
+
+    let latest = package.latest();
+
+    println!(
+        "{} {}",
+        package.name,
+        latest.map(|latest| latest.version.as_str()).unwrap_or("-")
+    );
+
+    if let Some(description) = &package.description {
+        println!("{}", description);
+    }
+
+    println!();
+    println!(
+        "license: {}",
+        package.license.as_deref().unwrap_or("unknown")
+    );
+    if let Some(repository) = &package.repository {
+        println!("repository: {}", repository);
+    }
+    println!("registry: {}", registry);
+
+    let mut versions = package.versions.iter().collect::<Vec<_>>();
+    versions
+        .sort_by_cached_key(|entry| std::cmp::Reverse(semver::Version::parse(&entry.version).ok()));
+
+    let versions = versions
+        .iter()
+        .map(|entry| match entry.yanked {
+            true => format!("{} (yanked)", entry.version),
+            false => entry.version.clone(),
+        })
+        .collect::<Vec<String>>();
+    println!("versions: {}", versions.join(", "));
+
+    let dependencies = latest
+        .map(|latest| &latest.deps)
+        .filter(|deps| !deps.is_empty());
+    if let Some(dependencies) = dependencies {
+        println!("dependencies:");
+
+        for (dependency, requirement) in dependencies {
+            println!("    {} = \"{}\"", dependency, requirement);
+        }
+    }
+
+    Ok(())
+}
+
+fn prebuild_dependencies(
+    context: &ProjectContext,
+    features: &FeatureSelection,
+) -> Result<(), String> {
+    depman::prebuild_dependencies(context, features).map_err(|e| e.to_string())
+}
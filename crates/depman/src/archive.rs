@@ -0,0 +1,103 @@
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+/// Packs the contents of `source_dir` into a `.tar.gz` at `archive_path`
+pub fn create_archive(source_dir: &Path, archive_path: &Path) -> Result<(), Box<dyn Error>> {
+    let archive_file = File::create(archive_path)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(archive_file, Compression::default()));
+
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", source_dir)?;
+    builder.into_inner()?.finish()?;
+
+    Ok(())
+}
+
+/// Unpacks a `.tar.gz` into `destination_dir`, creating it if needed
+pub fn extract_archive(archive_path: &Path, destination_dir: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(destination_dir)?;
+
+    let archive_file = File::open(archive_path)?;
+    tar::Archive::new(GzDecoder::new(archive_file)).unpack(destination_dir)?;
+
+    Ok(())
+}
+
+/// The hex encoded SHA-256 of the file at `path`
+pub fn sha256_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Downloads a source archive from a `file://` or HTTP(S) URL (or a plain path) into
+/// `destination_dir`, checking it against `checksum` if there is one. Source archives usually
+/// wrap everything in a single top-level directory, which is stripped.
+pub fn fetch_source_archive(
+    url: &str,
+    checksum: Option<&str>,
+    destination_dir: &Path,
+) -> Result<String, Box<dyn Error>> {
+    let archive = tempfile::NamedTempFile::new()?;
+
+    if url.starts_with("http://") || url.starts_with("https://") {
+        let response = ureq::get(url).call()?;
+        io::copy(
+            &mut response.into_reader(),
+            &mut File::create(archive.path())?,
+        )?;
+    } else {
+        fs::copy(url.strip_prefix("file://").unwrap_or(url), archive.path())?;
+    }
+
+    let actual = sha256_file(archive.path())?;
+    if let Some(expected) = checksum
+        && !expected.eq_ignore_ascii_case(&actual)
+    {
+        return Err(format!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            url, expected, actual
+        )
+        .into());
+    }
+
+    let unpacked = tempfile::tempdir()?;
+    extract_archive(archive.path(), unpacked.path())?;
+
+    let entries = fs::read_dir(unpacked.path())?.collect::<Result<Vec<_>, _>>()?;
+    let root = match entries.as_slice() {
+        [entry] if entry.file_type()?.is_dir() => entry.path(),
+        _ => unpacked.path().to_path_buf(),
+    };
+
+    if let Some(parent) = destination_dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    copy_dir(&root, destination_dir)?;
+
+    Ok(actual)
+}
+
+fn copy_dir(source_dir: &Path, destination_dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(destination_dir)?;
+
+    for entry in fs::read_dir(source_dir)? {
+        let entry = entry?;
+        let destination = destination_dir.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &destination)?;
+        } else {
+            fs::copy(entry.path(), destination)?;
+        }
+    }
+
+    Ok(())
+}
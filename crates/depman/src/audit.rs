@@ -0,0 +1,544 @@
+use crate::lockfile::{LockedPackage, Lockfile};
+use crate::registry::update_git_index;
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// The environment variable pointing to the advisory database, overriding `[audit] database`
+pub const DATABASE_VARIABLE: &str = "C3PM_ADVISORY_DB";
+
+/// An advisory, stored as a TOML file anywhere in the database (e.g.
+/// `packages/zlib/C3PM-2022-0001.toml`):
+///
+/// ```toml
+/// [advisory]
+/// id = "C3PM-2022-0001"
+/// package = "zlib"
+/// repository = "https://github.com/madler/zlib"
+/// title = "Heap buffer overflow in inflate"
+/// severity = "critical"
+/// aliases = ["CVE-2022-37434"]
+///
+/// [versions]
+/// patched = [">=1.2.13"]
+///
+/// [[commits]]
+/// fixed = "eff308af425b67093bab25f80f1ae950166bece1"
+/// ```
+///
+/// A package is affected when its version matches neither `patched` nor `unaffected`, or for
+/// packages without a version, when its commit is in one of the `commits` ranges. An advisory
+/// without either applies to every version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Advisory {
+    pub advisory: AdvisoryDetails,
+    #[serde(default)]
+    pub versions: AffectedVersions,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commits: Vec<CommitRange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AdvisoryDetails {
+    pub id: String,
+    /// The name of the affected package
+    pub package: String,
+    /// The affected repository, matched against the sources of dependencies so they are found
+    /// under any name
+    pub repository: Option<String>,
+    pub title: String,
+    pub description: Option<String>,
+    pub date: Option<String>,
+    pub severity: Option<Severity>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    None,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::None => "none",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+/// Semver requirements, e.g. `>=1.2.13` or `^1.1.5`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AffectedVersions {
+    /// The versions the vulnerability is fixed in
+    #[serde(default)]
+    pub patched: Vec<String>,
+    /// The versions that never had the vulnerability
+    #[serde(default)]
+    pub unaffected: Vec<String>,
+}
+
+/// The commits from `introduced` (or the first commit) up to, but not including, `fixed` (or
+/// the latest commit)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitRange {
+    pub introduced: Option<String>,
+    pub fixed: Option<String>,
+}
+
+pub struct AdvisoryDatabase {
+    pub location: String,
+    pub advisories: Vec<Advisory>,
+}
+
+/// A locked package an advisory applies to
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub package: String,
+    pub version: Option<String>,
+    pub commit: Option<String>,
+    pub advisory: AdvisoryDetails,
+    /// The versions the vulnerability is fixed in
+    pub patched: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditReport {
+    pub database: String,
+    pub advisories: usize,
+    pub packages: usize,
+    pub vulnerabilities: Vec<Finding>,
+    /// Packages an advisory names that couldn't be matched against its ranges, because neither
+    /// their version nor their commit could be compared
+    pub unchecked: Vec<Finding>,
+}
+
+impl AdvisoryDatabase {
+    /// Opens the database at `location`, a directory or a git repository (`git+<url>` or a URL
+    /// ending in `.git`) that is cloned into (or updated in) the c3pm cache
+    pub fn open(location: &str) -> Result<Self, Box<dyn Error>> {
+        let git_url = location
+            .strip_prefix("git+")
+            .or_else(|| location.ends_with(".git").then_some(location));
+
+        let root = match git_url {
+            Some(git_url) => {
+                let digest = hex::encode(Sha256::digest(git_url.as_bytes()));
+                let root = util::get_cache_dir()
+                    .join("advisory-db")
+                    .join(&digest[..16]);
+                update_git_index(git_url, &root)?;
+
+                root
+            }
+            None => PathBuf::from(location.strip_prefix("file://").unwrap_or(location)),
+        };
+
+        if !root.is_dir() {
+            return Err(format!("Advisory database {} not found", root.display()).into());
+        }
+
+        let mut advisories = Vec::new();
+        let entries = WalkDir::new(&root)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "toml"));
+
+        for entry in entries {
+            let advisory = toml::from_str::<Advisory>(&fs::read_to_string(entry.path())?)
+                .map_err(|e| format!("Invalid advisory {}: {}", entry.path().display(), e))?;
+            advisories.push(advisory);
+        }
+
+        Ok(AdvisoryDatabase {
+            location: location.to_string(),
+            advisories,
+        })
+    }
+}
+
+/// Checks the packages in the project's `cpppm.lock` against the advisory database at
+/// `database` (falling back to `$C3PM_ADVISORY_DB` and `[audit] database`), skipping the
+/// advisories in `ignore` and `[audit] ignore`
+pub fn audit_project(
+    project_root: &Path,
+    database: Option<&str>,
+    ignore: &[String],
+) -> Result<AuditReport, Box<dyn Error>> {
+    let config = util::load_project_config(&project_root.join(".cpppm.toml"))?;
+    let audit_config = config.audit.unwrap_or_default();
+
+    let location = database
+        .map(str::to_string)
+        .or_else(|| std::env::var(DATABASE_VARIABLE).ok())
+        .or(audit_config.database)
+        .ok_or("No advisory database, set `database` in `[audit]` or pass `--db`")?;
+    let database = AdvisoryDatabase::open(&location)?;
+    let lockfile = Lockfile::load(project_root)?;
+
+    let mut report = AuditReport {
+        database: location,
+        advisories: database.advisories.len(),
+        packages: lockfile.package.len(),
+        vulnerabilities: Vec::new(),
+        unchecked: Vec::new(),
+    };
+
+    let advisories = database.advisories.iter().filter(|advisory| {
+        let id = &advisory.advisory.id;
+        !ignore.contains(id) && !audit_config.ignore.contains(id)
+    });
+
+    for advisory in advisories {
+        for package in &lockfile.package {
+            if !advisory.names(package) {
+                continue;
+            }
+
+            let finding = Finding {
+                package: package.name.clone(),
+                version: package.version_or_revision(),
+                commit: package.commit.clone(),
+                advisory: advisory.advisory.clone(),
+                patched: advisory.versions.patched.clone(),
+            };
+
+            match advisory.affects(package, &project_root.join("deps").join(&package.name)) {
+                Some(true) => report.vulnerabilities.push(finding),
+                Some(false) => {}
+                None => report.unchecked.push(finding),
+            }
+        }
+    }
+
+    report.vulnerabilities.sort_by(|a, b| {
+        b.advisory
+            .severity
+            .cmp(&a.advisory.severity)
+            .then_with(|| a.package.cmp(&b.package))
+    });
+
+    Ok(report)
+}
+
+impl Advisory {
+    /// Whether the advisory is about `package`, by its source when the advisory names a
+    /// repository and the package was fetched from one, and by its name otherwise
+    fn names(&self, package: &LockedPackage) -> bool {
+        match (
+            &self.advisory.repository,
+            package.source.strip_prefix("git+"),
+        ) {
+            (Some(repository), Some(url)) => {
+                normalize_repository(repository) == normalize_repository(url)
+            }
+            _ => self.advisory.package == package.name,
+        }
+    }
+
+    /// Whether `package` (fetched into `source_dir`) is affected, or `None` when that can't be
+    /// told
+    fn affects(&self, package: &LockedPackage, source_dir: &Path) -> Option<bool> {
+        let versions = &self.versions;
+        let has_versions = !versions.patched.is_empty() || !versions.unaffected.is_empty();
+
+        if !has_versions && self.commits.is_empty() {
+            return Some(true);
+        }
+
+        let version = package
+            .version_or_revision()
+            .and_then(|version| parse_version(&version));
+
+        if let (true, Some(version)) = (has_versions, &version) {
+            let matches = |requirements: &[String]| {
+                requirements.iter().any(|requirement| {
+                    semver::VersionReq::parse(requirement)
+                        .is_ok_and(|requirement| requirement.matches(version))
+                })
+            };
+
+            return Some(!matches(&versions.patched) && !matches(&versions.unaffected));
+        }
+
+        let commit = package.commit.as_deref()?;
+        if self.commits.is_empty() {
+            return None;
+        }
+
+        let repo = Repository::open(source_dir).ok()?;
+        let commit = Oid::from_str(commit).ok()?;
+        // Commits missing from the clone (like a fix newer than what was fetched) aren't in the
+        // package's history
+        let contains = |ancestor: &str| {
+            let Ok(ancestor) = repo
+                .revparse_single(ancestor)
+                .and_then(|object| object.peel_to_commit())
+            else {
+                return false;
+            };
+
+            commit == ancestor.id()
+                || repo
+                    .graph_descendant_of(commit, ancestor.id())
+                    .unwrap_or(false)
+        };
+
+        for range in &self.commits {
+            let introduced = range.introduced.as_deref().is_none_or(contains);
+            let fixed = range.fixed.as_deref().is_some_and(contains);
+
+            if introduced && !fixed {
+                return Some(true);
+            }
+        }
+
+        Some(false)
+    }
+}
+
+/// Reads versions like tags, so `v1.2` and `release-1.2.0` are both read as `1.2.0`
+fn parse_version(version: &str) -> Option<semver::Version> {
+    let version = version.trim_start_matches(|c: char| !c.is_ascii_digit());
+    if let Ok(version) = semver::Version::parse(version) {
+        return Some(version);
+    }
+
+    let mut parts = version
+        .split('.')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+    parts.resize(3, 0);
+
+    Some(semver::Version::new(parts[0], parts[1], parts[2]))
+}
+
+/// Reduces a repository URL to `host/path`, reading `owner/repo` as a GitHub repository
+fn normalize_repository(url: &str) -> String {
+    let url = url.trim().to_lowercase();
+    let url = url.split_once("://").map(|(_, rest)| rest).unwrap_or(&url);
+    let url = url.trim_end_matches('/').trim_end_matches(".git");
+
+    match url.split_once('/') {
+        Some((owner, _)) if !owner.contains('.') && url.matches('/').count() == 1 => {
+            format!("github.com/{}", url)
+        }
+        _ => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn advisory(manifest: &str) -> Advisory {
+        toml::from_str(manifest).unwrap()
+    }
+
+    fn locked(source: &str, version: Option<&str>, commit: Option<&str>) -> LockedPackage {
+        LockedPackage {
+            name: "zlib".to_string(),
+            version: version.map(str::to_string),
+            source: source.to_string(),
+            registry: None,
+            revision: None,
+            commit: commit.map(str::to_string),
+            checksum: None,
+            signature: None,
+            dependencies: Vec::new(),
+        }
+    }
+
+    const ZLIB: &str = r#"
+        [advisory]
+        id = "C3PM-2022-0001"
+        package = "zlib"
+        title = "Heap buffer overflow in inflate"
+
+        [versions]
+        patched = [">=1.2.13"]
+        unaffected = ["<1.2.0"]
+    "#;
+
+    fn commit(repo: &Repository, message: &str) -> Oid {
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let signature = git2::Signature::now("c3pm", "c3pm@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn versions_in_a_patched_or_unaffected_range_are_not_affected() {
+        let advisory = advisory(ZLIB);
+        let source_dir = Path::new("missing");
+        let affects = |version: &str| {
+            advisory.affects(
+                &locked("archive+https://zlib.net/zlib.tar.gz", Some(version), None),
+                source_dir,
+            )
+        };
+
+        assert_eq!(affects("1.2.11"), Some(true));
+        assert_eq!(affects("v1.2.12"), Some(true));
+        assert_eq!(affects("1.2.13"), Some(false));
+        assert_eq!(affects("release-1.3"), Some(false));
+        assert_eq!(affects("1.1.4"), Some(false));
+    }
+
+    #[test]
+    fn advisories_without_ranges_affect_every_version() {
+        let advisory = advisory(
+            r#"
+            [advisory]
+            id = "C3PM-2024-0001"
+            package = "zlib"
+            title = "Everything is broken"
+            "#,
+        );
+
+        let package = locked("git+https://github.com/madler/zlib", None, None);
+        assert_eq!(advisory.affects(&package, Path::new("missing")), Some(true));
+    }
+
+    #[test]
+    fn unversioned_packages_are_checked_against_commit_ranges() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(repo_dir.path()).unwrap();
+        let before = commit(&repo, "before");
+        let introduced = commit(&repo, "introduced");
+        let vulnerable = commit(&repo, "vulnerable");
+        let fixed = commit(&repo, "fixed");
+        let after = commit(&repo, "after");
+
+        let advisory = advisory(&format!(
+            r#"
+            [advisory]
+            id = "C3PM-2022-0002"
+            package = "zlib"
+            title = "Use after free"
+
+            [[commits]]
+            introduced = "{}"
+            fixed = "{}"
+            "#,
+            introduced, fixed
+        ));
+        let affects = |commit: Oid| {
+            let package = locked(
+                "git+https://github.com/madler/zlib",
+                None,
+                Some(&commit.to_string()),
+            );
+            advisory.affects(&package, repo_dir.path())
+        };
+
+        assert_eq!(affects(before), Some(false));
+        assert_eq!(affects(introduced), Some(true));
+        assert_eq!(affects(vulnerable), Some(true));
+        assert_eq!(affects(fixed), Some(false));
+        assert_eq!(affects(after), Some(false));
+    }
+
+    #[test]
+    fn packages_that_cannot_be_compared_are_unchecked() {
+        let versioned = advisory(ZLIB);
+        let by_commit = advisory(
+            r#"
+            [advisory]
+            id = "C3PM-2022-0002"
+            package = "zlib"
+            title = "Use after free"
+
+            [[commits]]
+            fixed = "eff308af425b67093bab25f80f1ae950166bece1"
+            "#,
+        );
+        let missing = Path::new("missing");
+        let source = "git+https://github.com/madler/zlib";
+
+        // A branch isn't a version, and there is no commit to fall back to
+        let branch = locked(source, Some("develop"), None);
+        assert_eq!(versioned.affects(&branch, missing), None);
+
+        // A version range can't be checked against a commit
+        let pinned = locked(
+            source,
+            None,
+            Some("0123456789abcdef0123456789abcdef01234567"),
+        );
+        assert_eq!(versioned.affects(&pinned, missing), None);
+
+        // Nor can a commit range without the clone
+        assert_eq!(by_commit.affects(&pinned, missing), None);
+    }
+
+    #[test]
+    fn advisories_name_packages_by_repository_or_name() {
+        let advisory = advisory(
+            r#"
+            [advisory]
+            id = "C3PM-2022-0001"
+            package = "zlib"
+            repository = "https://github.com/madler/zlib"
+            title = "Heap buffer overflow in inflate"
+            "#,
+        );
+
+        for source in [
+            "git+https://github.com/madler/zlib",
+            "git+https://github.com/madler/zlib.git",
+            "git+HTTPS://GitHub.com/Madler/ZLib/",
+            "git+madler/zlib",
+        ] {
+            let mut package = locked(source, None, None);
+            package.name = "compression".to_string();
+            assert!(advisory.names(&package), "{}", source);
+        }
+
+        let fork = locked("git+https://github.com/zlib-ng/zlib-ng", None, None);
+        assert!(!advisory.names(&fork));
+
+        let archive = locked("archive+https://zlib.net/zlib-1.3.tar.gz", None, None);
+        assert!(advisory.names(&archive));
+
+        assert_eq!(
+            normalize_repository("madler/zlib"),
+            normalize_repository("https://github.com/madler/zlib.git")
+        );
+        assert_ne!(
+            normalize_repository("gitlab.com/madler/zlib"),
+            normalize_repository("madler/zlib")
+        );
+    }
+}
@@ -0,0 +1,503 @@
+use crate::archive::{create_archive, extract_archive};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use util::model::{BuildConfig, CacheConfig, OptionValue};
+
+/// Marks a cache entry whose install finished, so interrupted builds are never reused
+const COMPLETE_MARKER: &str = ".c3pm-complete";
+
+/// Everything that influences the binaries a dependency build produces
+#[derive(Debug, Clone)]
+pub struct CacheKey {
+    pub name: String,
+    pub commit: String,
+    /// The target operating system and architecture
+    pub os: String,
+    pub arch: String,
+    pub c_compiler: String,
+    pub compiler: String,
+    pub compiler_version: String,
+    pub standard: String,
+    pub build_type: BuildConfig,
+    pub options: BTreeMap<String, OptionValue>,
+    /// The digests of the cached dependencies the build could find through `CMAKE_PREFIX_PATH`
+    pub upstream: Vec<String>,
+}
+
+impl CacheKey {
+    pub fn digest(&self) -> String {
+        let mut canonical = format!(
+            "name={}\ncommit={}\nos={}\narch={}\nc-compiler={}\ncompiler={}\ncompiler-version={}\nstandard={}\nbuild-type={}\n",
+            self.name,
+            self.commit,
+            self.os,
+            self.arch,
+            self.c_compiler,
+            self.compiler,
+            self.compiler_version,
+            self.standard,
+            self.build_type
+        );
+
+        for (option, value) in &self.options {
+            canonical.push_str(&format!("option.{}={}\n", option, value));
+        }
+
+        for digest in &self.upstream {
+            canonical.push_str(&format!("upstream={}\n", digest));
+        }
+
+        hex::encode(Sha256::digest(canonical.as_bytes()))
+    }
+}
+
+/// A shared store of packed cache entries
+pub trait RemoteCache {
+    /// Downloads the entry for `digest` to `archive_path`, returning `false` if it doesn't exist
+    fn fetch(&self, digest: &str, archive_path: &Path) -> Result<bool, Box<dyn Error>>;
+
+    /// Uploads the packed entry at `archive_path` for `digest`
+    fn store(&self, digest: &str, archive_path: &Path) -> Result<(), Box<dyn Error>>;
+}
+
+/// A remote cache that is just a (possibly network mounted) directory
+pub struct DirectoryRemote {
+    pub root: PathBuf,
+}
+
+impl RemoteCache for DirectoryRemote {
+    fn fetch(&self, digest: &str, archive_path: &Path) -> Result<bool, Box<dyn Error>> {
+        let source = self.root.join(format!("{}.tar.gz", digest));
+
+        if !source.exists() {
+            return Ok(false);
+        }
+
+        fs::copy(source, archive_path)?;
+        Ok(true)
+    }
+
+    fn store(&self, digest: &str, archive_path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.root)?;
+
+        // Copy then rename so readers never see a partially written archive
+        let destination = self.root.join(format!("{}.tar.gz", digest));
+        let partial = self.root.join(format!("{}.tar.gz.partial", digest));
+        fs::copy(archive_path, &partial)?;
+        fs::rename(partial, destination)?;
+
+        Ok(())
+    }
+}
+
+/// A remote cache served over HTTP, using `GET`/`PUT` on `{base_url}/{digest}.tar.gz`
+pub struct HttpRemote {
+    pub base_url: String,
+    pub token: Option<String>,
+}
+
+impl HttpRemote {
+    fn url(&self, digest: &str) -> String {
+        format!("{}/{}.tar.gz", self.base_url.trim_end_matches('/'), digest)
+    }
+
+    fn authorize(&self, request: ureq::Request) -> ureq::Request {
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+}
+
+impl RemoteCache for HttpRemote {
+    fn fetch(&self, digest: &str, archive_path: &Path) -> Result<bool, Box<dyn Error>> {
+        let response = match self.authorize(ureq::get(&self.url(digest))).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(false),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let mut archive_file = File::create(archive_path)?;
+        io::copy(&mut response.into_reader(), &mut archive_file)?;
+
+        Ok(true)
+    }
+
+    fn store(&self, digest: &str, archive_path: &Path) -> Result<(), Box<dyn Error>> {
+        self.authorize(ureq::put(&self.url(digest)))
+            .set("Content-Type", "application/gzip")
+            .send(File::open(archive_path)?)?;
+
+        Ok(())
+    }
+}
+
+/// Picks the remote backend for a `[cache] remote` value
+pub fn remote_from_url(url: &str) -> Box<dyn RemoteCache> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Box::new(HttpRemote {
+            base_url: url.to_string(),
+            token: std::env::var("C3PM_CACHE_TOKEN").ok(),
+        })
+    } else {
+        Box::new(DirectoryRemote {
+            root: PathBuf::from(url.strip_prefix("file://").unwrap_or(url)),
+        })
+    }
+}
+
+pub struct BinaryCache {
+    root: PathBuf,
+    remote: Option<Box<dyn RemoteCache>>,
+    push: bool,
+}
+
+impl BinaryCache {
+    pub fn from_config(config: &CacheConfig) -> Self {
+        let root = match &config.dir {
+            Some(dir) => PathBuf::from(dir),
+            None => util::get_cache_dir(),
+        };
+
+        BinaryCache {
+            root: root.join("binary"),
+            remote: config.remote.as_deref().map(remote_from_url),
+            push: config.push,
+        }
+    }
+
+    /// Returns the install prefix for `key`, downloading it from the remote cache or running
+    /// `build` to install into a fresh prefix when it isn't cached locally yet
+    pub fn get_or_build(
+        &self,
+        key: &CacheKey,
+        build: impl FnOnce(&Path) -> Result<(), Box<dyn Error>>,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let digest = key.digest();
+        let prefix = self.root.join(&digest);
+
+        if prefix.join(COMPLETE_MARKER).exists() {
+            eprintln!("Using cached build of {} ({})", key.name, &digest[..12]);
+            return Ok(prefix);
+        }
+
+        fs::create_dir_all(&self.root)?;
+
+        let partial = self.root.join(format!("{}.partial", digest));
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+
+        let downloaded = match &self.remote {
+            Some(remote) => self.download(remote.as_ref(), &digest, &partial)?,
+            None => false,
+        };
+
+        if downloaded {
+            eprintln!(
+                "Downloaded cached build of {} ({})",
+                key.name,
+                &digest[..12]
+            );
+        } else {
+            eprintln!(
+                "Building {} into the binary cache ({})",
+                key.name,
+                &digest[..12]
+            );
+            build(&partial)?;
+        }
+
+        File::create(partial.join(COMPLETE_MARKER))?;
+
+        if prefix.exists() {
+            fs::remove_dir_all(&prefix)?;
+        }
+        fs::rename(&partial, &prefix)?;
+
+        let upload = match &self.remote {
+            Some(remote) if self.push && !downloaded => {
+                self.upload(remote.as_ref(), &digest, &prefix)
+            }
+            _ => Ok(()),
+        };
+
+        if let Err(e) = upload {
+            eprintln!("Failed to upload {} to the remote cache: {}", key.name, e);
+        }
+
+        Ok(prefix)
+    }
+
+    fn download(
+        &self,
+        remote: &dyn RemoteCache,
+        digest: &str,
+        destination: &Path,
+    ) -> Result<bool, Box<dyn Error>> {
+        let archive = tempfile::NamedTempFile::new_in(&self.root)?;
+
+        if !remote.fetch(digest, archive.path())? {
+            return Ok(false);
+        }
+
+        extract_archive(archive.path(), destination)?;
+        Ok(true)
+    }
+
+    fn upload(
+        &self,
+        remote: &dyn RemoteCache,
+        digest: &str,
+        prefix: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        let archive = tempfile::NamedTempFile::new_in(&self.root)?;
+        create_archive(prefix, archive.path())?;
+
+        remote.store(digest, archive.path())
+    }
+}
+
+/// Everything needed to configure a dependency build outside of the project's build tree
+pub struct DependencyBuild<'a> {
+    pub source_dir: &'a Path,
+    pub build_type: BuildConfig,
+    pub c_compiler: &'a str,
+    pub cxx_compiler: &'a str,
+    pub standard_variable: &'a str,
+    pub standard: &'a str,
+    pub options: &'a BTreeMap<String, OptionValue>,
+    /// Install prefixes of the cached builds of this dependency's own dependencies
+    pub prefix_path: &'a [PathBuf],
+}
+
+/// Configures, builds and installs a dependency into `prefix`
+pub fn build_dependency(build: &DependencyBuild, prefix: &Path) -> Result<(), Box<dyn Error>> {
+    let build_dir = tempfile::tempdir()?;
+    let build_type = build.build_type.to_string();
+    let prefix_path = build
+        .prefix_path
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<String>>()
+        .join(";");
+
+    let mut configure = Command::new("cmake");
+    configure
+        .arg("-S")
+        .arg(build.source_dir)
+        .arg("-B")
+        .arg(build_dir.path())
+        .arg(format!("-DCMAKE_BUILD_TYPE={}", build_type))
+        .arg(format!("-DCMAKE_INSTALL_PREFIX={}", prefix.display()))
+        .arg(format!("-DCMAKE_PREFIX_PATH={}", prefix_path))
+        .arg(format!("-D{}={}", build.standard_variable, build.standard));
+
+    if !build.c_compiler.is_empty() {
+        configure.arg(format!("-DCMAKE_C_COMPILER={}", build.c_compiler));
+    }
+
+    if !build.cxx_compiler.is_empty() {
+        configure.arg(format!("-DCMAKE_CXX_COMPILER={}", build.cxx_compiler));
+    }
+
+    for (option, value) in build.options {
+        configure.arg(format!("-D{}={}", option, value.cmake_value()));
+    }
+
+    run(&mut configure)?;
+    run(Command::new("cmake")
+        .arg("--build")
+        .arg(build_dir.path())
+        .args(["--config", &build_type, "--parallel"]))?;
+    run(Command::new("cmake")
+        .arg("--install")
+        .arg(build_dir.path())
+        .args(["--config", &build_type]))?;
+
+    Ok(())
+}
+
+fn run(command: &mut Command) -> Result<(), Box<dyn Error>> {
+    // Like the project's own build, keep stdout for what `cpppm run` and `cpppm test` run
+    let status = command.stdout(io::stderr()).status()?;
+
+    if !status.success() {
+        return Err(format!("{:?} failed with {}", command, status).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> CacheKey {
+        CacheKey {
+            name: "fmt".to_string(),
+            commit: "0123456789abcdef".to_string(),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            c_compiler: "gcc".to_string(),
+            compiler: "g++".to_string(),
+            compiler_version: "13.2.0".to_string(),
+            standard: "CPP17".to_string(),
+            build_type: BuildConfig::Release,
+            options: BTreeMap::new(),
+            upstream: Vec::new(),
+        }
+    }
+
+    fn cache(root: &Path, remote: Option<&Path>, push: bool) -> BinaryCache {
+        BinaryCache {
+            root: root.to_path_buf(),
+            remote: remote.map(|remote| {
+                Box::new(DirectoryRemote {
+                    root: remote.to_path_buf(),
+                }) as Box<dyn RemoteCache>
+            }),
+            push,
+        }
+    }
+
+    fn install(prefix: &Path) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(prefix.join("lib"))?;
+        fs::write(prefix.join("lib").join("libfmt.a"), "built")?;
+        Ok(())
+    }
+
+    #[test]
+    fn digest_is_stable() {
+        assert_eq!(key().digest(), key().digest());
+    }
+
+    #[test]
+    fn digest_covers_platform_compilers_and_upstream_builds() {
+        let base = key().digest();
+        let variants = [
+            CacheKey {
+                arch: "aarch64".to_string(),
+                ..key()
+            },
+            CacheKey {
+                os: "macos".to_string(),
+                ..key()
+            },
+            CacheKey {
+                c_compiler: "clang".to_string(),
+                ..key()
+            },
+            CacheKey {
+                upstream: vec!["abc".to_string()],
+                ..key()
+            },
+        ];
+
+        for variant in variants {
+            assert_ne!(variant.digest(), base, "{:?}", variant);
+        }
+    }
+
+    #[test]
+    fn get_or_build_reuses_complete_entries() {
+        let root = tempfile::tempdir().unwrap();
+        let prefix = root.path().join(key().digest());
+        fs::create_dir_all(&prefix).unwrap();
+        File::create(prefix.join(COMPLETE_MARKER)).unwrap();
+
+        let cached = cache(root.path(), None, false)
+            .get_or_build(&key(), |_| panic!("a complete entry was rebuilt"))
+            .unwrap();
+
+        assert_eq!(cached, prefix);
+    }
+
+    #[test]
+    fn get_or_build_rebuilds_interrupted_entries() {
+        let root = tempfile::tempdir().unwrap();
+        let digest = key().digest();
+        let prefix = root.path().join(&digest);
+        let partial = root.path().join(format!("{}.partial", digest));
+        fs::create_dir_all(&prefix).unwrap();
+        fs::write(prefix.join("stale"), "").unwrap();
+        fs::create_dir_all(&partial).unwrap();
+        fs::write(partial.join("half-installed"), "").unwrap();
+
+        let mut built = false;
+        let cached = cache(root.path(), None, false)
+            .get_or_build(&key(), |prefix| {
+                built = true;
+                install(prefix)
+            })
+            .unwrap();
+
+        assert!(built);
+        assert_eq!(cached, prefix);
+        assert!(prefix.join(COMPLETE_MARKER).exists());
+        assert!(prefix.join("lib").join("libfmt.a").exists());
+        assert!(!prefix.join("stale").exists());
+        assert!(!prefix.join("half-installed").exists());
+        assert!(!partial.exists());
+    }
+
+    #[test]
+    fn get_or_build_builds_and_pushes_entries_missing_from_the_remote() {
+        let root = tempfile::tempdir().unwrap();
+        let remote = tempfile::tempdir().unwrap();
+
+        cache(root.path(), Some(remote.path()), true)
+            .get_or_build(&key(), install)
+            .unwrap();
+
+        assert!(
+            remote
+                .path()
+                .join(format!("{}.tar.gz", key().digest()))
+                .exists()
+        );
+    }
+
+    #[test]
+    fn get_or_build_downloads_entries_from_the_remote() {
+        let builder_root = tempfile::tempdir().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let remote = tempfile::tempdir().unwrap();
+        cache(builder_root.path(), Some(remote.path()), true)
+            .get_or_build(&key(), install)
+            .unwrap();
+
+        let cached = cache(root.path(), Some(remote.path()), true)
+            .get_or_build(&key(), |_| panic!("a remote entry was rebuilt"))
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(cached.join("lib").join("libfmt.a")).unwrap(),
+            "built"
+        );
+        assert!(cached.join(COMPLETE_MARKER).exists());
+    }
+
+    #[test]
+    fn directory_remote_round_trips_archives() {
+        let work = tempfile::tempdir().unwrap();
+        let remote = DirectoryRemote {
+            root: work.path().join("remote"),
+        };
+        let uploaded = work.path().join("uploaded.tar.gz");
+        let downloaded = work.path().join("downloaded.tar.gz");
+        fs::write(&uploaded, "archive").unwrap();
+
+        assert!(!remote.fetch("abc", &downloaded).unwrap());
+
+        remote.store("abc", &uploaded).unwrap();
+        assert!(remote.fetch("abc", &downloaded).unwrap());
+        assert_eq!(fs::read_to_string(&downloaded).unwrap(), "archive");
+        assert!(!remote.root.join("abc.tar.gz.partial").exists());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::lockfile::Lockfile;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use util::context::ProjectContext;
+use util::generator::{FetchContentSource, get_exported_cmake};
+use util::model::FeatureSelection;
+
+pub use util::generator::EXPORTED_CMAKE_PATH;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportReport {
+    pub path: PathBuf,
+    pub dependencies: Vec<String>,
+    /// Whether the include of the exported file had to be added to `CMakeLists.txt`
+    pub patched_cmakelists: bool,
+    pub warnings: Vec<String>,
+}
+
+/// Writes the project's dependency wiring as plain FetchContent calls, pinned to the commits and
+/// checksums in `cpppm.lock`, and has `CMakeLists.txt` include it whenever c3pm doesn't provide
+/// its own file, so the project builds with just `cmake -S . -B build`
+///
+/// The dependencies are resolved for the project's platform and the selected features.
+/// Dev-dependencies are left out, since c3pm only adds them for tests and benchmarks.
+pub fn export_cmake(
+    context: &ProjectContext,
+    selection: &FeatureSelection,
+) -> Result<ExportReport, Box<dyn Error>> {
+    let project_root = context.root.as_path();
+    let platform = context.platform();
+    let (config, target) = context.config.for_platform(&platform)?;
+    let features = config.resolve_features(selection)?;
+    let lockfile = Lockfile::load(project_root)?;
+
+    let dependencies = util::collect_dependencies(project_root, &config, &features, &platform);
+    let mut sources = BTreeMap::new();
+    let mut warnings = Vec::new();
+
+    for dependency in &dependencies {
+        let data = &dependency.dependency;
+        if data.system {
+            continue;
+        }
+
+        let locked = lockfile
+            .find(&data.name)
+            .ok_or_else(|| format!("{} is not locked, run `cpppm fetch` first", data.name))?;
+
+        let source = match locked.source.split_once('+') {
+            Some(("git", repository)) => FetchContentSource::Git {
+                repository: repository.to_string(),
+                commit: locked
+                    .commit
+                    .clone()
+                    .ok_or_else(|| format!("{} has no locked commit", data.name))?,
+            },
+            Some(("archive", url)) => FetchContentSource::Archive {
+                url: url.to_string(),
+                checksum: locked.checksum.clone(),
+            },
+            Some(("path", path)) => {
+                if Path::new(path).is_absolute() || path.starts_with("..") {
+                    warnings.push(format!(
+                        "{} is a path dependency outside of the project, it only builds where {} exists",
+                        data.name, path
+                    ));
+                }
+
+                FetchContentSource::Directory(path.replace('\\', "/"))
+            }
+            _ => {
+                return Err(
+                    format!("{} has an unknown source {}", data.name, locked.source).into(),
+                );
+            }
+        };
+
+        sources.insert(data.name.clone(), source);
+    }
+
+    let path = project_root.join(EXPORTED_CMAKE_PATH);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(
+        &path,
+        get_exported_cmake(
+            &config,
+            &config.get_targets(project_root)?,
+            &target,
+            &features,
+            &dependencies,
+            &sources,
+        ),
+    )?;
+
+    Ok(ExportReport {
+        path,
+        dependencies: dependencies
+            .iter()
+            .map(|dependency| dependency.dependency.name.clone())
+            .collect(),
+        patched_cmakelists: include_exported_cmake(project_root)?,
+        warnings,
+    })
+}
+
+/// Adds the include of the exported file to `CMakeLists.txt` unless it is already there
+fn include_exported_cmake(project_root: &Path) -> Result<bool, Box<dyn Error>> {
+    let cmakelists_path = project_root.join("CMakeLists.txt");
+    let mut cmakelists = fs::read_to_string(&cmakelists_path)
+        .map_err(|e| format!("Failed to read {}: {}", cmakelists_path.display(), e))?;
+
+    if cmakelists.contains(EXPORTED_CMAKE_PATH) {
+        return Ok(false);
+    }
+
+    if !cmakelists.is_empty() && !cmakelists.ends_with('\n') {
+        cmakelists.push('\n');
+    }
+    cmakelists.push_str(&format!(
+        concat!(
+            "\n# Without c3pm, fetch the dependencies exported by `cpppm export-cmake`\n",
+            "if(NOT C3PM_CMAKE_FILE AND EXISTS ${{CMAKE_CURRENT_SOURCE_DIR}}/{path})\n",
+            "    include(${{CMAKE_CURRENT_SOURCE_DIR}}/{path})\n",
+            "endif()\n"
+        ),
+        path = EXPORTED_CMAKE_PATH
+    ));
+    fs::write(&cmakelists_path, cmakelists)?;
+
+    Ok(true)
+}
@@ -1,70 +1,594 @@
+use crate::archive::fetch_source_archive;
+use crate::cache::{BinaryCache, CacheKey, DependencyBuild, build_dependency};
+use crate::license::enforce_license_policy;
+use crate::lockfile::{LockedPackage, Lockfile};
+use crate::registry::{Registries, ResolvedPackage, resolve_packages};
+use crate::signing::verify_checksum;
 use git2::ErrorCode::Exists;
 use git2::Repository;
 use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error;
 use std::fs;
-use std::path::Path;
-use util::model::Dependency;
+use std::path::{Path, PathBuf};
+use util::cfg::Platform;
+use util::context::ProjectContext;
+use util::github::{LATEST_RELEASE, get_latest_release};
+use util::model::{
+    Dependency, DependencyData, FeatureSelection, GithubConfig, PackageRequirement, ProjectConfig,
+    ResolvedFeatures, SignaturePolicy, TargetConfig,
+};
+use util::select_compilers;
+
+pub mod archive;
+pub mod audit;
+pub mod cache;
+pub mod export;
+pub mod license;
+pub mod lockfile;
+pub mod package;
+pub mod publish;
+pub mod registry;
+pub mod sbom;
+pub mod server;
+pub mod signing;
+
+/// What the dependencies are being fetched for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workflow {
+    Build,
+    Test,
+    Bench,
+}
+
+/// A dependency about to be fetched
+struct ResolvedDependency {
+    dependency: Dependency,
+    /// The names of the packages it depends on according to its registry index
+    index_dependencies: Vec<String>,
+    /// The verified signature of a registry package
+    signature: Option<String>,
+}
+
+impl ResolvedDependency {
+    fn unindexed(dependency: Dependency) -> Self {
+        ResolvedDependency {
+            dependency,
+            index_dependencies: Vec::new(),
+            signature: None,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Depman {
-    #[serde(rename = "deps")]
+    #[serde(rename = "deps", default)]
     dependencies: Vec<Dependency>,
+    #[serde(default)]
+    packages: BTreeMap<String, PackageRequirement>,
+    #[serde(default)]
+    registries: BTreeMap<String, String>,
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    target: BTreeMap<String, TargetConfig>,
+    /// Always the policy of the project being built, dependencies can't loosen it
+    #[serde(skip)]
+    signature_policy: SignaturePolicy,
+    /// Like the signature policy, always taken from the project being built
+    #[serde(skip)]
+    github: GithubConfig,
 }
 
 impl Depman {
-    pub fn from_repo(repo_path: &Path) -> Result<Self, Box<dyn Error>> {
-        let toml_path = repo_path.join(".c3pm.toml");
+    /// Dev-dependencies are only included for test and bench workflows, and optional
+    /// dependencies only when one of the resolved features enables them. `config` is expected
+    /// to already have its `[target.'cfg(...)']` tables applied.
+    pub fn from_config(
+        config: &ProjectConfig,
+        workflow: Workflow,
+        features: &ResolvedFeatures,
+    ) -> Self {
+        let mut dependencies = config.dependencies.clone().unwrap_or_default();
+
+        if workflow != Workflow::Build {
+            dependencies.extend(config.dev_dependencies.clone().unwrap_or_default());
+        }
+
+        dependencies.retain(|dependency| features.is_dependency_enabled(dependency));
+
+        let mut packages = config.packages.clone();
+        packages.retain(|name, requirement| {
+            features.is_dependency_enabled(&requirement.to_dependency(name))
+        });
+
+        Depman {
+            dependencies,
+            packages,
+            registries: config.registries.clone(),
+            features: config.features.clone(),
+            target: BTreeMap::new(),
+            signature_policy: config.verify.clone().unwrap_or_default().signatures,
+            github: config.github.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Only reads regular dependencies, a dependency's dev-dependencies are never fetched
+    pub fn from_repo(repo_path: &Path, platform: &Platform) -> Result<Self, Box<dyn Error>> {
+        let toml_path = [".cpppm.toml", ".c3pm.toml"]
+            .iter()
+            .map(|file_name| repo_path.join(file_name))
+            .find(|path| path.exists());
+
+        let Some(toml_path) = toml_path else {
+            return Ok(Depman {
+                dependencies: Vec::new(),
+                packages: BTreeMap::new(),
+                registries: BTreeMap::new(),
+                features: BTreeMap::new(),
+                target: BTreeMap::new(),
+                signature_policy: SignaturePolicy::default(),
+                github: GithubConfig::default(),
+            });
+        };
+
         let toml_contents = fs::read_to_string(toml_path)?;
-        let depman: Depman = toml::from_str(&toml_contents)?;
+        let mut depman: Depman = toml::from_str(&toml_contents)?;
+
+        let target = ProjectConfig::merge_target_tables(&depman.target, platform)?;
+        depman
+            .dependencies
+            .extend(target.dependencies.unwrap_or_default());
+
+        // Paths are relative to the manifest they are declared in
+        for dependency in &mut depman.dependencies {
+            if let Some(path) = &dependency.dependency.path {
+                dependency.dependency.path = Some(repo_path.join(path).display().to_string());
+            }
+        }
+
+        for requirement in depman.packages.values_mut() {
+            if let PackageRequirement::Detailed(details) = requirement
+                && let Some(path) = &details.path
+            {
+                details.path = Some(repo_path.join(path).display().to_string());
+            }
+        }
+
+        // Dependencies are built with their default features
+        let package_dependencies = depman
+            .packages
+            .iter()
+            .map(|(name, requirement)| requirement.to_dependency(name))
+            .collect::<Vec<Dependency>>();
+        let dependencies = depman
+            .dependencies
+            .iter()
+            .chain(&package_dependencies)
+            .collect::<Vec<&Dependency>>();
+        let features = ProjectConfig::resolve_feature_table(
+            &depman.features,
+            &dependencies,
+            &FeatureSelection::default(),
+        )?;
+        depman
+            .dependencies
+            .retain(|dependency| features.is_dependency_enabled(dependency));
+        depman.packages.retain(|name, requirement| {
+            features.is_dependency_enabled(&requirement.to_dependency(name))
+        });
 
         Ok(depman)
     }
 
-    pub fn retrieve_dependencies(&self, base_path: &Path) -> Result<(), Box<dyn Error>> {
+    /// Resolves the registry packages (keeping the versions in `previous` where possible) and
+    /// returns them together with the git dependencies
+    fn resolve(&self, previous: &Lockfile) -> Result<Vec<ResolvedDependency>, Box<dyn Error>> {
+        let mut dependencies = self
+            .dependencies
+            .iter()
+            .cloned()
+            .map(ResolvedDependency::unindexed)
+            .collect::<Vec<ResolvedDependency>>();
+
+        // Path packages are used as they are, without going through a registry
+        let (path_packages, registry_packages): (BTreeMap<_, _>, BTreeMap<_, _>) = self
+            .packages
+            .clone()
+            .into_iter()
+            .partition(|(_, requirement)| requirement.path().is_some());
+
+        for (name, requirement) in &path_packages {
+            dependencies.push(ResolvedDependency::unindexed(
+                requirement.to_dependency(name),
+            ));
+        }
+
+        if registry_packages.is_empty() {
+            return Ok(dependencies);
+        }
+
+        let registries = Registries::open(&self.registries)?;
+
+        for package in resolve_packages(&registry_packages, &registries, previous)? {
+            dependencies.push(ResolvedDependency {
+                dependency: package.to_dependency(self.packages.get(&package.name)),
+                index_dependencies: package.entry.deps.keys().cloned().collect(),
+                signature: self.check_signature(&package)?,
+            });
+        }
+
+        Ok(dependencies)
+    }
+
+    /// Applies the signature policy to a registry package, returning its signature if it was
+    /// signed by one of the package's publishers
+    fn check_signature(&self, package: &ResolvedPackage) -> Result<Option<String>, String> {
+        if self.signature_policy == SignaturePolicy::Off {
+            return Ok(None);
+        }
+
+        let entry = &package.entry;
+        let verified = match (&entry.signature, &entry.checksum) {
+            (Some(signature), Some(checksum)) => {
+                verify_checksum(&package.publishers, checksum, signature)
+            }
+            _ => Err("it is not signed".to_string()),
+        };
+
+        match verified {
+            Ok(_) => Ok(entry.signature.clone()),
+            Err(e) => {
+                let message = format!(
+                    "Can't verify {} {} from {}: {}",
+                    package.name, package.version, package.registry, e
+                );
+
+                match self.signature_policy {
+                    SignaturePolicy::Require => Err(message),
+                    _ => {
+                        eprintln!("warning: {}", message);
+                        Ok(None)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fetches every dependency (and their dependencies) into `{base_path}/deps`, pinning them
+    /// to the commits in `previous` when their source hasn't changed and recording what was
+    /// fetched in `lockfile`
+    pub fn retrieve_dependencies(
+        &self,
+        base_path: &Path,
+        platform: &Platform,
+        previous: &Lockfile,
+        lockfile: &mut Lockfile,
+    ) -> Result<(), Box<dyn Error>> {
         let deps_dir = base_path.join("deps");
         fs::create_dir_all(&deps_dir)?;
 
-        for dependency in &self.dependencies {
-            let repo_url = format!(
-                "https://{}/{}/{}.git",
-                dependency
-                    .clone()
-                    .dependency
-                    .host
-                    .unwrap_or("github.com".to_string()),
-                dependency.clone().dependency.repository.0,
-                dependency.clone().dependency.repository.1
-            );
-            let dependency_name = &dependency.clone().dependency.name;
+        for resolved in self.resolve(previous)? {
+            let dependency = resolved.dependency;
+            let data = &dependency.dependency;
+            let dependency_name = &data.name;
             let dependency_path = deps_dir.join(dependency_name);
 
-            if let Err(e) = Repository::clone_recurse(repo_url.as_str(), &dependency_path) {
-                if e.code() == Exists {
-                    eprintln!("Repository {} already exists, skipping...", dependency_name);
-                    continue;
+            // Already fetched as a dependency of an earlier dependency, or left to the system
+            if lockfile.find(dependency_name).is_some() || data.system {
+                continue;
+            }
+
+            let source = data.source();
+            let locked = previous.find(dependency_name).filter(|locked| {
+                locked.source == source
+                    && locked.revision == data.revision
+                    && locked.version == data.version
+            });
+
+            let mut commit = None;
+            let mut checksum = None;
+
+            if let Some(path) = &data.path {
+                if !dependency_path.exists() {
+                    link_path_dependency(&base_path.join(path), &dependency_path)?;
+                }
+            } else if let Some(url) = &data.url {
+                let expected = data
+                    .checksum
+                    .as_deref()
+                    .or(locked.and_then(|locked| locked.checksum.as_deref()));
+
+                if dependency_path.exists() {
+                    eprintln!("Sources of {} already exist, skipping...", dependency_name);
+                    checksum = expected.map(str::to_string);
                 } else {
-                    return Err(Box::new(e));
+                    checksum = Some(fetch_source_archive(url, expected, &dependency_path)?);
                 }
+            } else {
+                let repo_url = data.git_url();
+
+                let dep_repo = match Repository::clone_recurse(repo_url.as_str(), &dependency_path)
+                {
+                    Ok(dep_repo) => dep_repo,
+                    Err(e) if e.code() == Exists => {
+                        eprintln!(
+                            "Repository {} already exists, reusing it...",
+                            dependency_name
+                        );
+                        Repository::open(&dependency_path)?
+                    }
+                    Err(e) => return Err(Box::new(e)),
+                };
+
+                let pinned = match locked.and_then(|locked| locked.commit.clone()) {
+                    Some(commit) => Some(commit),
+                    None => self.resolve_revision(data)?,
+                };
+
+                if let Some(revision) = pinned {
+                    check_out_revision(&dep_repo, &revision)?;
+                }
+
+                commit = Some(dep_repo.head()?.peel_to_commit()?.id().to_string());
             }
 
-            let dep_repo = Repository::open(&dependency_path)?;
-            let object = dep_repo.revparse_single(
-                dependency
-                    .clone()
-                    .dependency
-                    .revision
-                    .unwrap_or("".to_string())
-                    .as_str(),
-            );
-            dep_repo.checkout_tree(&object.unwrap(), None)?;
+            // Transitive dependencies live next to their dependents so CMake can add them once
+            let mut dep_deps = Depman::from_repo(&dependency_path, platform)?;
+            dep_deps.signature_policy = self.signature_policy;
+            dep_deps.github = self.github.clone();
 
-            let dep_deps = Depman::from_repo(&dependency_path)?;
-            dep_deps.retrieve_dependencies(&dependency_path)?
+            lockfile.insert(LockedPackage {
+                name: dependency_name.clone(),
+                version: data.version.clone(),
+                source,
+                registry: data.registry.clone(),
+                revision: data.revision.clone(),
+                commit,
+                checksum,
+                signature: resolved.signature,
+                dependencies: dep_deps
+                    .dependencies
+                    .iter()
+                    .map(|dependency| dependency.dependency.name.clone())
+                    .chain(dep_deps.packages.keys().cloned())
+                    .chain(resolved.index_dependencies)
+                    .collect::<BTreeSet<String>>()
+                    .into_iter()
+                    .collect(),
+            });
+
+            dep_deps.retrieve_dependencies(base_path, platform, previous, lockfile)?
         }
 
         Ok(())
     }
+
+    /// The revision to check out when nothing is locked yet, resolving `latest-release` to the
+    /// tag of the repository's newest GitHub release
+    fn resolve_revision(&self, data: &DependencyData) -> Result<Option<String>, String> {
+        if data.revision.as_deref() != Some(LATEST_RELEASE) {
+            return Ok(data.revision.clone());
+        }
+
+        let (owner, repo) = &data.repository;
+        if data.git.is_some() || owner.is_empty() || repo.is_empty() {
+            return Err(format!(
+                "`revision = \"{}\"` of {} needs a GitHub `repository`",
+                LATEST_RELEASE, data.name
+            ));
+        }
+
+        let api_url = util::github::api_url(&self.github, data.host.as_deref());
+        let tag = get_latest_release(&api_url, owner, repo, self.github.prereleases)?;
+        eprintln!("Resolved the latest release of {} to {}", data.name, tag);
+
+        Ok(Some(tag))
+    }
+}
+
+/// Checks `revision` out in `repo`, fetching `origin`'s branches and tags first when a clone from
+/// an earlier fetch doesn't have it yet
+fn check_out_revision(repo: &Repository, revision: &str) -> Result<(), git2::Error> {
+    let object = match repo.revparse_single(revision) {
+        Ok(object) => object,
+        Err(_) => {
+            repo.find_remote("origin")?.fetch(
+                &[
+                    "+refs/heads/*:refs/remotes/origin/*",
+                    "+refs/tags/*:refs/tags/*",
+                ],
+                None,
+                None,
+            )?;
+            repo.revparse_single(revision)?
+        }
+    };
+
+    repo.checkout_tree(&object, None)?;
+    repo.set_head_detached(object.peel_to_commit()?.id())
+}
+
+/// Path dependencies are linked into `deps/` so they are added like any other dependency while
+/// edits to them are picked up right away
+fn link_path_dependency(source_dir: &Path, dependency_path: &Path) -> Result<(), Box<dyn Error>> {
+    let source_dir = source_dir
+        .canonicalize()
+        .map_err(|e| format!("Path dependency {} not found: {}", source_dir.display(), e))?;
+
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&source_dir, dependency_path)?;
+    #[cfg(windows)]
+    std::os::windows::fs::symlink_dir(&source_dir, dependency_path)?;
+
+    Ok(())
+}
+
+/// Fetches the dependencies the project declares for its platform and updates `cpppm.lock`
+pub fn fetch_project_dependencies(
+    context: &ProjectContext,
+    workflow: Workflow,
+    selection: &FeatureSelection,
+) -> Result<(), Box<dyn Error>> {
+    let project_root = context.root.as_path();
+    let platform = context.platform();
+    let (config, _) = context.config.for_platform(&platform)?;
+    let features = config.resolve_features(selection)?;
+
+    let previous = Lockfile::load(project_root)?;
+    let mut lockfile = Lockfile::default();
+
+    Depman::from_config(&config, workflow, &features).retrieve_dependencies(
+        project_root,
+        &platform,
+        &previous,
+        &mut lockfile,
+    )?;
+
+    // Checked before the pins below are added, since only what was fetched can be checked, and
+    // reported after saving the lockfile, so `cpppm licenses` can show the violations
+    let license_check = match &config.licenses {
+        Some(policy) => enforce_license_policy(project_root, policy, &lockfile),
+        None => Ok(()),
+    };
+
+    // Keep the pins of dev-dependencies that weren't fetched this time
+    for dev_dependency in config.dev_dependencies.iter().flatten() {
+        let name = &dev_dependency.dependency.name;
+
+        if let (None, Some(locked)) = (lockfile.find(name), previous.find(name)) {
+            lockfile.insert(locked.clone());
+        }
+    }
+
+    lockfile.save(project_root)?;
+
+    Ok(license_check?)
+}
+
+/// Builds every cacheable dependency of the project into the binary cache (or reuses a cached
+/// build) and records the install prefixes in `{build_dir}/c3pm/prebuilt.toml`, which the
+/// generated CMake uses to `find_package` them instead of adding their sources
+pub fn prebuild_dependencies(
+    context: &ProjectContext,
+    selection: &FeatureSelection,
+) -> Result<(), Box<dyn Error>> {
+    let project_root = context.root.as_path();
+    let platform = context.platform();
+    let (config, _) = context.config.for_platform(&platform)?;
+    let prebuilt_path = context.build_dir.join("c3pm").join("prebuilt.toml");
+
+    let cache_config = match &config.cache {
+        Some(cache_config) if cache_config.enabled => cache_config,
+        _ => {
+            if prebuilt_path.exists() {
+                fs::remove_file(&prebuilt_path)?;
+            }
+
+            return Ok(());
+        }
+    };
+
+    let features = config.resolve_features(selection)?;
+    let lockfile = Lockfile::load(project_root)?;
+    let cache = BinaryCache::from_config(cache_config);
+    let (c_compiler, cxx_compiler) = (context.c_compiler.clone(), context.cxx_compiler.clone());
+    let compiler_version = util::get_compiler_version(&cxx_compiler)?;
+    let language = config.project_details.language;
+    let standard = language.get_lang_and_standard().1;
+    let standard_variable = match language.is_c() {
+        true => "CMAKE_C_STANDARD",
+        false => "CMAKE_CXX_STANDARD",
+    };
+
+    let mut prefixes: BTreeMap<String, String> = BTreeMap::new();
+    let mut builds: BTreeMap<String, (String, PathBuf)> = BTreeMap::new();
+
+    for dependency in util::collect_dependencies(project_root, &config, &features, &platform) {
+        let data = &dependency.dependency;
+
+        // Path dependencies are being worked on, so caching their builds would go stale
+        if data.cache == Some(false) || data.path.is_some() || data.system {
+            continue;
+        }
+
+        let source_dir = project_root.join("deps").join(&data.name);
+        // Archive sources have no commit, their checksum identifies them instead
+        let commit = match Repository::open(&source_dir) {
+            Ok(repo) => repo.head()?.peel_to_commit()?.id().to_string(),
+            Err(_) => lockfile
+                .find(&data.name)
+                .and_then(|locked| locked.checksum.clone())
+                .ok_or_else(|| format!("{} is not locked, run `cpppm fetch`", data.name))?,
+        };
+        let build_type = data.build_type.unwrap_or(context.profile.build_type);
+        let (upstream, prefix_path) = upstream_builds(&lockfile, &data.name, &builds);
+
+        let key = CacheKey {
+            name: data.name.clone(),
+            commit,
+            os: platform.os.clone(),
+            arch: platform.arch.clone(),
+            c_compiler: c_compiler.clone(),
+            compiler: cxx_compiler.clone(),
+            compiler_version: compiler_version.clone(),
+            standard: language.to_string(),
+            build_type,
+            options: data.options.clone(),
+            upstream,
+        };
+        let build = DependencyBuild {
+            source_dir: &source_dir,
+            build_type,
+            c_compiler: &c_compiler,
+            cxx_compiler: &cxx_compiler,
+            standard_variable,
+            standard: &standard,
+            options: &data.options,
+            prefix_path: &prefix_path,
+        };
+
+        let prefix = cache.get_or_build(&key, |prefix| build_dependency(&build, prefix))?;
+
+        prefixes.insert(data.name.clone(), prefix.display().to_string());
+        builds.insert(data.name.clone(), (key.digest(), prefix));
+    }
+
+    if let Some(parent) = prebuilt_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&prebuilt_path, toml::to_string(&prefixes)?)?;
+
+    Ok(())
+}
+
+/// The digests and install prefixes of the cached builds among `name`'s transitive dependencies
+/// in the lockfile, so a build only depends on the packages it can actually find
+fn upstream_builds(
+    lockfile: &Lockfile,
+    name: &str,
+    builds: &BTreeMap<String, (String, PathBuf)>,
+) -> (Vec<String>, Vec<PathBuf>) {
+    let mut transitive: BTreeSet<String> = BTreeSet::new();
+    let mut pending = vec![name.to_string()];
+
+    while let Some(package) = pending.pop() {
+        for dependency in lockfile
+            .find(&package)
+            .into_iter()
+            .flat_map(|p| &p.dependencies)
+        {
+            if dependency != name && transitive.insert(dependency.clone()) {
+                pending.push(dependency.clone());
+            }
+        }
+    }
+
+    transitive
+        .iter()
+        .filter_map(|dependency| builds.get(dependency))
+        .map(|(digest, prefix)| (digest.clone(), prefix.clone()))
+        .unzip()
 }
 
 pub fn fetch_repository(url: &str, base_path: &Path) -> Result<Depman, Box<dyn Error>> {
@@ -77,8 +601,181 @@ pub fn fetch_repository(url: &str, base_path: &Path) -> Result<Depman, Box<dyn E
 
     Repository::clone_recurse(url, &repo_path)?;
 
-    let depman = Depman::from_repo(&repo_path)?;
-    depman.retrieve_dependencies(&repo_path)?;
+    let platform = Platform::detect(&select_compilers().1);
+    let depman = Depman::from_repo(&repo_path, &platform)?;
+    let previous = Lockfile::load(base_path)?;
+    let mut lockfile = Lockfile::default();
+    depman.retrieve_dependencies(base_path, &platform, &previous, &mut lockfile)?;
+
+    for locked in &previous.package {
+        if lockfile.find(&locked.name).is_none() {
+            lockfile.insert(locked.clone());
+        }
+    }
+    lockfile.save(base_path)?;
 
     Ok(depman)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit_file(repo: &Repository, content: &str) -> git2::Oid {
+        let root = repo.workdir().unwrap();
+        fs::write(root.join("README.md"), content).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("README.md")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("c3pm", "c3pm@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            content,
+            &tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    fn locked(name: &str, dependencies: &[&str]) -> LockedPackage {
+        LockedPackage {
+            name: name.to_string(),
+            version: None,
+            source: format!("git+https://github.com/example/{}", name),
+            registry: None,
+            revision: None,
+            commit: None,
+            checksum: None,
+            signature: None,
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    fn key(name: &str, upstream: Vec<String>) -> CacheKey {
+        CacheKey {
+            name: name.to_string(),
+            commit: format!("{}-commit", name),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            c_compiler: "gcc".to_string(),
+            compiler: "g++".to_string(),
+            compiler_version: "13.2.0".to_string(),
+            standard: "CPP17".to_string(),
+            build_type: util::model::BuildConfig::Release,
+            options: BTreeMap::new(),
+            upstream,
+        }
+    }
+
+    /// Computes the digests of `order` like `prebuild_dependencies`, returning the one of `name`
+    /// and the prefix path its build would get
+    fn prebuilt_digest(lockfile: &Lockfile, order: &[&str], name: &str) -> (String, Vec<PathBuf>) {
+        let mut builds: BTreeMap<String, (String, PathBuf)> = BTreeMap::new();
+
+        for package in order {
+            let (upstream, prefix_path) = upstream_builds(lockfile, package, &builds);
+            let digest = key(package, upstream).digest();
+
+            if *package == name {
+                return (digest, prefix_path);
+            }
+
+            builds.insert(package.to_string(), (digest.clone(), PathBuf::from(digest)));
+        }
+
+        panic!("{} was not built", name);
+    }
+
+    #[test]
+    fn cached_builds_only_depend_on_their_own_dependencies() {
+        let first = Lockfile {
+            version: 1,
+            package: vec![
+                locked("zlib", &[]),
+                locked("fmt", &[]),
+                locked("spdlog", &["fmt"]),
+            ],
+        };
+        let second = Lockfile {
+            version: 1,
+            package: vec![
+                locked("catch2", &[]),
+                locked("fmt", &[]),
+                locked("json", &[]),
+                locked("spdlog", &["fmt"]),
+            ],
+        };
+
+        let (first_fmt, first_fmt_path) = prebuilt_digest(&first, &["zlib", "fmt"], "fmt");
+        let (second_fmt, _) = prebuilt_digest(&second, &["catch2", "fmt"], "fmt");
+        assert_eq!(first_fmt, second_fmt);
+        assert!(first_fmt_path.is_empty());
+
+        let (first_spdlog, first_path) =
+            prebuilt_digest(&first, &["zlib", "fmt", "spdlog"], "spdlog");
+        let (second_spdlog, second_path) =
+            prebuilt_digest(&second, &["catch2", "fmt", "json", "spdlog"], "spdlog");
+        assert_eq!(first_spdlog, second_spdlog);
+        assert_eq!(first_path, [PathBuf::from(&first_fmt)]);
+        assert_eq!(second_path, first_path);
+        assert_ne!(first_spdlog, key("spdlog", Vec::new()).digest());
+    }
+
+    #[test]
+    fn upstream_builds_follow_transitive_edges() {
+        let lockfile = Lockfile {
+            version: 1,
+            package: vec![
+                locked("app-core", &["spdlog"]),
+                locked("fmt", &[]),
+                locked("spdlog", &["fmt"]),
+                locked("zlib", &[]),
+            ],
+        };
+        let builds = ["fmt", "spdlog", "zlib"]
+            .iter()
+            .map(|name| {
+                (
+                    name.to_string(),
+                    (format!("{}-digest", name), PathBuf::from(name)),
+                )
+            })
+            .collect();
+
+        let (upstream, prefix_path) = upstream_builds(&lockfile, "app-core", &builds);
+        assert_eq!(upstream, ["fmt-digest", "spdlog-digest"]);
+        assert_eq!(prefix_path, [PathBuf::from("fmt"), PathBuf::from("spdlog")]);
+    }
+
+    #[test]
+    fn check_out_revision_fetches_commits_missing_from_an_earlier_clone() {
+        let origin_dir = tempfile::tempdir().unwrap();
+        let clone_dir = tempfile::tempdir().unwrap();
+        let origin = Repository::init(origin_dir.path()).unwrap();
+        let first = commit_file(&origin, "first");
+
+        let clone = Repository::clone(
+            origin_dir.path().to_str().unwrap(),
+            clone_dir.path().join("dep"),
+        )
+        .unwrap();
+        let second = commit_file(&origin, "second");
+        origin
+            .tag_lightweight("v2", &origin.find_object(second, None).unwrap(), false)
+            .unwrap();
+
+        check_out_revision(&clone, &second.to_string()).unwrap();
+        assert_eq!(clone.head().unwrap().peel_to_commit().unwrap().id(), second);
+
+        check_out_revision(&clone, &first.to_string()).unwrap();
+        assert_eq!(clone.head().unwrap().peel_to_commit().unwrap().id(), first);
+
+        check_out_revision(&clone, "v2").unwrap();
+        assert_eq!(clone.head().unwrap().peel_to_commit().unwrap().id(), second);
+    }
+}
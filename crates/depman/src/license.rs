@@ -0,0 +1,662 @@
+use crate::lockfile::Lockfile;
+use globset::GlobBuilder;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use util::context::ProjectContext;
+use util::model::{FeatureSelection, LicensePolicy, UnknownLicensePolicy};
+use walkdir::WalkDir;
+
+/// Phrases identifying common license texts, checked in order since some texts quote others. The
+/// GPLs are told apart by the phrases only their own text has, as they all mention each other
+/// (the GPLv3 refers to the AGPL and the LGPL, and the LGPLv3 incorporates the GPLv3).
+const LICENSE_TEXTS: &[(&str, &[&str])] = &[
+    (
+        "AGPL-3.0-or-later",
+        &[
+            "gnu affero general public license",
+            "remote network interaction",
+        ],
+    ),
+    (
+        "LGPL-3.0-or-later",
+        &["incorporates the terms and conditions of version 3 of the gnu general public license"],
+    ),
+    (
+        "LGPL-2.1-or-later",
+        &["gnu lesser general public license version 2.1, february 1999"],
+    ),
+    (
+        "GPL-3.0-or-later",
+        &["gnu general public license version 3, 29 june 2007"],
+    ),
+    (
+        "GPL-2.0-or-later",
+        &["gnu general public license version 2, june 1991"],
+    ),
+    ("MPL-2.0", &["mozilla public license", "2.0"]),
+    ("Apache-2.0", &["apache license", "version 2.0"]),
+    ("BSL-1.0", &["boost software license - version 1.0"]),
+    (
+        "MIT",
+        &[
+            "permission is hereby granted, free of charge",
+            "the above copyright notice and this permission notice shall be included",
+        ],
+    ),
+    (
+        "ISC",
+        &["permission to use, copy, modify, and/or distribute this software for any purpose"],
+    ),
+    (
+        "BSD-3-Clause",
+        &[
+            "redistribution and use in source and binary forms",
+            "neither the name of",
+        ],
+    ),
+    (
+        "BSD-2-Clause",
+        &["redistribution and use in source and binary forms"],
+    ),
+    (
+        "Zlib",
+        &[
+            "this software is provided 'as-is'",
+            "altered source versions must be plainly marked as such",
+        ],
+    ),
+    (
+        "Unlicense",
+        &["this is free and unencumbered software released into the public domain"],
+    ),
+    ("CC0-1.0", &["cc0 1.0 universal"]),
+];
+
+/// The files whose text identifies a license, matched against the start of lowercase file names
+const LICENSE_FILE_PREFIXES: &[&str] = &["license", "licence", "copying", "unlicense"];
+
+/// Files that have to be shipped along with the license, but don't identify it
+const NOTICE_FILE_PREFIXES: &[&str] = &["notice", "copyright"];
+
+/// The source files scanned for `SPDX-License-Identifier` headers
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "c", "cc", "cpp", "cxx", "c++", "h", "hh", "hpp", "hxx", "h++", "inl", "ipp", "tpp",
+];
+
+/// How many lines at the top of a source file are searched for an SPDX header
+const HEADER_LINES: usize = 30;
+
+/// What is known about a dependency's license
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DetectedLicense {
+    /// An SPDX license expression, if any license could be detected
+    pub license: Option<String>,
+    /// The license and notice files at the top of the sources, relative to them
+    pub files: Vec<PathBuf>,
+}
+
+/// A dependency's license, as listed by `cpppm licenses`
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyLicense {
+    pub name: String,
+    pub version: Option<String>,
+    pub source: Option<String>,
+    /// Provided by the system, so its license can't be detected
+    pub system: bool,
+    #[serde(flatten)]
+    pub detected: DetectedLicense,
+    /// Why the project's `[licenses]` policy rejects the dependency
+    pub violation: Option<String>,
+}
+
+/// The result of checking a license against a [`LicensePolicy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LicenseCheck {
+    Allowed,
+    Unknown,
+    Denied(String),
+}
+
+/// Detects the license of the sources in `source_dir` from the license its c3pm manifest
+/// declares, its license files (`LICENSE`, `COPYING`, ...) and the `SPDX-License-Identifier`
+/// headers of its source files. Everything found is combined with `AND`, so a file under another
+/// license vendored into the sources shows up too.
+pub fn detect_license(source_dir: &Path) -> DetectedLicense {
+    let mut expressions = BTreeSet::new();
+
+    if let Ok(config) = util::load_project_config(&source_dir.join(".cpppm.toml"))
+        && let Some(license) = config.project_details.license
+    {
+        expressions.insert(license);
+    }
+
+    let files = find_license_files(source_dir);
+    for file in &files {
+        if !has_prefix(file, LICENSE_FILE_PREFIXES) {
+            continue;
+        }
+
+        if let Some(license) = fs::read_to_string(source_dir.join(file))
+            .ok()
+            .and_then(|text| identify_license_text(&text))
+        {
+            expressions.insert(license.to_string());
+        }
+    }
+
+    expressions.extend(find_spdx_headers(source_dir));
+
+    let license = match expressions.len() {
+        0 => None,
+        1 => expressions.into_iter().next(),
+        _ => Some(
+            expressions
+                .iter()
+                .map(|expression| match expression.contains(' ') {
+                    true => format!("({})", expression),
+                    false => expression.clone(),
+                })
+                .collect::<Vec<String>>()
+                .join(" AND "),
+        ),
+    };
+
+    DetectedLicense { license, files }
+}
+
+/// Lists the licenses of the project's fetched dependencies (and system dependencies), in the
+/// order they are added to the build, including dev-dependencies when `dev` is set
+pub fn collect_licenses(
+    context: &ProjectContext,
+    selection: &FeatureSelection,
+    dev: bool,
+) -> Result<Vec<DependencyLicense>, Box<dyn Error>> {
+    let project_root = context.root.as_path();
+    let platform = context.platform();
+    let (config, _) = context.config.for_platform(&platform)?;
+    let features = config.resolve_features(selection)?;
+    let lockfile = Lockfile::load(project_root)?;
+    let policy = config.licenses.clone().unwrap_or_default();
+
+    let mut dependencies = util::collect_dependencies(project_root, &config, &features, &platform);
+    if dev {
+        dependencies.extend(util::collect_dev_dependencies(
+            project_root,
+            &config,
+            &features,
+            &platform,
+        ));
+    }
+
+    let mut licenses = Vec::new();
+
+    for dependency in dependencies {
+        let data = dependency.dependency;
+
+        if data.system {
+            licenses.push(DependencyLicense {
+                name: data.name,
+                version: data.version,
+                source: None,
+                system: true,
+                detected: DetectedLicense::default(),
+                violation: None,
+            });
+            continue;
+        }
+
+        let locked = lockfile
+            .find(&data.name)
+            .ok_or_else(|| format!("{} is not locked, run `cpppm fetch` first", data.name))?;
+        let detected = detect_license(&project_root.join("deps").join(&data.name));
+        let violation = match policy.ignore.contains(&data.name) {
+            true => None,
+            false => match check_license(&policy, detected.license.as_deref()) {
+                LicenseCheck::Denied(reason) => Some(reason),
+                LicenseCheck::Unknown if policy.unknown == UnknownLicensePolicy::Deny => {
+                    Some("no license detected".to_string())
+                }
+                _ => None,
+            },
+        };
+
+        licenses.push(DependencyLicense {
+            name: data.name,
+            version: locked.version_or_revision(),
+            source: Some(locked.source.clone()),
+            system: false,
+            detected,
+            violation,
+        });
+    }
+
+    Ok(licenses)
+}
+
+/// Checks the licenses of the packages in `lockfile` (fetched into `{project_root}/deps`)
+/// against `policy`, failing with every violation at once
+pub fn enforce_license_policy(
+    project_root: &Path,
+    policy: &LicensePolicy,
+    lockfile: &Lockfile,
+) -> Result<(), String> {
+    let mut violations = Vec::new();
+
+    for package in &lockfile.package {
+        if policy.ignore.contains(&package.name) {
+            continue;
+        }
+
+        let detected = detect_license(&project_root.join("deps").join(&package.name));
+
+        match check_license(policy, detected.license.as_deref()) {
+            LicenseCheck::Allowed => {}
+            LicenseCheck::Unknown => match policy.unknown {
+                UnknownLicensePolicy::Allow => {}
+                UnknownLicensePolicy::Warn => {
+                    eprintln!("warning: no license detected for {}", package.name)
+                }
+                UnknownLicensePolicy::Deny => {
+                    violations.push(format!("{}: no license detected", package.name))
+                }
+            },
+            LicenseCheck::Denied(reason) => {
+                violations.push(format!("{}: {}", package.name, reason))
+            }
+        }
+    }
+
+    match violations.is_empty() {
+        true => Ok(()),
+        false => Err(format!(
+            "Dependencies violate the `[licenses]` policy:\n  {}\n(add them to `ignore` once their licenses are reviewed)",
+            violations.join("\n  ")
+        )),
+    }
+}
+
+/// Checks an SPDX expression against `policy`: every license joined with `AND` has to be
+/// acceptable, but only one of the alternatives joined with `OR`
+pub fn check_license(policy: &LicensePolicy, license: Option<&str>) -> LicenseCheck {
+    let Some(license) = license else {
+        return LicenseCheck::Unknown;
+    };
+
+    let mut rejected = BTreeSet::new();
+    let tokens = tokenize_expression(license);
+    let mut position = 0;
+
+    let allowed = evaluate_or(&tokens, &mut position, &mut |id| {
+        let permitted = !matches_any(&policy.deny, id)
+            && (policy.allow.is_empty() || matches_any(&policy.allow, id));
+
+        if !permitted {
+            rejected.insert(id.to_string());
+        }
+
+        permitted
+    });
+
+    if position != tokens.len() || (!allowed && rejected.is_empty()) {
+        return LicenseCheck::Denied(format!("invalid license expression `{}`", license));
+    }
+
+    match allowed {
+        true => LicenseCheck::Allowed,
+        false => {
+            let verb = if rejected.len() == 1 { "is" } else { "are" };
+            let rejected = rejected.into_iter().collect::<Vec<String>>().join(", ");
+
+            LicenseCheck::Denied(match license.contains(' ') {
+                true => format!("{} {} not allowed (in `{}`)", rejected, verb, license),
+                false => format!("{} {} not allowed", rejected, verb),
+            })
+        }
+    }
+}
+
+/// Writes the license and notice files of every fetched dependency into one notices file, to
+/// ship along with a build
+pub fn write_notices(
+    project_root: &Path,
+    project_name: &str,
+    licenses: &[DependencyLicense],
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut notices = format!(
+        "THIRD-PARTY SOFTWARE NOTICES\n\n{} includes the following third-party software.\n",
+        project_name
+    );
+
+    for dependency in licenses.iter().filter(|dependency| !dependency.system) {
+        notices.push_str(&format!("\n{}\n\n{}", "=".repeat(80), dependency.name));
+        if let Some(version) = &dependency.version {
+            notices.push_str(&format!(" {}", version));
+        }
+        notices.push_str(&format!(
+            "\nLicense: {}\n",
+            dependency.detected.license.as_deref().unwrap_or("unknown")
+        ));
+        if let Some(source) = &dependency.source {
+            notices.push_str(&format!("Source: {}\n", source));
+        }
+
+        let source_dir = project_root.join("deps").join(&dependency.name);
+        for file in &dependency.detected.files {
+            let text = fs::read_to_string(source_dir.join(file))?;
+            notices.push_str(&format!(
+                "\n--- {} ---\n\n{}\n",
+                file.display(),
+                text.trim_end()
+            ));
+        }
+    }
+
+    fs::write(path, notices)?;
+
+    Ok(())
+}
+
+fn find_license_files(source_dir: &Path) -> Vec<PathBuf> {
+    let mut files = fs::read_dir(source_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| PathBuf::from(entry.file_name()))
+        .filter(|file| {
+            has_prefix(file, LICENSE_FILE_PREFIXES) || has_prefix(file, NOTICE_FILE_PREFIXES)
+        })
+        .collect::<Vec<PathBuf>>();
+
+    files.sort();
+    files
+}
+
+fn has_prefix(file: &Path, prefixes: &[&str]) -> bool {
+    let name = file.to_string_lossy().to_lowercase();
+    prefixes.iter().any(|prefix| name.starts_with(prefix))
+}
+
+fn identify_license_text(text: &str) -> Option<&'static str> {
+    let normalized = text
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase();
+
+    LICENSE_TEXTS
+        .iter()
+        .find(|(_, phrases)| phrases.iter().all(|phrase| normalized.contains(phrase)))
+        .map(|(id, _)| *id)
+}
+
+/// Collects the expressions of the `SPDX-License-Identifier` headers in the source files,
+/// skipping fetched dependencies and git metadata
+fn find_spdx_headers(source_dir: &Path) -> BTreeSet<String> {
+    let entries = WalkDir::new(source_dir)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != ".git" && entry.file_name() != "deps")
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .is_some_and(|extension| SOURCE_EXTENSIONS.contains(&&*extension.to_string_lossy()))
+        });
+
+    let mut expressions = BTreeSet::new();
+
+    for entry in entries {
+        let Ok(file) = fs::File::open(entry.path()) else {
+            continue;
+        };
+
+        for line in BufReader::new(file).lines().take(HEADER_LINES) {
+            let Ok(line) = line else {
+                break;
+            };
+
+            if let Some((_, expression)) = line.split_once("SPDX-License-Identifier:") {
+                let expression = expression
+                    .trim()
+                    .trim_end_matches("*/")
+                    .trim_end_matches("-->")
+                    .trim();
+
+                if !expression.is_empty() {
+                    expressions.insert(expression.to_string());
+                }
+                break;
+            }
+        }
+    }
+
+    expressions
+}
+
+/// Matches a license id against ids and globs, where `GPL-3.0` also matches `GPL-3.0-only` and
+/// `GPL-3.0-or-later`
+fn matches_any(patterns: &[String], id: &str) -> bool {
+    let base_id = id
+        .strip_suffix("-only")
+        .or_else(|| id.strip_suffix("-or-later"))
+        .unwrap_or(id);
+
+    patterns.iter().any(|pattern| {
+        GlobBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map(|glob| {
+                let matcher = glob.compile_matcher();
+                matcher.is_match(id) || matcher.is_match(base_id)
+            })
+            .unwrap_or_else(|_| pattern.eq_ignore_ascii_case(id))
+    })
+}
+
+fn tokenize_expression(expression: &str) -> Vec<String> {
+    expression
+        .replace('(', " ( ")
+        .replace(')', " ) ")
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+fn evaluate_or(
+    tokens: &[String],
+    position: &mut usize,
+    permitted: &mut dyn FnMut(&str) -> bool,
+) -> bool {
+    let mut result = evaluate_and(tokens, position, permitted);
+
+    while tokens.get(*position).is_some_and(|token| token == "OR") {
+        *position += 1;
+        // Evaluated before combining, so every rejected license is reported
+        let alternative = evaluate_and(tokens, position, permitted);
+        result = result || alternative;
+    }
+
+    result
+}
+
+fn evaluate_and(
+    tokens: &[String],
+    position: &mut usize,
+    permitted: &mut dyn FnMut(&str) -> bool,
+) -> bool {
+    let mut result = evaluate_license(tokens, position, permitted);
+
+    while tokens.get(*position).is_some_and(|token| token == "AND") {
+        *position += 1;
+        let operand = evaluate_license(tokens, position, permitted);
+        result = result && operand;
+    }
+
+    result
+}
+
+fn evaluate_license(
+    tokens: &[String],
+    position: &mut usize,
+    permitted: &mut dyn FnMut(&str) -> bool,
+) -> bool {
+    let Some(token) = tokens.get(*position) else {
+        return false;
+    };
+    *position += 1;
+
+    if token == "(" {
+        let result = evaluate_or(tokens, position, permitted);
+
+        return match tokens.get(*position).is_some_and(|token| token == ")") {
+            true => {
+                *position += 1;
+                result
+            }
+            false => false,
+        };
+    }
+
+    // `GPL-2.0-or-later WITH Classpath-exception-2.0` is judged by its license
+    if tokens.get(*position).is_some_and(|token| token == "WITH") {
+        *position += 2;
+    }
+
+    permitted(token.trim_end_matches('+'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GPL_3: &str = include_str!("../testdata/licenses/GPL-3.0.txt");
+    const LGPL_3: &str = include_str!("../testdata/licenses/LGPL-3.0.txt");
+    const AGPL_3: &str = include_str!("../testdata/licenses/AGPL-3.0.txt");
+    const GPL_2: &str = include_str!("../testdata/licenses/GPL-2.0.txt");
+    const LGPL_2_1: &str = include_str!("../testdata/licenses/LGPL-2.1.txt");
+
+    fn policy(allow: &[&str], deny: &[&str]) -> LicensePolicy {
+        LicensePolicy {
+            allow: allow.iter().map(|id| id.to_string()).collect(),
+            deny: deny.iter().map(|id| id.to_string()).collect(),
+            unknown: UnknownLicensePolicy::default(),
+            ignore: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn identifies_gpl_family_texts() {
+        assert_eq!(identify_license_text(GPL_3), Some("GPL-3.0-or-later"));
+        assert_eq!(identify_license_text(LGPL_3), Some("LGPL-3.0-or-later"));
+        assert_eq!(identify_license_text(AGPL_3), Some("AGPL-3.0-or-later"));
+        assert_eq!(identify_license_text(GPL_2), Some("GPL-2.0-or-later"));
+        assert_eq!(identify_license_text(LGPL_2_1), Some("LGPL-2.1-or-later"));
+    }
+
+    #[test]
+    fn identifies_permissive_texts() {
+        let mit = "MIT License\n\nPermission is hereby granted, free of charge, to any person \
+                   obtaining a copy...\nThe above copyright notice and this permission notice \
+                   shall be included in all copies.";
+        let bsd_2 = "Redistribution and use in source and binary forms, with or without \
+                     modification, are permitted.";
+        let bsd_3 = "Redistribution and use in source and binary forms, with or without \
+                     modification, are permitted.\n3. Neither the name of the copyright holder";
+
+        assert_eq!(identify_license_text(mit), Some("MIT"));
+        assert_eq!(identify_license_text(bsd_2), Some("BSD-2-Clause"));
+        assert_eq!(identify_license_text(bsd_3), Some("BSD-3-Clause"));
+        assert_eq!(identify_license_text("All rights reserved."), None);
+    }
+
+    #[test]
+    fn detects_license_files_and_spdx_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("COPYING"), GPL_3).unwrap();
+        fs::write(dir.path().join("NOTICE"), "Some notice").unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src").join("vendored.c"),
+            "/* SPDX-License-Identifier: MIT OR Apache-2.0 */\nint x;\n",
+        )
+        .unwrap();
+
+        let detected = detect_license(dir.path());
+
+        assert_eq!(
+            detected.license.as_deref(),
+            Some("GPL-3.0-or-later AND (MIT OR Apache-2.0)")
+        );
+        assert_eq!(
+            detected.files,
+            vec![PathBuf::from("COPYING"), PathBuf::from("NOTICE")]
+        );
+    }
+
+    #[test]
+    fn detects_nothing_without_license_information() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let detected = detect_license(dir.path());
+
+        assert_eq!(detected.license, None);
+        assert!(detected.files.is_empty());
+    }
+
+    #[test]
+    fn denies_gpl_3_text_with_gpl_glob() {
+        let license = identify_license_text(GPL_3);
+
+        assert!(matches!(
+            check_license(&policy(&[], &["GPL-*"]), license),
+            LicenseCheck::Denied(_)
+        ));
+    }
+
+    #[test]
+    fn checks_expressions_against_policy() {
+        let policy = policy(&["MIT", "Apache-2.0", "BSD-*"], &["GPL-3.0"]);
+
+        assert_eq!(check_license(&policy, Some("MIT")), LicenseCheck::Allowed);
+        assert_eq!(
+            check_license(&policy, Some("BSD-3-Clause")),
+            LicenseCheck::Allowed
+        );
+        assert_eq!(
+            check_license(&policy, Some("GPL-3.0-only OR MIT")),
+            LicenseCheck::Allowed
+        );
+        assert_eq!(
+            check_license(&policy, Some("MIT AND GPL-3.0-or-later")),
+            LicenseCheck::Denied(
+                "GPL-3.0-or-later is not allowed (in `MIT AND GPL-3.0-or-later`)".to_string()
+            )
+        );
+        assert_eq!(
+            check_license(&policy, Some("Zlib")),
+            LicenseCheck::Denied("Zlib is not allowed".to_string())
+        );
+        assert_eq!(check_license(&policy, None), LicenseCheck::Unknown);
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        let policy = policy(&[], &[]);
+
+        assert!(matches!(
+            check_license(&policy, Some("(MIT OR")),
+            LicenseCheck::Denied(_)
+        ));
+        assert!(matches!(
+            check_license(&policy, Some("MIT Apache-2.0")),
+            LicenseCheck::Denied(_)
+        ));
+    }
+}
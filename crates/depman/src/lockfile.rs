@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use util::github::LATEST_RELEASE;
+
+pub const LOCKFILE_NAME: &str = "cpppm.lock";
+
+/// `cpppm.lock`, pinning every fetched dependency to the exact sources it was fetched from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub version: u32,
+    #[serde(default)]
+    pub package: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LockedPackage {
+    pub name: String,
+    /// The registry version, for packages resolved from a registry
+    pub version: Option<String>,
+    /// `git+<url>` or `archive+<url>`
+    pub source: String,
+    pub registry: Option<String>,
+    /// The revision requested in the manifest or index
+    pub revision: Option<String>,
+    /// The commit that was checked out, for git sources
+    pub commit: Option<String>,
+    /// The SHA-256 of the archive, for archive sources
+    pub checksum: Option<String>,
+    /// The publisher's signature of `checksum`, for signed registry packages
+    pub signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+}
+
+impl Lockfile {
+    /// Loads `{project_root}/cpppm.lock`, or an empty lockfile if there is none yet
+    pub fn load(project_root: &Path) -> Result<Self, Box<dyn Error>> {
+        let lockfile_path = project_root.join(LOCKFILE_NAME);
+
+        if !lockfile_path.exists() {
+            return Ok(Lockfile {
+                version: 1,
+                package: Vec::new(),
+            });
+        }
+
+        let lockfile = toml::from_str(&fs::read_to_string(&lockfile_path)?)
+            .map_err(|e| format!("Invalid {}: {}", lockfile_path.display(), e))?;
+
+        Ok(lockfile)
+    }
+
+    pub fn save(&self, project_root: &Path) -> Result<(), Box<dyn Error>> {
+        let mut lockfile = self.clone();
+        lockfile.version = 1;
+        lockfile.package.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let contents = format!(
+            "# This file is generated by cpppm, do not edit it by hand\n\n{}",
+            toml::to_string(&lockfile)?
+        );
+        fs::write(project_root.join(LOCKFILE_NAME), contents)?;
+
+        Ok(())
+    }
+
+    pub fn find(&self, name: &str) -> Option<&LockedPackage> {
+        self.package.iter().find(|package| package.name == name)
+    }
+
+    /// Adds or replaces the entry for `package.name`
+    pub fn insert(&mut self, package: LockedPackage) {
+        self.package.retain(|locked| locked.name != package.name);
+        self.package.push(package);
+    }
+}
+
+impl LockedPackage {
+    /// The registry version, or else the revision the manifest asked for (unless it only asked
+    /// for the latest release)
+    pub fn version_or_revision(&self) -> Option<String> {
+        self.version.clone().or_else(|| {
+            self.revision
+                .clone()
+                .filter(|revision| revision != LATEST_RELEASE)
+        })
+    }
+}
@@ -0,0 +1,450 @@
+use crate::archive::sha256_file;
+use crate::signing::SecretKey;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use git2::{Repository, StatusOptions};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use util::model::{Dependency, PackageDetails, PackageRequirement, ProjectConfig};
+use walkdir::WalkDir;
+
+/// Packaged unless `include` is set in `[project-details]`, on top of the sources and headers
+const DEFAULT_INCLUDE: [&str; 6] = [
+    "**/CMakeLists.txt",
+    "**/*.cmake",
+    "cmake/**",
+    "README*",
+    "LICENSE*",
+    "COPYING*",
+];
+
+/// A source package written by [`package_project`]
+pub struct Package {
+    pub archive_path: PathBuf,
+    pub checksum: String,
+    /// The signature of `checksum`, also written to `{archive_path}.sig`
+    pub signature: Option<String>,
+    /// The packaged files, relative to the project root
+    pub files: Vec<String>,
+}
+
+/// Packs the project's sources into `{build_dir}/package/{name}-{version}.tar.gz` next to a
+/// `.sha256` file. The archive only depends on the packaged files, so packaging the same
+/// sources twice produces the same bytes. With a `signing_key`, the archive's checksum is
+/// signed into a `.sig` file as well.
+pub fn package_project(
+    project_root: &Path,
+    allow_dirty: bool,
+    signing_key: Option<&SecretKey>,
+) -> Result<Package, Box<dyn Error>> {
+    let config = util::load_project_config(&project_root.join(".cpppm.toml"))?;
+    let name = config.get_name().to_string();
+    let version = config
+        .project_details
+        .version
+        .clone()
+        .ok_or("Set `version` in `[project-details]` to package the project")?;
+    semver::Version::parse(&version)
+        .map_err(|e| format!("Invalid project version `{}`: {}", version, e))?;
+
+    let files = collect_package_files(project_root, &config)?;
+
+    if !allow_dirty {
+        check_committed(project_root, &files)?;
+    }
+
+    let manifest = normalize_manifest(project_root, &config)?;
+
+    let build_dir = project_root.join(config.get_build_dir().unwrap_or("build".to_string()));
+    let package_dir = build_dir.join("package");
+    fs::create_dir_all(&package_dir)?;
+
+    let file_name = format!("{}-{}.tar.gz", name, version);
+    let archive_path = package_dir.join(&file_name);
+    let prefix = format!("{}-{}", name, version);
+
+    let mut builder = tar::Builder::new(GzEncoder::new(
+        File::create(&archive_path)?,
+        Compression::default(),
+    ));
+
+    append_file(
+        &mut builder,
+        &prefix,
+        ".cpppm.toml",
+        manifest.as_bytes(),
+        false,
+    )?;
+
+    for file in &files {
+        let path = project_root.join(file);
+        append_file(
+            &mut builder,
+            &prefix,
+            file,
+            &fs::read(&path)?,
+            is_executable(&path)?,
+        )?;
+    }
+
+    builder.into_inner()?.finish()?;
+
+    let checksum = sha256_file(&archive_path)?;
+    fs::write(
+        package_dir.join(format!("{}.sha256", file_name)),
+        format!("{}  {}\n", checksum, file_name),
+    )?;
+
+    let signature = match signing_key {
+        Some(signing_key) => {
+            let signature = signing_key.sign_checksum(&checksum)?;
+            fs::write(
+                package_dir.join(format!("{}.sig", file_name)),
+                format!(
+                    "untrusted comment: signature from c3pm key {}\n{}\n",
+                    signing_key.public_key().id(),
+                    signature
+                ),
+            )?;
+
+            Some(signature)
+        }
+        None => None,
+    };
+
+    Ok(Package {
+        archive_path,
+        checksum,
+        signature,
+        files,
+    })
+}
+
+/// The files to package, sorted. The manifest is always packaged (normalized), `deps/`, the
+/// build directory and version control metadata never are.
+fn collect_package_files(
+    project_root: &Path,
+    config: &ProjectConfig,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let details = &config.project_details;
+    let include = match &details.include {
+        Some(include) => build_glob_set(include.iter().map(String::as_str))?,
+        None => {
+            let dirs = [config.get_sources_dir(), config.get_headers_dir()]
+                .into_iter()
+                .flatten()
+                .map(|dir| format!("{}/**", dir.trim_end_matches('/')))
+                .collect::<Vec<String>>();
+
+            build_glob_set(
+                DEFAULT_INCLUDE
+                    .iter()
+                    .copied()
+                    .chain(dirs.iter().map(String::as_str)),
+            )?
+        }
+    };
+    let exclude = build_glob_set(details.exclude.iter().flatten().map(String::as_str))?;
+
+    let build_dir = config.get_build_dir().unwrap_or("build".to_string());
+    let skipped = [
+        project_root.join(".git"),
+        project_root.join("deps"),
+        project_root.join(build_dir.trim_end_matches('/')),
+    ];
+
+    let mut files = Vec::new();
+
+    let walker = WalkDir::new(project_root)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|entry| !skipped.iter().any(|skipped| entry.path() == skipped));
+
+    for entry in walker {
+        let entry = entry?;
+
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative = entry
+            .path()
+            .strip_prefix(project_root)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if relative == ".cpppm.toml" {
+            continue;
+        }
+
+        if include.is_match(&relative) && !exclude.is_match(&relative) {
+            files.push(relative);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+fn build_glob_set<'a>(globs: impl Iterator<Item = &'a str>) -> Result<GlobSet, Box<dyn Error>> {
+    let mut builder = GlobSetBuilder::new();
+
+    for glob in globs {
+        builder.add(Glob::new(glob).map_err(|e| format!("Invalid glob `{}`: {}", glob, e))?);
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Fails if any of the files to package (or the manifest) has uncommitted changes
+fn check_committed(project_root: &Path, files: &[String]) -> Result<(), Box<dyn Error>> {
+    let Ok(repo) = Repository::discover(project_root) else {
+        return Ok(());
+    };
+    let Some(workdir) = repo.workdir() else {
+        return Ok(());
+    };
+
+    let project_prefix = project_root
+        .canonicalize()?
+        .strip_prefix(workdir.canonicalize()?)?
+        .to_path_buf();
+
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+
+    let mut dirty = Vec::new();
+
+    for status in repo.statuses(Some(&mut options))?.iter() {
+        let Some(path) = status.path() else {
+            continue;
+        };
+        let Ok(relative) = Path::new(path).strip_prefix(&project_prefix) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+
+        if relative == ".cpppm.toml" || files.contains(&relative) {
+            dirty.push(relative);
+        }
+    }
+
+    if dirty.is_empty() {
+        return Ok(());
+    }
+
+    Err(format!(
+        "{} files to package have uncommitted changes:\n\n{}\n\nCommit them or pass `--allow-dirty` to package anyway",
+        dirty.len(),
+        dirty
+            .iter()
+            .map(|file| format!("    {}", file))
+            .collect::<Vec<String>>()
+            .join("\n")
+    )
+    .into())
+}
+
+/// The manifest as it is packaged: path packages and path `[deps]` become registry packages
+/// requiring the version found in their own manifest
+fn normalize_manifest(
+    project_root: &Path,
+    config: &ProjectConfig,
+) -> Result<String, Box<dyn Error>> {
+    let mut config = config.clone();
+
+    if let Some(dependencies) = config.dependencies.take() {
+        let (path_dependencies, dependencies): (Vec<Dependency>, Vec<Dependency>) = dependencies
+            .into_iter()
+            .partition(|dependency| dependency.dependency.path.is_some());
+
+        for dependency in path_dependencies {
+            let data = dependency.dependency;
+            let path = data.path.as_deref().unwrap_or_default();
+
+            if config.packages.contains_key(&data.name) {
+                return Err(format!(
+                    "`{}` is declared both in `[deps]` and in `[packages]`",
+                    data.name
+                )
+                .into());
+            }
+
+            let version = path_version(project_root, path, &data.name)?;
+            config.packages.insert(
+                data.name,
+                PackageRequirement::Detailed(PackageDetails {
+                    version: Some(version),
+                    registry: None,
+                    path: None,
+                    optional: data.optional,
+                    options: data.options,
+                    build_type: data.build_type,
+                    targets: data.targets,
+                }),
+            );
+        }
+
+        config.dependencies = Some(dependencies).filter(|dependencies| !dependencies.is_empty());
+    }
+
+    for (name, requirement) in config.packages.iter_mut() {
+        let PackageRequirement::Detailed(details) = requirement else {
+            continue;
+        };
+        let Some(path) = details.path.take() else {
+            continue;
+        };
+
+        if details.version.is_none() {
+            details.version = Some(path_version(project_root, &path, name)?);
+        }
+    }
+
+    Ok(toml::to_string(&config)?)
+}
+
+/// The requirement a path dependency is packaged with, from the version in its own manifest
+fn path_version(project_root: &Path, path: &str, name: &str) -> Result<String, Box<dyn Error>> {
+    let dependency_config =
+        util::load_project_config(&project_root.join(path).join(".cpppm.toml"))?;
+    let version = dependency_config.project_details.version.ok_or_else(|| {
+        format!(
+            "The path package `{}` has no version, set one in its manifest or in `[packages]`",
+            name
+        )
+    })?;
+
+    Ok(format!("^{}", version))
+}
+
+/// Appends a file with normalized metadata, so the archive doesn't depend on who packaged it
+/// or when
+fn append_file(
+    builder: &mut tar::Builder<GzEncoder<File>>,
+    prefix: &str,
+    relative_path: &str,
+    contents: &[u8],
+    executable: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(if executable { 0o755 } else { 0o644 });
+    header.set_mtime(0);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_entry_type(tar::EntryType::Regular);
+
+    builder.append_data(
+        &mut header,
+        format!("{}/{}", prefix, relative_path),
+        contents,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> std::io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Ok(fs::metadata(path)?.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::model::DependencyData;
+
+    fn write_path_package(project_root: &Path, path: &str, version: Option<&str>) {
+        let mut config = ProjectConfig::default();
+        config.project_details.version = version.map(str::to_string);
+
+        fs::create_dir_all(project_root.join(path)).unwrap();
+        fs::write(
+            project_root.join(path).join(".cpppm.toml"),
+            toml::to_string(&config).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn path_dependency(name: &str, path: &str) -> Dependency {
+        Dependency {
+            dependency: DependencyData {
+                name: name.to_string(),
+                path: Some(path.to_string()),
+                optional: true,
+                targets: Some(vec![format!("{}::{}", name, name)]),
+                ..DependencyData::default()
+            },
+        }
+    }
+
+    #[test]
+    fn normalize_turns_path_deps_into_versioned_packages() {
+        let root = tempfile::tempdir().unwrap();
+        write_path_package(root.path(), "libs/geometry", Some("1.2.0"));
+
+        let config = ProjectConfig {
+            dependencies: Some(vec![
+                path_dependency("geometry", "libs/geometry"),
+                Dependency {
+                    dependency: DependencyData {
+                        name: "fmt".to_string(),
+                        repository: ("fmtlib".to_string(), "fmt".to_string()),
+                        ..DependencyData::default()
+                    },
+                },
+            ]),
+            ..ProjectConfig::default()
+        };
+
+        let manifest = normalize_manifest(root.path(), &config).unwrap();
+        let normalized: ProjectConfig = toml::from_str(&manifest).unwrap();
+
+        let dependencies = normalized.dependencies.unwrap();
+        assert_eq!(dependencies.len(), 1);
+        assert_eq!(dependencies[0].dependency.name, "fmt");
+
+        let Some(PackageRequirement::Detailed(details)) = normalized.packages.get("geometry")
+        else {
+            panic!(
+                "geometry isn't a detailed package: {:?}",
+                normalized.packages
+            );
+        };
+        assert_eq!(details.version.as_deref(), Some("^1.2.0"));
+        assert_eq!(details.path, None);
+        assert!(details.optional);
+        assert_eq!(
+            details.targets.as_deref(),
+            Some(&["geometry::geometry".to_string()][..])
+        );
+    }
+
+    #[test]
+    fn normalize_requires_a_version_in_path_deps() {
+        let root = tempfile::tempdir().unwrap();
+        write_path_package(root.path(), "libs/geometry", None);
+
+        let config = ProjectConfig {
+            dependencies: Some(vec![path_dependency("geometry", "libs/geometry")]),
+            ..ProjectConfig::default()
+        };
+
+        let error = normalize_manifest(root.path(), &config).unwrap_err();
+        assert!(error.to_string().contains("has no version"), "{}", error);
+    }
+}
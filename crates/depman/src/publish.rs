@@ -0,0 +1,104 @@
+use crate::package::{Package, package_project};
+use crate::registry::{HttpIndex, get_registry};
+use crate::server::TOKEN_VARIABLE;
+use crate::signing::SecretKey;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use util::model::ProjectConfig;
+
+/// Packages the project and uploads it to a registry served over HTTP (see
+/// [`crate::server::RegistryServer`]), which adds it to its index
+///
+/// `token` falls back to the `C3PM_REGISTRY_TOKEN` environment variable. Packages signed with
+/// `signing_key` are uploaded with the signature and public key, which the registry checks
+/// against the package's publishers.
+pub fn publish_project(
+    project_root: &Path,
+    registry: Option<&str>,
+    token: Option<String>,
+    allow_dirty: bool,
+    signing_key: Option<&SecretKey>,
+) -> Result<Package, Box<dyn Error>> {
+    let config = util::load_project_config(&project_root.join(".cpppm.toml"))?;
+    validate_manifest(&config)?;
+
+    let registry_url = get_registry(&config.registries, registry)?;
+    if !registry_url.starts_with("http://") && !registry_url.starts_with("https://") {
+        return Err(format!(
+            "Can't publish to {}, only registries served over HTTP accept packages",
+            registry_url
+        )
+        .into());
+    }
+
+    let package = package_project(project_root, allow_dirty, signing_key)?;
+
+    let index = HttpIndex {
+        url: registry_url.clone(),
+    };
+    let version = config
+        .project_details
+        .version
+        .as_deref()
+        .unwrap_or_default();
+    let mut request = ureq::put(&index.publish_url(config.get_name(), version))
+        .set("Content-Type", "application/gzip")
+        .set("X-Checksum-Sha256", &package.checksum);
+
+    if let (Some(signature), Some(signing_key)) = (&package.signature, signing_key) {
+        request = request
+            .set("X-Signature", signature)
+            .set("X-Public-Key", &signing_key.public_key().encode());
+    }
+
+    if let Some(token) = token.or_else(|| std::env::var(TOKEN_VARIABLE).ok()) {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+
+    match request.send_bytes(&fs::read(&package.archive_path)?) {
+        Ok(_) => Ok(package),
+        Err(ureq::Error::Status(status, response)) => Err(format!(
+            "The registry rejected the package ({}): {}",
+            status,
+            response.into_string().unwrap_or_default().trim()
+        )
+        .into()),
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+/// Checks what the registry needs to index the package, and warns about what consumers would
+/// miss
+fn validate_manifest(config: &ProjectConfig) -> Result<(), String> {
+    let details = &config.project_details;
+    let name = &details.name;
+
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "Invalid package name `{}`, only ASCII letters, digits, `-` and `_` are allowed",
+            name
+        ));
+    }
+
+    let version = details
+        .version
+        .as_deref()
+        .ok_or("Set `version` in `[project-details]` to publish the project")?;
+    semver::Version::parse(version)
+        .map_err(|e| format!("Invalid project version `{}`: {}", version, e))?;
+
+    if details.description.is_none() {
+        eprintln!("warning: `[project-details]` has no `description`");
+    }
+
+    if details.license.is_none() {
+        eprintln!("warning: `[project-details]` has no `license`");
+    }
+
+    Ok(())
+}
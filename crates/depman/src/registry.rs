@@ -0,0 +1,736 @@
+use crate::lockfile::Lockfile;
+use git2::Repository;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use util::model::{Dependency, PackageRequirement};
+
+/// A package's file in the index, `packages/{name}.toml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IndexPackage {
+    pub name: String,
+    pub description: Option<String>,
+    pub license: Option<String>,
+    pub repository: Option<String>,
+    /// The public keys allowed to sign the package's versions, by key id
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub publishers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub versions: Vec<IndexVersion>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IndexVersion {
+    pub version: String,
+    /// A git repository and revision to fetch the version from
+    pub git: Option<String>,
+    pub rev: Option<String>,
+    /// A source archive to fetch the version from
+    pub url: Option<String>,
+    /// The SHA-256 of the source archive
+    pub checksum: Option<String>,
+    /// Maps package names to semver requirements
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub deps: BTreeMap<String, String>,
+    /// A publisher's signature of `checksum`
+    pub signature: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub yanked: bool,
+}
+
+impl IndexPackage {
+    /// The newest version that isn't yanked
+    pub fn latest(&self) -> Option<&IndexVersion> {
+        self.versions
+            .iter()
+            .filter(|entry| !entry.yanked)
+            .filter_map(|entry| Some((Version::parse(&entry.version).ok()?, entry)))
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, entry)| entry)
+    }
+
+    /// Whether `query` appears in the package's name or description, ignoring case
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+
+        self.name.to_lowercase().contains(&query)
+            || self
+                .description
+                .as_ref()
+                .is_some_and(|description| description.to_lowercase().contains(&query))
+    }
+}
+
+/// Read access to a registry index
+pub trait Index {
+    /// The index URL, as configured in `[registries]`
+    fn url(&self) -> &str;
+
+    fn package(&self, name: &str) -> Result<Option<IndexPackage>, Box<dyn Error>>;
+
+    /// The packages whose name or description contain `query`, sorted by name
+    fn search(&self, query: &str) -> Result<Vec<IndexPackage>, Box<dyn Error>>;
+}
+
+/// The response to a registry server's `GET /api/v1/search?q={query}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchResults {
+    #[serde(default)]
+    pub packages: Vec<IndexPackage>,
+}
+
+/// An index stored in a local directory (or a local clone of a git index)
+pub struct DirectoryIndex {
+    pub url: String,
+    pub root: PathBuf,
+}
+
+impl Index for DirectoryIndex {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn package(&self, name: &str) -> Result<Option<IndexPackage>, Box<dyn Error>> {
+        let package_path = self.root.join("packages").join(format!("{}.toml", name));
+
+        if !package_path.exists() {
+            return Ok(None);
+        }
+
+        let package: IndexPackage = toml::from_str(&fs::read_to_string(&package_path)?)
+            .map_err(|e| format!("Invalid index file {}: {}", package_path.display(), e))?;
+
+        Ok(Some(package))
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<IndexPackage>, Box<dyn Error>> {
+        let packages_dir = self.root.join("packages");
+        let mut packages = Vec::new();
+
+        if !packages_dir.exists() {
+            return Ok(packages);
+        }
+
+        for entry in fs::read_dir(&packages_dir)? {
+            let path = entry?.path();
+
+            let Some(name) = path
+                .file_name()
+                .and_then(|file_name| file_name.to_str())
+                .and_then(|file_name| file_name.strip_suffix(".toml"))
+            else {
+                continue;
+            };
+
+            if let Some(package) = self.package(name)?
+                && package.matches(query)
+            {
+                packages.push(package);
+            }
+        }
+
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(packages)
+    }
+}
+
+/// An index served by a c3pm registry server, see [`crate::server`]
+pub struct HttpIndex {
+    pub url: String,
+}
+
+impl HttpIndex {
+    /// The endpoint `cpppm publish` uploads `name`'s `version` to
+    pub fn publish_url(&self, name: &str, version: &str) -> String {
+        format!(
+            "{}/api/v1/packages/{}/{}",
+            self.url.trim_end_matches('/'),
+            name,
+            version
+        )
+    }
+}
+
+impl Index for HttpIndex {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn package(&self, name: &str) -> Result<Option<IndexPackage>, Box<dyn Error>> {
+        let package_url = format!(
+            "{}/index/packages/{}.toml",
+            self.url.trim_end_matches('/'),
+            name
+        );
+
+        let response = match ureq::get(&package_url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let package: IndexPackage = toml::from_str(&response.into_string()?)
+            .map_err(|e| format!("Invalid index file {}: {}", package_url, e))?;
+
+        Ok(Some(package))
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<IndexPackage>, Box<dyn Error>> {
+        let search_url = format!("{}/api/v1/search", self.url.trim_end_matches('/'));
+        let response = ureq::get(&search_url).query("q", query).call()?;
+
+        let results: SearchResults = toml::from_str(&response.into_string()?)
+            .map_err(|e| format!("Invalid search results from {}: {}", self.url, e))?;
+
+        Ok(results.packages)
+    }
+}
+
+/// Opens the index at `url`, which is either a directory (a plain path or a `file://` URL), a
+/// git repository (`git+<url>` or a URL ending in `.git`) that is cloned into the c3pm cache,
+/// or a c3pm registry server (`http://` or `https://`)
+pub fn open_index(url: &str) -> Result<Box<dyn Index>, Box<dyn Error>> {
+    if let Some(git_url) = url.strip_prefix("git+") {
+        return open_git_index(url, git_url);
+    }
+
+    if url.ends_with(".git") {
+        return open_git_index(url, url);
+    }
+
+    if let Some(path) = url.strip_prefix("file://") {
+        return Ok(Box::new(DirectoryIndex {
+            url: url.to_string(),
+            root: PathBuf::from(path),
+        }));
+    }
+
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return Ok(Box::new(HttpIndex {
+            url: url.to_string(),
+        }));
+    }
+
+    if url.contains("://") {
+        return Err(format!("Unsupported registry index URL: {}", url).into());
+    }
+
+    Ok(Box::new(DirectoryIndex {
+        url: url.to_string(),
+        root: PathBuf::from(url),
+    }))
+}
+
+fn open_git_index(url: &str, git_url: &str) -> Result<Box<dyn Index>, Box<dyn Error>> {
+    let digest = hex::encode(Sha256::digest(git_url.as_bytes()));
+    let root = util::get_cache_dir().join("registry").join(&digest[..16]);
+
+    update_git_index(git_url, &root)?;
+
+    Ok(Box::new(DirectoryIndex {
+        url: url.to_string(),
+        root,
+    }))
+}
+
+/// Clones the index (or any other repository c3pm keeps in its cache), or fast-forwards an
+/// existing clone to the remote's `HEAD`
+pub(crate) fn update_git_index(git_url: &str, root: &Path) -> Result<(), Box<dyn Error>> {
+    if !root.exists() {
+        Repository::clone(git_url, root)?;
+        return Ok(());
+    }
+
+    let repo = Repository::open(root)?;
+    repo.find_remote("origin")?.fetch(&["HEAD"], None, None)?;
+
+    let fetch_head = repo.find_reference("FETCH_HEAD")?.peel_to_commit()?;
+    repo.checkout_tree(
+        fetch_head.as_object(),
+        Some(git2::build::CheckoutBuilder::new().force()),
+    )?;
+    repo.set_head_detached(fetch_head.id())?;
+
+    Ok(())
+}
+
+/// A manifest's `[registries]`, opened
+pub struct Registries {
+    indexes: BTreeMap<String, Box<dyn Index>>,
+}
+
+impl Registries {
+    pub fn open(registries: &BTreeMap<String, String>) -> Result<Self, Box<dyn Error>> {
+        let mut indexes = BTreeMap::new();
+
+        for (name, url) in registries {
+            indexes.insert(name.clone(), open_index(url)?);
+        }
+
+        Ok(Registries { indexes })
+    }
+
+    /// Looks up a registry by name, defaulting to the one named `default` or the only one
+    pub fn get(&self, name: Option<&str>) -> Result<&dyn Index, String> {
+        get_registry(&self.indexes, name).map(|index| index.as_ref())
+    }
+
+    /// The registries to query, either the one named `registry` or all of them
+    fn selected(&self, registry: Option<&str>) -> Result<Vec<(&String, &dyn Index)>, String> {
+        match registry {
+            Some(registry) => self
+                .indexes
+                .get_key_value(registry)
+                .map(|(name, index)| vec![(name, index.as_ref())])
+                .ok_or_else(|| format!("Unknown registry `{}`", registry)),
+            None => Ok(self
+                .indexes
+                .iter()
+                .map(|(name, index)| (name, index.as_ref()))
+                .collect()),
+        }
+    }
+
+    /// Searches the registry named `registry`, or every registry, returning each matching package
+    /// with the name of the registry it was found in
+    pub fn search(
+        &self,
+        registry: Option<&str>,
+        query: &str,
+    ) -> Result<Vec<(String, IndexPackage)>, Box<dyn Error>> {
+        let mut results = Vec::new();
+
+        for (name, index) in self.selected(registry)? {
+            for package in index.search(query)? {
+                results.push((name.clone(), package));
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Looks `package` up in the registry named `registry`, or in the first registry that has it
+    pub fn find(
+        &self,
+        registry: Option<&str>,
+        package: &str,
+    ) -> Result<Option<(String, IndexPackage)>, Box<dyn Error>> {
+        for (name, index) in self.selected(registry)? {
+            if let Some(found) = index.package(package)? {
+                return Ok(Some((name.clone(), found)));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Looks up a registry's value by name in `registries`, defaulting to the one named `default` or
+/// the only one
+pub fn get_registry<'a, T>(
+    registries: &'a BTreeMap<String, T>,
+    name: Option<&str>,
+) -> Result<&'a T, String> {
+    let registry = match name {
+        Some(name) => registries.get(name),
+        None if registries.len() == 1 => registries.values().next(),
+        None => registries.get("default"),
+    };
+
+    match (registry, name) {
+        (Some(registry), _) => Ok(registry),
+        (None, Some(name)) => Err(format!("Unknown registry `{}`", name)),
+        (None, None) => {
+            Err("No registry configured, add a `default` entry to `[registries]`".to_string())
+        }
+    }
+}
+
+/// A package version picked by the resolver
+#[derive(Debug, Clone)]
+pub struct ResolvedPackage {
+    pub name: String,
+    pub version: Version,
+    pub registry: String,
+    pub entry: IndexVersion,
+    /// The package's publisher keys, to verify `entry.signature` with
+    pub publishers: BTreeMap<String, String>,
+}
+
+impl ResolvedPackage {
+    /// Turns the resolved version into a dependency depman can fetch, keeping the settings of
+    /// the project's `[packages]` entry if there is one
+    pub fn to_dependency(&self, requirement: Option<&PackageRequirement>) -> Dependency {
+        let mut dependency = match requirement {
+            Some(requirement) => requirement.to_dependency(&self.name),
+            None => PackageRequirement::Version(String::new()).to_dependency(&self.name),
+        };
+
+        let data = &mut dependency.dependency;
+        data.git = self.entry.git.clone();
+        data.revision = self.entry.rev.clone();
+        data.url = self.entry.url.clone();
+        data.checksum = self.entry.checksum.clone();
+        data.version = Some(self.version.to_string());
+        data.registry = Some(self.registry.clone());
+
+        dependency
+    }
+}
+
+struct Constraint<'a> {
+    registry: Option<&'a str>,
+    requirements: Vec<VersionReq>,
+}
+
+/// Resolves `requirements` and everything they depend on to concrete versions. Versions pinned
+/// in `lockfile` are kept as long as they still match, otherwise the newest matching, non-yanked
+/// version is picked. Dependencies of registry packages are resolved from the same registry.
+pub fn resolve_packages(
+    requirements: &BTreeMap<String, PackageRequirement>,
+    registries: &Registries,
+    lockfile: &Lockfile,
+) -> Result<Vec<ResolvedPackage>, Box<dyn Error>> {
+    let mut selected: BTreeMap<String, ResolvedPackage> = BTreeMap::new();
+    let mut index_cache: BTreeMap<(String, String), IndexPackage> = BTreeMap::new();
+    // The registry each selected package was resolved from, by package name
+    let mut origins: BTreeMap<String, Option<&str>> = BTreeMap::new();
+
+    for _ in 0..100 {
+        let mut constraints: BTreeMap<String, Constraint> = BTreeMap::new();
+
+        for (name, requirement) in requirements {
+            add_constraint(
+                &mut constraints,
+                name,
+                requirement.version_req(),
+                requirement.registry(),
+            )?;
+        }
+
+        for package in selected.values() {
+            let registry = origins.get(&package.name).copied().flatten();
+
+            for (name, requirement) in &package.entry.deps {
+                add_constraint(&mut constraints, name, requirement, registry)?;
+            }
+        }
+
+        let mut next: BTreeMap<String, ResolvedPackage> = BTreeMap::new();
+
+        for (name, constraint) in &constraints {
+            let matches_all = |version: &Version| {
+                constraint
+                    .requirements
+                    .iter()
+                    .all(|requirement| requirement.matches(version))
+            };
+
+            if let Some(current) = selected.get(name)
+                && matches_all(&current.version)
+            {
+                next.insert(name.clone(), current.clone());
+                continue;
+            }
+
+            let index = registries.get(constraint.registry)?;
+            let cache_key = (index.url().to_string(), name.clone());
+
+            if !index_cache.contains_key(&cache_key) {
+                let package = index.package(name)?.ok_or_else(|| {
+                    format!("Package `{}` not found in registry {}", name, index.url())
+                })?;
+                index_cache.insert(cache_key.clone(), package);
+            }
+
+            let package = &index_cache[&cache_key];
+            let locked = lockfile
+                .find(name)
+                .and_then(|locked| locked.version.as_deref())
+                .and_then(|version| Version::parse(version).ok());
+
+            let mut candidates = package
+                .versions
+                .iter()
+                .filter_map(|entry| Some((Version::parse(&entry.version).ok()?, entry)))
+                .filter(|(version, entry)| matches_all(version) && !entry.yanked)
+                .collect::<Vec<(Version, &IndexVersion)>>();
+            candidates.sort_by(|a, b| b.0.cmp(&a.0));
+
+            let picked = candidates
+                .iter()
+                .find(|(version, _)| Some(version) == locked.as_ref())
+                .or(candidates.first())
+                .ok_or_else(|| {
+                    let formatted_requirements = constraint
+                        .requirements
+                        .iter()
+                        .map(|r| r.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ");
+
+                    format!(
+                        "No version of `{}` matches {}",
+                        name, formatted_requirements
+                    )
+                })?;
+
+            next.insert(
+                name.clone(),
+                ResolvedPackage {
+                    name: name.clone(),
+                    version: picked.0.clone(),
+                    registry: index.url().to_string(),
+                    entry: picked.1.clone(),
+                    publishers: package.publishers.clone(),
+                },
+            );
+        }
+
+        origins = constraints
+            .iter()
+            .map(|(name, constraint)| (name.clone(), constraint.registry))
+            .collect();
+
+        let unchanged = next.len() == selected.len()
+            && next.iter().all(|(name, package)| {
+                selected.get(name).map(|p| &p.version) == Some(&package.version)
+            });
+
+        selected = next;
+
+        if unchanged {
+            return Ok(selected.into_values().collect());
+        }
+    }
+
+    Err("Package resolution did not converge".into())
+}
+
+fn add_constraint<'a>(
+    constraints: &mut BTreeMap<String, Constraint<'a>>,
+    name: &str,
+    requirement: &str,
+    registry: Option<&'a str>,
+) -> Result<(), Box<dyn Error>> {
+    let requirement = VersionReq::parse(requirement).map_err(|e| {
+        format!(
+            "Invalid version requirement `{}` for `{}`: {}",
+            requirement, name, e
+        )
+    })?;
+
+    let constraint = constraints
+        .entry(name.to_string())
+        .or_insert_with(|| Constraint {
+            registry,
+            requirements: Vec::new(),
+        });
+
+    constraint.registry = constraint.registry.or(registry);
+    constraint.requirements.push(requirement);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lockfile::LockedPackage;
+
+    /// A package's versions, each given as `(version, [(dependency, requirement)])`
+    type TestVersions<'a> = &'a [(&'a str, &'a [(&'a str, &'a str)])];
+
+    /// Writes `packages` to `{root}/packages/{name}.toml`, each given as `(name, versions)`
+    fn write_index(root: &Path, packages: &[(&str, TestVersions)]) {
+        fs::create_dir_all(root.join("packages")).unwrap();
+
+        for (name, versions) in packages {
+            let package = IndexPackage {
+                name: name.to_string(),
+                description: None,
+                license: None,
+                repository: None,
+                publishers: BTreeMap::new(),
+                versions: versions
+                    .iter()
+                    .map(|(version, deps)| IndexVersion {
+                        version: version.to_string(),
+                        git: Some(format!("https://example.com/{}.git", name)),
+                        rev: Some(format!("v{}", version)),
+                        url: None,
+                        checksum: None,
+                        deps: deps
+                            .iter()
+                            .map(|(dep, requirement)| (dep.to_string(), requirement.to_string()))
+                            .collect(),
+                        signature: None,
+                        yanked: false,
+                    })
+                    .collect(),
+            };
+
+            fs::write(
+                root.join("packages").join(format!("{}.toml", name)),
+                toml::to_string(&package).unwrap(),
+            )
+            .unwrap();
+        }
+    }
+
+    fn registries(roots: &[(&str, &Path)]) -> Registries {
+        Registries {
+            indexes: roots
+                .iter()
+                .map(|(name, root)| {
+                    let index: Box<dyn Index> = Box::new(DirectoryIndex {
+                        url: name.to_string(),
+                        root: root.to_path_buf(),
+                    });
+                    (name.to_string(), index)
+                })
+                .collect(),
+        }
+    }
+
+    fn requirements(manifest: &str) -> BTreeMap<String, PackageRequirement> {
+        toml::from_str(manifest).unwrap()
+    }
+
+    fn versions(resolved: &[ResolvedPackage]) -> Vec<(String, String, String)> {
+        resolved
+            .iter()
+            .map(|package| {
+                (
+                    package.name.clone(),
+                    package.version.to_string(),
+                    package.registry.clone(),
+                )
+            })
+            .collect()
+    }
+
+    fn package(name: &str, version: &str, registry: &str) -> (String, String, String) {
+        (name.to_string(), version.to_string(), registry.to_string())
+    }
+
+    #[test]
+    fn resolve_picks_newest_matching_versions_of_every_dependency() {
+        let root = tempfile::tempdir().unwrap();
+        write_index(
+            root.path(),
+            &[
+                (
+                    "app-utils",
+                    &[("1.0.0", &[("fmt", "^9")]), ("1.2.0", &[("fmt", "^10")])],
+                ),
+                ("fmt", &[("9.1.0", &[]), ("10.1.1", &[]), ("10.2.0", &[])]),
+            ],
+        );
+
+        let resolved = resolve_packages(
+            &requirements("app-utils = \"^1\""),
+            &registries(&[("default", root.path())]),
+            &Lockfile::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            versions(&resolved),
+            [
+                package("app-utils", "1.2.0", "default"),
+                package("fmt", "10.2.0", "default"),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_keeps_locked_versions_that_still_match() {
+        let root = tempfile::tempdir().unwrap();
+        write_index(root.path(), &[("fmt", &[("10.1.1", &[]), ("10.2.0", &[])])]);
+
+        let lockfile = Lockfile {
+            version: 1,
+            package: vec![LockedPackage {
+                name: "fmt".to_string(),
+                version: Some("10.1.1".to_string()),
+                source: "git+https://example.com/fmt.git".to_string(),
+                registry: None,
+                revision: None,
+                commit: None,
+                checksum: None,
+                signature: None,
+                dependencies: Vec::new(),
+            }],
+        };
+
+        let registries = registries(&[("default", root.path())]);
+        let locked = resolve_packages(&requirements("fmt = \"^10\""), &registries, &lockfile);
+        let unlocked = resolve_packages(&requirements("fmt = \"^10.2\""), &registries, &lockfile);
+
+        assert_eq!(
+            versions(&locked.unwrap()),
+            [package("fmt", "10.1.1", "default")]
+        );
+        assert_eq!(
+            versions(&unlocked.unwrap()),
+            [package("fmt", "10.2.0", "default")]
+        );
+    }
+
+    #[test]
+    fn resolve_takes_transitive_packages_from_their_parents_registry() {
+        let default = tempfile::tempdir().unwrap();
+        let internal = tempfile::tempdir().unwrap();
+        write_index(default.path(), &[("fmt", &[("10.2.0", &[])])]);
+        write_index(
+            internal.path(),
+            &[
+                ("app-utils", &[("1.0.0", &[("fmt", "^9")])]),
+                ("fmt", &[("9.1.0", &[])]),
+            ],
+        );
+
+        let resolved = resolve_packages(
+            &requirements("app-utils = { version = \"^1\", registry = \"internal\" }"),
+            &registries(&[("default", default.path()), ("internal", internal.path())]),
+            &Lockfile::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            versions(&resolved),
+            [
+                package("app-utils", "1.0.0", "internal"),
+                package("fmt", "9.1.0", "internal"),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_reports_conflicting_requirements() {
+        let root = tempfile::tempdir().unwrap();
+        write_index(
+            root.path(),
+            &[
+                ("app-utils", &[("1.0.0", &[("fmt", "^9")])]),
+                ("fmt", &[("9.1.0", &[]), ("10.2.0", &[])]),
+            ],
+        );
+
+        let error = resolve_packages(
+            &requirements("app-utils = \"^1\"\nfmt = \"^10\""),
+            &registries(&[("default", root.path())]),
+            &Lockfile::default(),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.to_string(), "No version of `fmt` matches ^10, ^9");
+    }
+}
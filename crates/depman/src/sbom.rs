@@ -0,0 +1,407 @@
+use crate::license::detect_license;
+use crate::lockfile::Lockfile;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::Path;
+use util::context::ProjectContext;
+use util::model::{Dependency, FeatureSelection};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    /// SPDX 2.3 as JSON
+    SpdxJson,
+    /// CycloneDX 1.5 as JSON
+    CycloneDxJson,
+}
+
+/// The project or one of its dependencies, as described in the SBOM
+struct Component {
+    name: String,
+    version: Option<String>,
+    /// `git`, `archive` or `path`
+    source_kind: Option<String>,
+    /// Where the sources were fetched from
+    source_url: Option<String>,
+    commit: Option<String>,
+    checksum: Option<String>,
+    license: Option<String>,
+    /// Provided by the build environment, so only its name and required version are known
+    system: bool,
+    required_version: Option<String>,
+    purl: String,
+    dependencies: Vec<String>,
+}
+
+/// Describes the project and its resolved dependency graph (as fetched into `deps/` and recorded
+/// in `cpppm.lock`) in `format`, including dev-dependencies when `dev` is set
+///
+/// The timestamp is taken from `SOURCE_DATE_EPOCH` when set, and the document's serial number
+/// is derived from its contents, so the same inputs produce the same SBOM.
+pub fn generate_sbom(
+    context: &ProjectContext,
+    selection: &FeatureSelection,
+    dev: bool,
+    format: SbomFormat,
+) -> Result<String, Box<dyn Error>> {
+    let project_root = context.root.as_path();
+    let platform = context.platform();
+    let (config, _) = context.config.for_platform(&platform)?;
+    let features = config.resolve_features(selection)?;
+    let lockfile = Lockfile::load(project_root)?;
+
+    let mut dependencies = util::collect_dependencies(project_root, &config, &features, &platform);
+    let mut direct = config.get_dependencies();
+
+    if dev {
+        dependencies.extend(util::collect_dev_dependencies(
+            project_root,
+            &config,
+            &features,
+            &platform,
+        ));
+        direct.extend(config.dev_dependencies.clone().unwrap_or_default());
+    }
+
+    direct.retain(|dependency| features.is_dependency_enabled(dependency));
+
+    let mut components = dependencies
+        .iter()
+        .map(|dependency| get_component(project_root, &lockfile, dependency))
+        .collect::<Result<Vec<Component>, String>>()?;
+
+    // Only keep the edges to components that are part of this build
+    let names = components
+        .iter()
+        .map(|component| component.name.clone())
+        .collect::<BTreeSet<String>>();
+    for component in &mut components {
+        component.dependencies.retain(|name| names.contains(name));
+    }
+
+    let details = &config.project_details;
+    let root = Component {
+        name: details.name.clone(),
+        version: details.version.clone(),
+        source_kind: None,
+        source_url: None,
+        commit: None,
+        checksum: None,
+        license: details.license.clone(),
+        system: false,
+        required_version: None,
+        purl: get_purl(&details.name, details.version.as_deref(), None),
+        dependencies: direct
+            .iter()
+            .map(|dependency| dependency.dependency.name.clone())
+            .filter(|name| names.contains(name))
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect(),
+    };
+
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<i64>().ok())
+        .and_then(|epoch| DateTime::from_timestamp(epoch, 0))
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let document = match format {
+        SbomFormat::SpdxJson => get_spdx_document(&root, &components, &timestamp),
+        SbomFormat::CycloneDxJson => get_cyclonedx_document(&root, &components, &timestamp),
+    };
+
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+fn get_component(
+    project_root: &Path,
+    lockfile: &Lockfile,
+    dependency: &Dependency,
+) -> Result<Component, String> {
+    let data = &dependency.dependency;
+
+    if data.system {
+        return Ok(Component {
+            name: data.name.clone(),
+            version: None,
+            source_kind: None,
+            source_url: None,
+            commit: None,
+            checksum: None,
+            license: None,
+            system: true,
+            required_version: data.version.clone(),
+            purl: get_purl(&data.name, None, None),
+            dependencies: Vec::new(),
+        });
+    }
+
+    let locked = lockfile
+        .find(&data.name)
+        .ok_or_else(|| format!("{} is not locked, run `cpppm fetch` first", data.name))?;
+    let (source_kind, source_url) = match locked.source.split_once('+') {
+        Some((kind, url)) => (Some(kind.to_string()), Some(url.to_string())),
+        None => (None, None),
+    };
+    let version = locked.version_or_revision();
+
+    Ok(Component {
+        name: data.name.clone(),
+        purl: get_purl(
+            &data.name,
+            version.as_deref().or(locked.commit.as_deref()),
+            source_url.as_deref(),
+        ),
+        version,
+        source_kind,
+        source_url,
+        commit: locked.commit.clone(),
+        checksum: locked.checksum.clone(),
+        license: detect_license(&project_root.join("deps").join(&data.name)).license,
+        system: false,
+        required_version: None,
+        dependencies: locked.dependencies.clone(),
+    })
+}
+
+/// A package URL, `pkg:github/{owner}/{repo}` for GitHub sources and `pkg:generic/{name}`
+/// otherwise
+fn get_purl(name: &str, version: Option<&str>, source_url: Option<&str>) -> String {
+    let github_repository = source_url
+        .and_then(|url| url.strip_prefix("https://github.com/"))
+        .map(|repository| repository.trim_end_matches('/').trim_end_matches(".git"))
+        .filter(|repository| repository.split('/').count() == 2);
+
+    let purl = match github_repository {
+        Some(repository) => format!("pkg:github/{}", repository.to_lowercase()),
+        None => format!("pkg:generic/{}", name),
+    };
+
+    match version {
+        Some(version) => format!("{}@{}", purl, version),
+        None => purl,
+    }
+}
+
+fn get_spdx_document(root: &Component, components: &[Component], timestamp: &str) -> Value {
+    let packages = std::iter::once(root)
+        .chain(components)
+        .map(get_spdx_package)
+        .collect::<Vec<Value>>();
+
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": get_spdx_id(&root.name),
+    })];
+
+    for component in std::iter::once(root).chain(components) {
+        for dependency in &component.dependencies {
+            relationships.push(json!({
+                "spdxElementId": get_spdx_id(&component.name),
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": get_spdx_id(dependency),
+            }));
+        }
+    }
+
+    let mut document = json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": get_document_name(root),
+        "creationInfo": {
+            "created": timestamp,
+            "creators": [format!("Tool: cpppm-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    });
+
+    document["documentNamespace"] = json!(format!(
+        "https://spdx.org/spdxdocs/{}-{}",
+        get_document_name(root),
+        get_content_uuid(&document)
+    ));
+
+    document
+}
+
+fn get_spdx_package(component: &Component) -> Value {
+    let download_location = match (component.source_kind.as_deref(), &component.source_url) {
+        (Some("git"), Some(url)) => match &component.commit {
+            Some(commit) => format!("git+{}@{}", url, commit),
+            None => format!("git+{}", url),
+        },
+        (Some("archive"), Some(url)) => url.clone(),
+        _ => "NOASSERTION".to_string(),
+    };
+
+    let mut package = json!({
+        "name": component.name,
+        "SPDXID": get_spdx_id(&component.name),
+        "downloadLocation": download_location,
+        "filesAnalyzed": false,
+        "licenseConcluded": "NOASSERTION",
+        "licenseDeclared": component.license.as_deref().unwrap_or("NOASSERTION"),
+        "copyrightText": "NOASSERTION",
+        "externalRefs": [{
+            "referenceCategory": "PACKAGE-MANAGER",
+            "referenceType": "purl",
+            "referenceLocator": component.purl,
+        }],
+    });
+
+    if let Some(version) = &component.version {
+        package["versionInfo"] = json!(version);
+    }
+
+    if let Some(checksum) = &component.checksum {
+        package["checksums"] = json!([{ "algorithm": "SHA256", "checksumValue": checksum }]);
+    }
+
+    if component.system {
+        package["comment"] = json!(match &component.required_version {
+            Some(version) => format!("System dependency (version {} or newer)", version),
+            None => "System dependency".to_string(),
+        });
+    }
+
+    package
+}
+
+fn get_cyclonedx_document(root: &Component, components: &[Component], timestamp: &str) -> Value {
+    let dependencies = std::iter::once(root)
+        .chain(components)
+        .map(|component| {
+            json!({
+                "ref": component.name,
+                "dependsOn": component.dependencies,
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    let mut root_component = get_cyclonedx_component(root);
+    root_component["type"] = json!("application");
+
+    let mut document = json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": timestamp,
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": "cpppm",
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+            "component": root_component,
+        },
+        "components": components
+            .iter()
+            .map(get_cyclonedx_component)
+            .collect::<Vec<Value>>(),
+        "dependencies": dependencies,
+    });
+
+    document["serialNumber"] = json!(format!("urn:uuid:{}", get_content_uuid(&document)));
+
+    document
+}
+
+fn get_cyclonedx_component(component: &Component) -> Value {
+    let mut value = json!({
+        "type": "library",
+        "bom-ref": component.name,
+        "name": component.name,
+        "purl": component.purl,
+    });
+
+    if let Some(version) = &component.version {
+        value["version"] = json!(version);
+    }
+
+    if let Some(checksum) = &component.checksum {
+        value["hashes"] = json!([{ "alg": "SHA-256", "content": checksum }]);
+    }
+
+    if let Some(license) = &component.license {
+        value["licenses"] = json!([{ "expression": license }]);
+    }
+
+    let reference_type = match component.source_kind.as_deref() {
+        Some("git") => Some("vcs"),
+        Some("archive") => Some("distribution"),
+        _ => None,
+    };
+
+    if let (Some(reference_type), Some(url)) = (reference_type, &component.source_url) {
+        value["externalReferences"] = json!([{ "type": reference_type, "url": url }]);
+    }
+
+    let mut properties = Vec::new();
+
+    if let Some(commit) = &component.commit {
+        properties.push(json!({ "name": "cpppm:commit", "value": commit }));
+    }
+
+    if component.system {
+        properties.push(json!({ "name": "cpppm:system", "value": "true" }));
+    }
+
+    if let Some(version) = &component.required_version {
+        properties.push(json!({ "name": "cpppm:required-version", "value": version }));
+    }
+
+    if !properties.is_empty() {
+        value["properties"] = json!(properties);
+    }
+
+    value
+}
+
+fn get_document_name(root: &Component) -> String {
+    match &root.version {
+        Some(version) => format!("{}-{}", root.name, version),
+        None => root.name.clone(),
+    }
+}
+
+/// SPDX ids may only contain letters, digits, `.` and `-`
+fn get_spdx_id(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                true => c,
+                false => '-',
+            },
+        )
+        .collect::<String>();
+
+    format!("SPDXRef-Package-{}", name)
+}
+
+/// A UUID derived from the document's contents, using version 8 (the custom UUID version)
+fn get_content_uuid(document: &Value) -> String {
+    let digest = Sha256::digest(document.to_string());
+    let mut bytes: [u8; 16] = digest[..16].try_into().unwrap_or_default();
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
@@ -0,0 +1,630 @@
+use crate::registry::{DirectoryIndex, Index, IndexPackage, IndexVersion, SearchResults};
+use crate::signing::{PublicKey, verify_checksum};
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tiny_http::{Header, Method, Request, Response, ResponseBox, Server};
+use util::model::ProjectConfig;
+
+/// The environment variable holding the token `cpppm publish` authenticates with, and the token
+/// `cpppm registry serve` accepts when none is passed on the command line
+pub const TOKEN_VARIABLE: &str = "C3PM_REGISTRY_TOKEN";
+
+/// A minimal c3pm registry, storing its index in `{root}/packages` (the same layout as a
+/// directory index) and the published archives in `{root}/archives`
+///
+/// Serves `GET /index/packages/{name}.toml`, `GET /archives/{file}`, `GET /api/v1/search?q=`
+/// and `PUT /api/v1/packages/{name}/{version}`, which publishes the `.tar.gz` in the request body.
+pub struct RegistryServer {
+    root: PathBuf,
+    /// Publishing requires `Authorization: Bearer {token}` when set
+    token: Option<String>,
+    /// The URL consumers reach the registry at, which the index's archive URLs start with
+    /// (defaults to `http://{address}`)
+    public_url: Option<String>,
+}
+
+impl RegistryServer {
+    pub fn new(
+        root: &Path,
+        token: Option<String>,
+        public_url: Option<String>,
+    ) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(root.join("packages"))?;
+        fs::create_dir_all(root.join("archives"))?;
+
+        Ok(RegistryServer {
+            root: root.to_path_buf(),
+            token,
+            public_url,
+        })
+    }
+
+    /// Handles requests on `address` (e.g. `127.0.0.1:8080`) until the process is stopped
+    pub fn serve(&self, address: &str) -> Result<(), Box<dyn Error>> {
+        let server = Server::http(address).map_err(|e| e.to_string())?;
+
+        self.run(server);
+
+        Ok(())
+    }
+
+    fn run(&self, server: Server) {
+        let public_url = match &self.public_url {
+            Some(public_url) => public_url.trim_end_matches('/').to_string(),
+            None => format!("http://{}", server.server_addr()),
+        };
+
+        println!(
+            "Serving the registry in {} on http://{}",
+            self.root.display(),
+            server.server_addr()
+        );
+
+        for mut request in server.incoming_requests() {
+            let response =
+                self.handle(&mut request, &public_url)
+                    .unwrap_or_else(|(status, message)| {
+                        Response::from_string(format!("{}\n", message))
+                            .with_status_code(status)
+                            .boxed()
+                    });
+
+            println!(
+                "{} {} {}",
+                request.method(),
+                request.url(),
+                response.status_code().0
+            );
+
+            if let Err(e) = request.respond(response) {
+                eprintln!("Failed to respond: {}", e);
+            }
+        }
+    }
+
+    fn handle(
+        &self,
+        request: &mut Request,
+        public_url: &str,
+    ) -> Result<ResponseBox, (u16, String)> {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let segments = path
+            .trim_start_matches('/')
+            .split('/')
+            .collect::<Vec<&str>>();
+
+        if segments.iter().any(|segment| !is_safe_segment(segment)) {
+            return Err((400, "Invalid path".to_string()));
+        }
+
+        match (request.method(), segments.as_slice()) {
+            (Method::Get, ["index", "packages", file]) => {
+                self.serve_file(&self.root.join("packages").join(file))
+            }
+            (Method::Get, ["archives", file]) => {
+                self.serve_file(&self.root.join("archives").join(file))
+            }
+            (Method::Get, ["api", "v1", "search"]) => self.search(query),
+            (Method::Put, ["api", "v1", "packages", name, version]) => {
+                let (name, version) = (name.to_string(), version.to_string());
+                self.publish(request, public_url, &name, &version)
+            }
+            _ => Err((404, "Not found".to_string())),
+        }
+    }
+
+    fn serve_file(&self, path: &Path) -> Result<ResponseBox, (u16, String)> {
+        match fs::File::open(path) {
+            Ok(file) => Ok(Response::from_file(file).boxed()),
+            Err(_) => Err((404, "Not found".to_string())),
+        }
+    }
+
+    fn search(&self, query: &str) -> Result<ResponseBox, (u16, String)> {
+        let search = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == "q")
+            .map(|(_, value)| decode_query_value(value))
+            .unwrap_or_default();
+
+        let index = DirectoryIndex {
+            url: self.root.display().to_string(),
+            root: self.root.clone(),
+        };
+        let results = SearchResults {
+            packages: index.search(&search).map_err(|e| (500, e.to_string()))?,
+        };
+
+        let contents = toml::to_string(&results).map_err(|e| (500, e.to_string()))?;
+        Ok(Response::from_string(contents).boxed())
+    }
+
+    fn publish(
+        &self,
+        request: &mut Request,
+        public_url: &str,
+        name: &str,
+        version: &str,
+    ) -> Result<ResponseBox, (u16, String)> {
+        if let Some(token) = &self.token {
+            let expected = format!("Bearer {}", token);
+
+            if header(request, "Authorization") != Some(expected.as_str()) {
+                return Err((401, "Missing or invalid token".to_string()));
+            }
+        }
+
+        semver::Version::parse(version)
+            .map_err(|e| (400, format!("Invalid version `{}`: {}", version, e)))?;
+
+        let mut archive = Vec::new();
+        request
+            .as_reader()
+            .read_to_end(&mut archive)
+            .map_err(|e| (400, format!("Failed to read the package: {}", e)))?;
+
+        let checksum = hex::encode(Sha256::digest(&archive));
+        if let Some(expected) = header(request, "X-Checksum-Sha256")
+            && !expected.eq_ignore_ascii_case(&checksum)
+        {
+            return Err((400, "The package doesn't match its checksum".to_string()));
+        }
+
+        let index_path = self.root.join("packages").join(format!("{}.toml", name));
+        let mut package = match fs::read_to_string(&index_path) {
+            Ok(contents) => toml::from_str::<IndexPackage>(&contents)
+                .map_err(|e| (500, format!("Invalid index file: {}", e)))?,
+            Err(_) => IndexPackage {
+                name: name.to_string(),
+                description: None,
+                license: None,
+                repository: None,
+                publishers: BTreeMap::new(),
+                versions: Vec::new(),
+            },
+        };
+
+        if package
+            .versions
+            .iter()
+            .any(|entry| entry.version == version)
+        {
+            return Err((409, format!("{} {} is already published", name, version)));
+        }
+
+        let manifest = read_manifest(&archive, name, version).map_err(|e| (400, e))?;
+        let details = &manifest.project_details;
+
+        if details.name != name || details.version.as_deref() != Some(version) {
+            return Err((
+                400,
+                format!("The package's manifest isn't for {} {}", name, version),
+            ));
+        }
+
+        let signature = self.check_publisher(request, &mut package, &checksum)?;
+
+        let mut deps = BTreeMap::new();
+        for (dependency, requirement) in &manifest.packages {
+            if requirement.path().is_some() {
+                return Err((
+                    400,
+                    format!("The path package `{}` wasn't normalized", dependency),
+                ));
+            }
+
+            deps.insert(dependency.clone(), requirement.version_req().to_string());
+        }
+
+        let file_name = format!("{}-{}.tar.gz", name, version);
+
+        write_atomically(&self.root.join("archives").join(&file_name), &archive)
+            .map_err(|e| (500, e.to_string()))?;
+
+        package.description = details.description.clone().or(package.description);
+        package.license = details.license.clone().or(package.license);
+        package.versions.push(IndexVersion {
+            version: version.to_string(),
+            git: None,
+            rev: None,
+            url: Some(format!("{}/archives/{}", public_url, file_name)),
+            checksum: Some(checksum),
+            deps,
+            signature,
+            yanked: false,
+        });
+
+        let index_contents = toml::to_string(&package).map_err(|e| (500, e.to_string()))?;
+        write_atomically(&index_path, index_contents.as_bytes())
+            .map_err(|e| (500, e.to_string()))?;
+
+        Ok(Response::from_string(format!("Published {} {}\n", name, version)).boxed())
+    }
+
+    /// Checks the `X-Signature` of a publish request against the `X-Public-Key` it was sent
+    /// with. The first key to sign a package becomes its publisher, after which every version
+    /// has to be signed by one of the package's publishers.
+    fn check_publisher(
+        &self,
+        request: &Request,
+        package: &mut IndexPackage,
+        checksum: &str,
+    ) -> Result<Option<String>, (u16, String)> {
+        let Some(signature) = header(request, "X-Signature") else {
+            return match package.publishers.is_empty() {
+                true => Ok(None),
+                false => Err((
+                    403,
+                    format!("{} only accepts signed versions", package.name),
+                )),
+            };
+        };
+
+        let public_key = header(request, "X-Public-Key")
+            .ok_or((400, "A signed package needs its X-Public-Key".to_string()))?;
+        let public_key = PublicKey::decode(public_key).map_err(|e| (400, e))?;
+
+        if package.publishers.is_empty() {
+            package
+                .publishers
+                .insert(public_key.id(), public_key.encode());
+        }
+
+        let allowed = package
+            .publishers
+            .get(&public_key.id())
+            .is_some_and(|key| *key == public_key.encode());
+        if !allowed {
+            return Err((
+                403,
+                format!("{} is not a publisher of {}", public_key.id(), package.name),
+            ));
+        }
+
+        verify_checksum(&package.publishers, checksum, signature).map_err(|e| (400, e))?;
+
+        Ok(Some(signature.to_string()))
+    }
+}
+
+fn header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header: &&Header| header.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Decodes `+` and `%XX` escapes in a query string value
+fn decode_query_value(value: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut chars = value.bytes();
+
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let escape = [chars.next(), chars.next()];
+                let decoded = match escape {
+                    [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                    _ => None,
+                };
+
+                bytes.push(decoded.unwrap_or(b'%'));
+            }
+            byte => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn is_safe_segment(segment: &str) -> bool {
+    segment != ".." && !segment.contains('\\')
+}
+
+/// Reads `{name}-{version}/.cpppm.toml` out of a packaged `.tar.gz`
+fn read_manifest(archive: &[u8], name: &str, version: &str) -> Result<ProjectConfig, String> {
+    let manifest_path = format!("{}-{}/.cpppm.toml", name, version);
+    let mut entries = tar::Archive::new(GzDecoder::new(archive));
+
+    for entry in entries.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path().map_err(|e| e.to_string())?;
+
+        if path.to_string_lossy() != manifest_path {
+            continue;
+        }
+
+        let mut contents = String::new();
+        entry
+            .read_to_string(&mut contents)
+            .map_err(|e| e.to_string())?;
+
+        return toml::from_str(&contents).map_err(|e| format!("Invalid manifest: {}", e));
+    }
+
+    Err(format!("The package has no {}", manifest_path))
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let partial = path.with_extension("partial");
+    fs::write(&partial, contents)?;
+    fs::rename(partial, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lockfile::Lockfile;
+    use crate::publish::publish_project;
+    use crate::registry::{HttpIndex, Registries, resolve_packages};
+    use crate::signing::SecretKey;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use tempfile::TempDir;
+
+    /// Serves a registry on an ephemeral port, returning its directory and URL
+    fn start(token: Option<&str>, public_url: Option<&str>) -> (TempDir, String) {
+        let root = tempfile::tempdir().unwrap();
+        let registry = RegistryServer::new(
+            root.path(),
+            token.map(str::to_string),
+            public_url.map(str::to_string),
+        )
+        .unwrap();
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+
+        std::thread::spawn(move || registry.run(server));
+
+        (root, url)
+    }
+
+    /// Writes a project named `geometry` that publishes `version` to `registry_url`
+    fn project(registry_url: &str, version: &str) -> TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.project_details.name = "geometry".to_string();
+        config.project_details.version = Some(version.to_string());
+        config
+            .registries
+            .insert("default".to_string(), registry_url.to_string());
+
+        fs::write(
+            root.path().join(".cpppm.toml"),
+            toml::to_string(&config).unwrap(),
+        )
+        .unwrap();
+        fs::create_dir_all(root.path().join("src")).unwrap();
+        fs::write(
+            root.path().join("src").join("lib.cpp"),
+            "int area() { return 1; }\n",
+        )
+        .unwrap();
+
+        root
+    }
+
+    fn publish(project: &TempDir, token: Option<&str>) -> Result<String, String> {
+        publish_signed(project, token, None)
+    }
+
+    fn publish_signed(
+        project: &TempDir,
+        token: Option<&str>,
+        signing_key: Option<&SecretKey>,
+    ) -> Result<String, String> {
+        publish_project(
+            project.path(),
+            None,
+            token.map(str::to_string),
+            true,
+            signing_key,
+        )
+        .map(|package| package.checksum)
+        .map_err(|e| e.to_string())
+    }
+
+    /// A `.tar.gz` holding only `{prefix}/.cpppm.toml`
+    fn archive(prefix: &str, manifest: &ProjectConfig) -> Vec<u8> {
+        let contents = toml::to_string(manifest).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        builder
+            .append_data(
+                &mut header,
+                format!("{}/.cpppm.toml", prefix),
+                contents.as_bytes(),
+            )
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn put(url: &str, archive: &[u8], checksum: &str) -> Result<(), (u16, String)> {
+        match ureq::put(url)
+            .set("X-Checksum-Sha256", checksum)
+            .send_bytes(archive)
+        {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, response)) => {
+                Err((status, response.into_string().unwrap()))
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn published_packages_resolve_through_the_http_index() {
+        let (_root, url) = start(Some("secret"), None);
+        let project = project(&url, "1.2.0");
+
+        let checksum = publish(&project, Some("secret")).unwrap();
+
+        let index = HttpIndex { url: url.clone() };
+        let package = index.package("geometry").unwrap().unwrap();
+        assert_eq!(package.versions.len(), 1);
+        assert_eq!(
+            package.versions[0].checksum.as_deref(),
+            Some(checksum.as_str())
+        );
+        assert_eq!(
+            package.versions[0].url.as_deref(),
+            Some(format!("{}/archives/geometry-1.2.0.tar.gz", url).as_str())
+        );
+
+        let registries = Registries::open(&BTreeMap::from([("default".to_string(), url)])).unwrap();
+        let requirements = toml::from_str(r#"geometry = "^1.0""#).unwrap();
+        let resolved = resolve_packages(&requirements, &registries, &Lockfile::default()).unwrap();
+        assert_eq!(resolved[0].version.to_string(), "1.2.0");
+
+        let archive = ureq::get(package.versions[0].url.as_deref().unwrap())
+            .call()
+            .unwrap();
+        assert_eq!(archive.status(), 200);
+    }
+
+    #[test]
+    fn archive_urls_use_the_configured_public_url() {
+        let (_root, url) = start(None, Some("https://registry.example.com/"));
+        let project = project(&url, "1.0.0");
+
+        publish(&project, None).unwrap();
+
+        let package = HttpIndex { url }.package("geometry").unwrap().unwrap();
+        assert_eq!(
+            package.versions[0].url.as_deref(),
+            Some("https://registry.example.com/archives/geometry-1.0.0.tar.gz")
+        );
+    }
+
+    #[test]
+    fn publishing_requires_the_token() {
+        let (root, url) = start(Some("secret"), None);
+        let project = project(&url, "1.0.0");
+
+        let missing = publish(&project, None).unwrap_err();
+        assert!(missing.contains("(401)"), "{}", missing);
+
+        let wrong = publish(&project, Some("guess")).unwrap_err();
+        assert!(wrong.contains("(401)"), "{}", wrong);
+
+        assert!(!root.path().join("packages").join("geometry.toml").exists());
+    }
+
+    #[test]
+    fn publishing_a_version_twice_conflicts() {
+        let (_root, url) = start(None, None);
+        let project = project(&url, "1.0.0");
+
+        publish(&project, None).unwrap();
+        let error = publish(&project, None).unwrap_err();
+
+        assert!(error.contains("(409)"), "{}", error);
+        assert!(
+            error.contains("geometry 1.0.0 is already published"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn publishing_rejects_mismatched_checksums_and_manifests() {
+        let (root, url) = start(None, None);
+        let mut manifest = ProjectConfig::default();
+        manifest.project_details.name = "geometry".to_string();
+        manifest.project_details.version = Some("1.0.0".to_string());
+        let package = archive("geometry-1.0.0", &manifest);
+        let checksum = hex::encode(Sha256::digest(&package));
+        let publish_url =
+            |name: &str, version: &str| HttpIndex { url: url.clone() }.publish_url(name, version);
+
+        let (status, message) =
+            put(&publish_url("geometry", "1.0.0"), &package, &"0".repeat(64)).unwrap_err();
+        assert_eq!(status, 400);
+        assert!(
+            message.contains("doesn't match its checksum"),
+            "{}",
+            message
+        );
+
+        let (status, message) =
+            put(&publish_url("geometry", "2.0.0"), &package, &checksum).unwrap_err();
+        assert_eq!(status, 400);
+        assert!(
+            message.contains("has no geometry-2.0.0/.cpppm.toml"),
+            "{}",
+            message
+        );
+
+        let renamed = archive("shapes-1.0.0", &manifest);
+        let (status, message) = put(
+            &publish_url("shapes", "1.0.0"),
+            &renamed,
+            &hex::encode(Sha256::digest(&renamed)),
+        )
+        .unwrap_err();
+        assert_eq!(status, 400);
+        assert!(message.contains("isn't for shapes 1.0.0"), "{}", message);
+
+        assert!(
+            fs::read_dir(root.path().join("archives"))
+                .unwrap()
+                .next()
+                .is_none()
+        );
+
+        put(&publish_url("geometry", "1.0.0"), &package, &checksum).unwrap();
+    }
+
+    #[test]
+    fn only_the_first_signing_key_publishes_later_versions() {
+        let (_root, url) = start(None, None);
+        let publisher = SecretKey::generate().unwrap();
+        let other = SecretKey::generate().unwrap();
+
+        publish_signed(&project(&url, "1.0.0"), None, Some(&publisher)).unwrap();
+
+        let unsigned = publish(&project(&url, "1.1.0"), None).unwrap_err();
+        assert!(unsigned.contains("(403)"), "{}", unsigned);
+        assert!(
+            unsigned.contains("only accepts signed versions"),
+            "{}",
+            unsigned
+        );
+
+        let foreign = publish_signed(&project(&url, "1.1.0"), None, Some(&other)).unwrap_err();
+        assert!(foreign.contains("(403)"), "{}", foreign);
+        assert!(
+            foreign.contains("is not a publisher of geometry"),
+            "{}",
+            foreign
+        );
+
+        publish_signed(&project(&url, "1.1.0"), None, Some(&publisher)).unwrap();
+
+        let package = HttpIndex { url }.package("geometry").unwrap().unwrap();
+        assert_eq!(
+            package.publishers.keys().collect::<Vec<_>>(),
+            [&publisher.public_key().id()]
+        );
+        assert!(
+            package
+                .versions
+                .iter()
+                .all(|version| version.signature.is_some())
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Prefixes every encoded signature, like minisign's `Ed` algorithm tag
+const SIGNATURE_ALGORITHM: &[u8; 2] = b"Ed";
+
+/// The environment variable pointing to the secret key `--sign` uses by default
+pub const KEY_VARIABLE: &str = "C3PM_SIGNING_KEY";
+
+/// The default location of the secret key, `{config_dir}/signing.key`
+pub fn default_key_path() -> PathBuf {
+    match std::env::var_os(KEY_VARIABLE) {
+        Some(path) => PathBuf::from(path),
+        None => util::get_config_dir().join("signing.key"),
+    }
+}
+
+/// An ed25519 key pair, stored minisign-style as an untrusted comment line followed by the
+/// base64 encoded key
+pub struct SecretKey {
+    signing_key: SigningKey,
+}
+
+impl SecretKey {
+    pub fn generate() -> Result<Self, Box<dyn Error>> {
+        let mut seed = [0u8; 32];
+        getrandom::getrandom(&mut seed).map_err(|e| e.to_string())?;
+
+        Ok(SecretKey {
+            signing_key: SigningKey::from_bytes(&seed),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read the secret key {}: {}", path.display(), e))?;
+        let seed: [u8; 32] = decode_key_file(&contents)?
+            .try_into()
+            .map_err(|_| format!("{} is not a c3pm secret key", path.display()))?;
+
+        Ok(SecretKey {
+            signing_key: SigningKey::from_bytes(&seed),
+        })
+    }
+
+    /// Writes the secret key to `path` and the public key next to it, as `{path}.pub`
+    pub fn save(&self, path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let public_key = self.public_key();
+        let public_path = PathBuf::from(format!("{}.pub", path.display()));
+
+        fs::write(
+            path,
+            format!(
+                "untrusted comment: c3pm secret key {}\n{}\n",
+                public_key.id(),
+                STANDARD.encode(self.signing_key.to_bytes())
+            ),
+        )?;
+        restrict_permissions(path)?;
+
+        fs::write(
+            &public_path,
+            format!(
+                "untrusted comment: c3pm public key {}\n{}\n",
+                public_key.id(),
+                public_key.encode()
+            ),
+        )?;
+
+        Ok(public_path)
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey {
+            key: self.signing_key.verifying_key(),
+        }
+    }
+
+    /// Signs a package by its hex encoded SHA-256, so signatures can be checked against the
+    /// index before the archive is downloaded
+    pub fn sign_checksum(&self, checksum: &str) -> Result<String, Box<dyn Error>> {
+        let digest = hex::decode(checksum)?;
+        let signature = self.signing_key.sign(&digest);
+
+        let mut encoded = SIGNATURE_ALGORITHM.to_vec();
+        encoded.extend_from_slice(&self.public_key().id_bytes());
+        encoded.extend_from_slice(&signature.to_bytes());
+
+        Ok(STANDARD.encode(encoded))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    key: VerifyingKey,
+}
+
+impl PublicKey {
+    pub fn decode(encoded: &str) -> Result<Self, String> {
+        let bytes: [u8; 32] = STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| format!("Invalid public key: {}", e))?
+            .try_into()
+            .map_err(|_| "Invalid public key length".to_string())?;
+
+        let key = VerifyingKey::from_bytes(&bytes).map_err(|e| e.to_string())?;
+        Ok(PublicKey { key })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let bytes = decode_key_file(&contents)?;
+
+        Ok(PublicKey::decode(&STANDARD.encode(bytes))?)
+    }
+
+    pub fn encode(&self) -> String {
+        STANDARD.encode(self.key.to_bytes())
+    }
+
+    /// The first 8 bytes of the key's SHA-256, which signatures carry to name their key
+    fn id_bytes(&self) -> [u8; 8] {
+        let digest = Sha256::digest(self.key.to_bytes());
+        digest[..8].try_into().unwrap_or_default()
+    }
+
+    /// The hex encoded key id, which identifies a publisher in the index
+    pub fn id(&self) -> String {
+        hex::encode(self.id_bytes())
+    }
+}
+
+/// Checks `signature` against `checksum` using the matching key in `publishers` (which maps key
+/// ids to public keys), returning the id of the key that signed it
+pub fn verify_checksum(
+    publishers: &BTreeMap<String, String>,
+    checksum: &str,
+    signature: &str,
+) -> Result<String, String> {
+    let decoded = STANDARD
+        .decode(signature.trim())
+        .map_err(|e| format!("Invalid signature: {}", e))?;
+
+    if decoded.len() != 2 + 8 + 64 || &decoded[..2] != SIGNATURE_ALGORITHM {
+        return Err("Invalid signature".to_string());
+    }
+
+    let key_id = hex::encode(&decoded[2..10]);
+    let public_key = publishers
+        .get(&key_id)
+        .ok_or_else(|| format!("Signed by {}, which is not a known publisher", key_id))?;
+    let public_key = PublicKey::decode(public_key)?;
+
+    let signature_bytes: [u8; 64] = decoded[10..]
+        .try_into()
+        .map_err(|_| "Invalid signature length".to_string())?;
+    let digest = hex::decode(checksum).map_err(|e| format!("Invalid checksum: {}", e))?;
+
+    public_key
+        .key
+        .verify(&digest, &Signature::from_bytes(&signature_bytes))
+        .map_err(|_| format!("The signature by {} doesn't match", key_id))?;
+
+    Ok(key_id)
+}
+
+/// Reads the key line of a key file, skipping the untrusted comment
+fn decode_key_file(contents: &str) -> Result<Vec<u8>, String> {
+    let key_line = contents
+        .lines()
+        .find(|line| !line.trim().is_empty() && !line.starts_with("untrusted comment:"))
+        .ok_or("Empty key file")?;
+
+    STANDARD
+        .decode(key_line.trim())
+        .map_err(|e| format!("Invalid key file: {}", e))
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECKSUM: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    fn publishers(keys: &[&PublicKey]) -> BTreeMap<String, String> {
+        keys.iter().map(|key| (key.id(), key.encode())).collect()
+    }
+
+    #[test]
+    fn signatures_verify_against_the_signing_key() {
+        let key = SecretKey::generate().unwrap();
+        let other = SecretKey::generate().unwrap();
+        let signature = key.sign_checksum(CHECKSUM).unwrap();
+
+        let key_id = verify_checksum(
+            &publishers(&[&other.public_key(), &key.public_key()]),
+            CHECKSUM,
+            &signature,
+        )
+        .unwrap();
+
+        assert_eq!(key_id, key.public_key().id());
+    }
+
+    #[test]
+    fn signatures_fail_for_other_checksums_and_unknown_keys() {
+        let key = SecretKey::generate().unwrap();
+        let other = SecretKey::generate().unwrap();
+        let signature = key.sign_checksum(CHECKSUM).unwrap();
+        let tampered = CHECKSUM.replace("9f86", "0000");
+
+        assert_eq!(
+            verify_checksum(&publishers(&[&key.public_key()]), &tampered, &signature).unwrap_err(),
+            format!("The signature by {} doesn't match", key.public_key().id())
+        );
+        assert_eq!(
+            verify_checksum(&publishers(&[&other.public_key()]), CHECKSUM, &signature).unwrap_err(),
+            format!(
+                "Signed by {}, which is not a known publisher",
+                key.public_key().id()
+            )
+        );
+        assert_eq!(
+            verify_checksum(&publishers(&[&key.public_key()]), CHECKSUM, "RWQ=").unwrap_err(),
+            "Invalid signature"
+        );
+    }
+
+    #[test]
+    fn keys_survive_a_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("keys").join("signing.key");
+        let key = SecretKey::generate().unwrap();
+
+        let public_path = key.save(&key_path).unwrap();
+        let loaded = SecretKey::load(&key_path).unwrap();
+
+        assert_eq!(public_path, dir.path().join("keys").join("signing.key.pub"));
+        assert_eq!(loaded.public_key(), key.public_key());
+        assert_eq!(PublicKey::load(&public_path).unwrap(), key.public_key());
+
+        let signature = loaded.sign_checksum(CHECKSUM).unwrap();
+        assert!(verify_checksum(&publishers(&[&key.public_key()]), CHECKSUM, &signature).is_ok());
+    }
+}
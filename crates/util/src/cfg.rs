@@ -0,0 +1,360 @@
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+/// A `cfg(...)` expression used to key platform- and compiler-conditional tables
+///
+/// Supports `key = "value"` predicates for `os`, `family`, `arch` and `compiler`, the `unix`
+/// and `windows` shorthands, and the `all(...)`, `any(...)` and `not(...)` combinators.
+/// A comma-separated list directly inside `cfg(...)` must match completely, like `all(...)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CfgExpr {
+    Equals(String, String),
+    All(Vec<CfgExpr>),
+    Any(Vec<CfgExpr>),
+    Not(Box<CfgExpr>),
+}
+
+/// The host and toolchain `cfg(...)` expressions are evaluated against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    pub os: String,
+    pub family: String,
+    pub arch: String,
+    pub compiler: String,
+}
+
+const CFG_KEYS: [&str; 4] = ["os", "family", "arch", "compiler"];
+
+impl Platform {
+    /// Describes the current host, using `compiler` (e.g. `clang++`, `gcc`, `cl`) as the toolchain
+    pub fn detect(compiler: &str) -> Platform {
+        Platform {
+            os: std::env::consts::OS.to_string(),
+            family: std::env::consts::FAMILY.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            compiler: compiler_family(compiler),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            "os" => Some(&self.os),
+            "family" => Some(&self.family),
+            "arch" => Some(&self.arch),
+            "compiler" => Some(&self.compiler),
+            _ => None,
+        }
+    }
+}
+
+/// Generic compiler drivers, which are whichever compiler the system installed under that name
+const GENERIC_DRIVERS: [&str; 2] = ["cc", "c++"];
+
+/// Maps a compiler executable to the name used in `cfg(compiler = "...")`. Versioned names like
+/// `g++-13` map like their unversioned ones, and generic drivers like `c++` are told apart by
+/// their `--version` banner.
+pub fn compiler_family(compiler: &str) -> String {
+    let file_name = compiler.rsplit(['/', '\\']).next().unwrap_or(compiler);
+    let file_name = file_name.trim_end_matches(".exe");
+    let name = match file_name.rsplit_once('-') {
+        Some((name, version)) if version.chars().all(|c| c.is_ascii_digit() || c == '.') => name,
+        _ => file_name,
+    };
+
+    if name.contains("clang") {
+        "clang".to_string()
+    } else if name.contains("gcc") || name.contains("g++") {
+        "gcc".to_string()
+    } else if name == "cl" {
+        "msvc".to_string()
+    } else if GENERIC_DRIVERS.contains(&name) {
+        probe_compiler_family(compiler).unwrap_or_else(|| name.to_string())
+    } else {
+        name.to_string()
+    }
+}
+
+fn probe_compiler_family(compiler: &str) -> Option<String> {
+    let output = std::process::Command::new(compiler)
+        .arg("--version")
+        .output()
+        .ok()?;
+    let banner = String::from_utf8_lossy(&output.stdout).to_lowercase();
+
+    if banner.contains("clang") {
+        Some("clang".to_string())
+    } else if banner.contains("free software foundation") || banner.contains("gcc") {
+        Some("gcc".to_string())
+    } else {
+        None
+    }
+}
+
+impl CfgExpr {
+    pub fn matches(&self, platform: &Platform) -> bool {
+        match self {
+            CfgExpr::Equals(key, value) => platform.get(key) == Some(value.as_str()),
+            CfgExpr::All(exprs) => exprs.iter().all(|e| e.matches(platform)),
+            CfgExpr::Any(exprs) => exprs.iter().any(|e| e.matches(platform)),
+            CfgExpr::Not(expr) => !expr.matches(platform),
+        }
+    }
+}
+
+impl FromStr for CfgExpr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.trim().chars().peekable();
+
+        if parse_ident(&mut chars) != "cfg" {
+            return Err(format!("Expected `cfg(...)`, found `{}`", s));
+        }
+
+        let exprs = parse_list(&mut chars).map_err(|e| format!("Invalid `{}`: {}", s, e))?;
+
+        skip_whitespace(&mut chars);
+        if chars.peek().is_some() {
+            return Err(format!("Invalid `{}`: unexpected trailing input", s));
+        }
+
+        match exprs.len() {
+            1 => Ok(exprs.into_iter().next().unwrap()),
+            _ => Ok(CfgExpr::All(exprs)),
+        }
+    }
+}
+
+impl Display for CfgExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let join = |exprs: &Vec<CfgExpr>| {
+            exprs
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        match self {
+            CfgExpr::Equals(key, value) => write!(f, "{} = \"{}\"", key, value),
+            CfgExpr::All(exprs) => write!(f, "all({})", join(exprs)),
+            CfgExpr::Any(exprs) => write!(f, "any({})", join(exprs)),
+            CfgExpr::Not(expr) => write!(f, "not({})", expr),
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), String> {
+    skip_whitespace(chars);
+
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("expected `{}`, found `{}`", expected, c)),
+        None => Err(format!("expected `{}`, found end of input", expected)),
+    }
+}
+
+fn parse_ident(chars: &mut Peekable<Chars>) -> String {
+    skip_whitespace(chars);
+
+    let mut ident = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+        ident.push(c);
+    }
+
+    ident
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    expect(chars, '"')?;
+
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(value),
+            Some(c) => value.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+/// Parses `( expr, expr, ... )`
+fn parse_list(chars: &mut Peekable<Chars>) -> Result<Vec<CfgExpr>, String> {
+    expect(chars, '(')?;
+
+    let mut exprs = Vec::new();
+    loop {
+        skip_whitespace(chars);
+
+        if chars.next_if_eq(&')').is_some() {
+            return Ok(exprs);
+        }
+
+        exprs.push(parse_expr(chars)?);
+
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => continue,
+            Some(')') => return Ok(exprs),
+            Some(c) => return Err(format!("expected `,` or `)`, found `{}`", c)),
+            None => return Err("expected `)`, found end of input".to_string()),
+        }
+    }
+}
+
+fn parse_expr(chars: &mut Peekable<Chars>) -> Result<CfgExpr, String> {
+    let ident = parse_ident(chars);
+    skip_whitespace(chars);
+
+    match (ident.as_str(), chars.peek()) {
+        ("all", Some('(')) => Ok(CfgExpr::All(parse_list(chars)?)),
+        ("any", Some('(')) => Ok(CfgExpr::Any(parse_list(chars)?)),
+        ("not", Some('(')) => {
+            let mut exprs = parse_list(chars)?;
+
+            match exprs.len() {
+                1 => Ok(CfgExpr::Not(Box::new(exprs.remove(0)))),
+                _ => Err("`not(...)` takes exactly one predicate".to_string()),
+            }
+        }
+        ("unix" | "windows", _) => Ok(CfgExpr::Equals("family".to_string(), ident)),
+        (key, Some('=')) if CFG_KEYS.contains(&key) => {
+            chars.next();
+            Ok(CfgExpr::Equals(ident, parse_string(chars)?))
+        }
+        ("", _) => Err("expected a predicate".to_string()),
+        (key, _) => Err(format!(
+            "unknown predicate `{}`, possible keys are: {}",
+            key,
+            CFG_KEYS.join(", ")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn platform(os: &str, family: &str, arch: &str, compiler: &str) -> Platform {
+        Platform {
+            os: os.to_string(),
+            family: family.to_string(),
+            arch: arch.to_string(),
+            compiler: compiler.to_string(),
+        }
+    }
+
+    fn cfg(expr: &str) -> CfgExpr {
+        CfgExpr::from_str(expr).unwrap()
+    }
+
+    #[test]
+    fn from_str_parses_predicates_and_combinators() {
+        assert_eq!(
+            cfg(r#"cfg(os = "linux")"#),
+            CfgExpr::Equals("os".to_string(), "linux".to_string())
+        );
+        assert_eq!(
+            cfg("cfg(unix)"),
+            CfgExpr::Equals("family".to_string(), "unix".to_string())
+        );
+        assert_eq!(
+            cfg(r#" cfg( any( windows , all(os="macos", arch = "aarch64") ) ) "#).to_string(),
+            r#"any(family = "windows", all(os = "macos", arch = "aarch64"))"#
+        );
+        assert_eq!(
+            cfg(r#"cfg(unix, not(compiler = "clang"))"#).to_string(),
+            r#"all(family = "unix", not(compiler = "clang"))"#
+        );
+    }
+
+    #[test]
+    fn from_str_rejects_invalid_expressions() {
+        let errors = [
+            (r#"os = "linux""#, "Expected `cfg(...)`, found `os = \"linux\"`"),
+            (
+                r#"cfg(os = "linux"#,
+                "Invalid `cfg(os = \"linux`: unterminated string",
+            ),
+            (
+                r#"cfg(vendor = "apple")"#,
+                "Invalid `cfg(vendor = \"apple\")`: unknown predicate `vendor`, possible keys are: os, family, arch, compiler",
+            ),
+            (
+                "cfg(not(unix, windows))",
+                "Invalid `cfg(not(unix, windows))`: `not(...)` takes exactly one predicate",
+            ),
+            (
+                "cfg(unix) extra",
+                "Invalid `cfg(unix) extra`: unexpected trailing input",
+            ),
+            ("cfg(,)", "Invalid `cfg(,)`: expected a predicate"),
+        ];
+
+        for (expr, error) in errors {
+            assert_eq!(CfgExpr::from_str(expr).unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    fn matches_evaluates_against_the_platform() {
+        let linux_gcc = platform("linux", "unix", "x86_64", "gcc");
+        let windows_msvc = platform("windows", "windows", "x86_64", "msvc");
+
+        let expr = cfg(r#"cfg(all(unix, not(compiler = "clang")))"#);
+        assert!(expr.matches(&linux_gcc));
+        assert!(!expr.matches(&windows_msvc));
+
+        let expr = cfg(r#"cfg(any(os = "windows", arch = "aarch64"))"#);
+        assert!(!expr.matches(&linux_gcc));
+        assert!(expr.matches(&windows_msvc));
+
+        // An empty list is `all()`, which always matches
+        assert!(cfg("cfg()").matches(&linux_gcc));
+    }
+
+    #[test]
+    fn compiler_family_maps_executables() {
+        assert_eq!(compiler_family("/usr/bin/clang++-17"), "clang");
+        assert_eq!(compiler_family("x86_64-linux-gnu-g++"), "gcc");
+        assert_eq!(compiler_family(r"C:\VS\bin\cl.exe"), "msvc");
+        assert_eq!(compiler_family("icpx"), "icpx");
+        assert_eq!(compiler_family("g++-13"), "gcc");
+        assert_eq!(compiler_family("/usr/bin/gcc-12.2"), "gcc");
+        assert_eq!(compiler_family("x86_64-linux-gnu-g++-13"), "gcc");
+        assert_eq!(compiler_family("clang-cl"), "clang");
+        assert_eq!(compiler_family("icpx-2024.1"), "icpx");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn compiler_family_probes_generic_drivers() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let bin = tempfile::tempdir().unwrap();
+        let driver = |name: &str, banner: &str| {
+            let path = bin.path().join(name);
+            std::fs::write(&path, format!("#!/bin/sh\necho '{}'\n", banner)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path.display().to_string()
+        };
+
+        let clang = driver("c++", "Ubuntu clang version 17.0.6");
+        let gcc = driver(
+            "cc",
+            "cc (Debian 12.2.0-14) 12.2.0\nCopyright (C) 2022 Free Software Foundation, Inc.",
+        );
+
+        assert_eq!(compiler_family(&clang), "clang");
+        assert_eq!(compiler_family(&gcc), "gcc");
+        assert_eq!(
+            compiler_family(&bin.path().join("missing").join("c++").display().to_string()),
+            "c++"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use crate::cfg::Platform;
+use crate::model::{Generator, Profile, ProjectConfig, ProjectOverrides, DEFAULT_PROFILE};
+use crate::{get_current_path, load_project_config, select_compilers};
+use std::path::{Path, PathBuf};
+
+/// Overrides `build` in `[dirs]`
+pub const BUILD_DIR_VARIABLE: &str = "C3PM_BUILD_DIR";
+
+/// Selects the build profile when `--profile` isn't given
+pub const PROFILE_VARIABLE: &str = "C3PM_PROFILE";
+
+/// The C and C++ compilers, as CMake itself reads them
+pub const C_COMPILER_VARIABLE: &str = "CC";
+pub const CXX_COMPILER_VARIABLE: &str = "CXX";
+
+/// A project as every command sees it: the command-line overrides take precedence over the
+/// environment, which takes precedence over `.cpppm.toml`, which takes precedence over defaults
+/// and detection
+#[derive(Debug, Clone)]
+pub struct ProjectContext {
+    pub root: PathBuf,
+    pub config: ProjectConfig,
+    pub profile: Profile,
+    /// The absolute directory holding the build directories of every profile
+    pub build_root: PathBuf,
+    /// The absolute build directory of the selected profile, so switching between profiles
+    /// doesn't rebuild everything
+    pub build_dir: PathBuf,
+    /// The generator to configure with, CMake picks its default when there is none
+    pub generator: Option<Generator>,
+    pub c_compiler: String,
+    pub cxx_compiler: String,
+}
+
+impl ProjectContext {
+    pub fn resolve(
+        root: &Path,
+        generator: Option<Generator>,
+        overrides: &ProjectOverrides,
+    ) -> Result<ProjectContext, String> {
+        ProjectContext::resolve_with_env(root, generator, overrides, |name| {
+            std::env::var(name).ok()
+        })
+    }
+
+    fn resolve_with_env(
+        root: &Path,
+        generator: Option<Generator>,
+        overrides: &ProjectOverrides,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<ProjectContext, String> {
+        let env = |name: &str| env(name).filter(|value| !value.is_empty());
+        let config = load_project_config(&root.join(".cpppm.toml"))?;
+        let profile = overrides
+            .profile
+            .clone()
+            .or_else(|| env(PROFILE_VARIABLE))
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+        let profile = config.resolve_profile(&profile)?;
+
+        let build_dir = overrides
+            .build_dir
+            .clone()
+            .or_else(|| env(BUILD_DIR_VARIABLE))
+            .or_else(|| config.get_build_dir())
+            .unwrap_or_else(|| "build".to_string());
+
+        let compiler = &config.project_details.compiler;
+        let c_compiler = overrides
+            .c_compiler
+            .clone()
+            .or_else(|| env(C_COMPILER_VARIABLE))
+            .or_else(|| compiler.c_compiler.clone())
+            .filter(|c_compiler| !c_compiler.is_empty());
+        let cxx_compiler = overrides
+            .cxx_compiler
+            .clone()
+            .or_else(|| env(CXX_COMPILER_VARIABLE))
+            .or_else(|| compiler.cxx_compiler.clone())
+            .filter(|cxx_compiler| !cxx_compiler.is_empty());
+
+        // Only probe for compilers when one of them isn't configured anywhere
+        let (c_compiler, cxx_compiler) = match (c_compiler, cxx_compiler) {
+            (Some(c_compiler), Some(cxx_compiler)) => (c_compiler, cxx_compiler),
+            (c_compiler, cxx_compiler) => {
+                let detected = select_compilers();
+
+                (
+                    c_compiler.unwrap_or(detected.0),
+                    cxx_compiler.unwrap_or(detected.1),
+                )
+            }
+        };
+
+        let build_root = root.join(build_dir.trim_end_matches('/'));
+
+        Ok(ProjectContext {
+            root: root.to_path_buf(),
+            build_dir: build_root.join(&profile.name),
+            build_root,
+            profile,
+            generator: generator.or(config.project_details.generator),
+            config,
+            c_compiler,
+            cxx_compiler,
+        })
+    }
+
+    /// Resolves the project in the current directory
+    pub fn current(
+        generator: Option<Generator>,
+        overrides: &ProjectOverrides,
+    ) -> Result<ProjectContext, String> {
+        let current_dir = get_current_path().map_err(|e| e.to_string())?;
+
+        ProjectContext::resolve(Path::new(&current_dir), generator, overrides)
+    }
+
+    /// The directory the artifacts of the selected profile are staged into
+    pub fn target_dir(&self) -> PathBuf {
+        self.root.join("target").join(&self.profile.name)
+    }
+
+    /// The host platform, using the resolved C++ compiler as toolchain
+    pub fn platform(&self) -> Platform {
+        Platform::detect(&self.cxx_compiler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::CompilerDetails;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Writes a project that sets everything in `.cpppm.toml` when `configured`, and nothing
+    /// otherwise
+    fn project(configured: bool) -> TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.project_details.compiler = CompilerDetails {
+            c_compiler: None,
+            cxx_compiler: None,
+        };
+        config.dirs.remove("build");
+
+        if configured {
+            config.project_details.generator = Some(Generator::Ninja);
+            config.project_details.compiler = CompilerDetails {
+                c_compiler: Some("config-cc".to_string()),
+                cxx_compiler: Some("config-c++".to_string()),
+            };
+            config.dirs.insert("build".to_string(), "out/".to_string());
+        }
+
+        let mut contents = toml::to_string(&config).unwrap();
+        contents.push_str("\n[profile.ci]\ninherits = \"release\"\n");
+        fs::write(root.path().join(".cpppm.toml"), contents).unwrap();
+
+        root
+    }
+
+    fn resolve(
+        root: &TempDir,
+        overrides: &ProjectOverrides,
+        env: &[(&str, &str)],
+    ) -> ProjectContext {
+        let env = env
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<BTreeMap<String, String>>();
+
+        ProjectContext::resolve_with_env(root.path(), None, overrides, |name| {
+            env.get(name).cloned()
+        })
+        .unwrap()
+    }
+
+    fn cli_overrides() -> ProjectOverrides {
+        ProjectOverrides {
+            build_dir: Some("cli-build".to_string()),
+            c_compiler: Some("cli-cc".to_string()),
+            cxx_compiler: Some("cli-c++".to_string()),
+            profile: Some("debug".to_string()),
+        }
+    }
+
+    const ENVIRONMENT: &[(&str, &str)] = &[
+        (BUILD_DIR_VARIABLE, "env-build"),
+        (C_COMPILER_VARIABLE, "env-cc"),
+        (CXX_COMPILER_VARIABLE, "env-c++"),
+        (PROFILE_VARIABLE, "ci"),
+    ];
+
+    #[test]
+    fn command_line_overrides_take_precedence() {
+        let root = project(true);
+
+        let context = resolve(&root, &cli_overrides(), ENVIRONMENT);
+
+        assert_eq!(context.build_root, root.path().join("cli-build"));
+        assert_eq!(
+            context.build_dir,
+            root.path().join("cli-build").join("debug")
+        );
+        assert_eq!(context.c_compiler, "cli-cc");
+        assert_eq!(context.cxx_compiler, "cli-c++");
+        assert_eq!(context.profile.name, "debug");
+    }
+
+    #[test]
+    fn environment_overrides_the_config() {
+        let root = project(true);
+
+        let context = resolve(&root, &ProjectOverrides::default(), ENVIRONMENT);
+
+        assert_eq!(context.build_dir, root.path().join("env-build").join("ci"));
+        assert_eq!(context.c_compiler, "env-cc");
+        assert_eq!(context.cxx_compiler, "env-c++");
+        assert_eq!(context.profile.name, "ci");
+    }
+
+    #[test]
+    fn config_overrides_the_defaults() {
+        let root = project(true);
+
+        // Empty variables count as unset
+        let context = resolve(
+            &root,
+            &ProjectOverrides::default(),
+            &[(C_COMPILER_VARIABLE, "")],
+        );
+
+        assert_eq!(context.build_root, root.path().join("out"));
+        assert_eq!(
+            context.build_dir,
+            root.path().join("out").join(DEFAULT_PROFILE)
+        );
+        assert_eq!(context.c_compiler, "config-cc");
+        assert_eq!(context.cxx_compiler, "config-c++");
+        assert!(matches!(context.generator, Some(Generator::Ninja)));
+        assert_eq!(
+            context.target_dir(),
+            root.path().join("target").join(DEFAULT_PROFILE)
+        );
+    }
+
+    #[test]
+    fn defaults_apply_without_any_configuration() {
+        let root = project(false);
+
+        let context = resolve(&root, &ProjectOverrides::default(), &[]);
+        let (c_compiler, cxx_compiler) = select_compilers();
+
+        assert_eq!(
+            context.build_dir,
+            root.path().join("build").join(DEFAULT_PROFILE)
+        );
+        assert_eq!(context.profile.name, DEFAULT_PROFILE);
+        assert!(context.generator.is_none());
+        assert_eq!(context.c_compiler, c_compiler);
+        assert_eq!(context.cxx_compiler, cxx_compiler);
+    }
+}
@@ -0,0 +1,393 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+/// The name c3pm's queries are filed under, so they don't clash with an IDE's
+const CLIENT_NAME: &str = "client-c3pm";
+
+const CODEMODEL_QUERY: &str = r#"{ "requests": [ { "kind": "codemodel", "version": 2 } ] }"#;
+
+/// The kind of a CMake target that produces artifacts, read in the File API's
+/// `SCREAMING_SNAKE_CASE` and in the kebab-case `artifacts.json` is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "SCREAMING_SNAKE_CASE", serialize = "kebab-case"))]
+pub enum TargetKind {
+    #[serde(alias = "executable")]
+    Executable,
+    #[serde(alias = "static-library")]
+    StaticLibrary,
+    #[serde(alias = "shared-library")]
+    SharedLibrary,
+    #[serde(alias = "module-library")]
+    ModuleLibrary,
+    #[serde(alias = "object-library")]
+    ObjectLibrary,
+    #[serde(alias = "interface-library")]
+    InterfaceLibrary,
+    #[serde(alias = "utility")]
+    Utility,
+}
+
+/// A file a target produces for one configuration
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    pub target: String,
+    pub kind: TargetKind,
+    /// The absolute path of the file in the build directory
+    pub path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct ReplyIndex {
+    reply: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ClientReply {
+    #[serde(rename = "query.json")]
+    query: QueryReply,
+}
+
+#[derive(Deserialize)]
+struct QueryReply {
+    #[serde(default)]
+    responses: Vec<ReplyObject>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReplyObject {
+    kind: String,
+    json_file: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Codemodel {
+    paths: CodemodelPaths,
+    configurations: Vec<Configuration>,
+}
+
+#[derive(Deserialize)]
+struct CodemodelPaths {
+    build: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Configuration {
+    name: String,
+    targets: Vec<TargetReference>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TargetReference {
+    json_file: String,
+    project_index: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Target {
+    name: String,
+    #[serde(rename = "type")]
+    kind: TargetKind,
+    #[serde(default)]
+    artifacts: Vec<TargetArtifact>,
+    #[serde(default)]
+    is_generator_provided: bool,
+}
+
+#[derive(Deserialize)]
+struct TargetArtifact {
+    path: PathBuf,
+}
+
+fn api_dir(build_dir: &Path) -> PathBuf {
+    build_dir.join(".cmake").join("api").join("v1")
+}
+
+/// Asks CMake to write a codemodel reply into the build directory the next time it configures it
+pub fn write_codemodel_query(build_dir: &Path) -> Result<(), Error> {
+    let query_dir = api_dir(build_dir).join("query").join(CLIENT_NAME);
+    fs::create_dir_all(&query_dir)?;
+
+    fs::write(query_dir.join("query.json"), CODEMODEL_QUERY)
+}
+
+/// Lists the artifacts of `config` from the codemodel reply CMake wrote when it last configured
+/// `build_dir`. Only the project's own targets are included, along with the shared libraries of
+/// its dependencies, which its executables need at runtime.
+pub fn find_artifacts(build_dir: &Path, config: &str) -> Result<Vec<Artifact>, Error> {
+    let reply_dir = api_dir(build_dir).join("reply");
+    let codemodel: Codemodel = read_reply(&reply_dir, &find_codemodel_file(&reply_dir)?)?;
+
+    let configuration = match codemodel
+        .configurations
+        .iter()
+        .find(|configuration| configuration.name.eq_ignore_ascii_case(config))
+    {
+        Some(configuration) => configuration,
+        // Single-config generators only know the build type the project was configured with
+        None if codemodel.configurations.len() == 1 => &codemodel.configurations[0],
+        None => {
+            let formatted_possible_values = codemodel
+                .configurations
+                .iter()
+                .map(|configuration| configuration.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ");
+
+            return Err(Error::other(format!(
+                "The build directory has no `{}` configuration! Possible values are: {}",
+                config, formatted_possible_values
+            )));
+        }
+    };
+
+    let mut artifacts: Vec<Artifact> = Vec::new();
+
+    for reference in &configuration.targets {
+        let target: Target = read_reply(&reply_dir, &reference.json_file)?;
+
+        // The top-level project always comes first, dependencies add their own projects
+        let is_dependency = reference.project_index != 0;
+        let is_runtime_dependency = matches!(
+            target.kind,
+            TargetKind::SharedLibrary | TargetKind::ModuleLibrary
+        );
+
+        if target.is_generator_provided || (is_dependency && !is_runtime_dependency) {
+            continue;
+        }
+
+        for artifact in target.artifacts {
+            artifacts.push(Artifact {
+                target: target.name.clone(),
+                kind: target.kind,
+                path: codemodel.paths.build.join(artifact.path),
+            });
+        }
+    }
+
+    Ok(artifacts)
+}
+
+/// Finds the codemodel answering c3pm's query in the newest reply index
+fn find_codemodel_file(reply_dir: &Path) -> Result<String, Error> {
+    let missing_reply = || {
+        Error::other(format!(
+            "No CMake File API reply found in {}, reconfigure the project",
+            reply_dir.display()
+        ))
+    };
+
+    // Index files are named after the time they were written, so the newest sorts last
+    let index_file = fs::read_dir(reply_dir)
+        .map_err(|_| missing_reply())?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with("index-") && name.ends_with(".json"))
+        .max()
+        .ok_or_else(missing_reply)?;
+
+    let index: ReplyIndex = read_reply(reply_dir, &index_file)?;
+    let client = index.reply.get(CLIENT_NAME).ok_or_else(missing_reply)?;
+    let client: ClientReply = serde_json::from_value(client.clone()).map_err(Error::other)?;
+
+    client
+        .query
+        .responses
+        .into_iter()
+        .find(|response| response.kind == "codemodel")
+        .and_then(|response| response.json_file)
+        .ok_or_else(missing_reply)
+}
+
+fn read_reply<T: for<'de> Deserialize<'de>>(reply_dir: &Path, file: &str) -> Result<T, Error> {
+    let contents = fs::read_to_string(reply_dir.join(file))?;
+
+    serde_json::from_str(&contents)
+        .map_err(|e| Error::other(format!("Invalid CMake File API reply {}: {}", file, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::StagingMode;
+    use crate::staging::{stage_artifacts, ArtifactManifest};
+    use serde_json::json;
+
+    fn write_json(path: &Path, value: serde_json::Value) {
+        fs::write(path, value.to_string()).unwrap();
+    }
+
+    fn target(
+        reply_dir: &Path,
+        config: &str,
+        name: &str,
+        kind: &str,
+        artifact: Option<&str>,
+        project_index: usize,
+    ) -> serde_json::Value {
+        let json_file = format!("target-{}-{}.json", name, config);
+        let artifacts = artifact
+            .map(|artifact| json!([{ "path": format!("{}/{}", config, artifact) }]))
+            .unwrap_or(json!([]));
+
+        write_json(
+            &reply_dir.join(&json_file),
+            json!({
+                "name": name,
+                "type": kind,
+                "artifacts": artifacts,
+                "isGeneratorProvided": kind == "UTILITY",
+            }),
+        );
+
+        json!({ "name": name, "jsonFile": json_file, "projectIndex": project_index })
+    }
+
+    /// Writes the reply of a multi-config generator with `Debug` and `Release` configurations
+    fn write_reply(build_dir: &Path) {
+        let reply_dir = api_dir(build_dir).join("reply");
+        fs::create_dir_all(&reply_dir).unwrap();
+
+        let configurations = ["Debug", "Release"]
+            .iter()
+            .map(|config| {
+                json!({
+                    "name": config,
+                    "targets": [
+                        target(&reply_dir, config, "app", "EXECUTABLE", Some("app"), 0),
+                        target(&reply_dir, config, "core", "STATIC_LIBRARY", Some("libcore.a"), 0),
+                        target(&reply_dir, config, "ALL_BUILD", "UTILITY", None, 0),
+                        target(&reply_dir, config, "fmt", "STATIC_LIBRARY", Some("libfmt.a"), 1),
+                        target(&reply_dir, config, "z", "SHARED_LIBRARY", Some("libz.so"), 1),
+                    ],
+                })
+            })
+            .collect::<Vec<_>>();
+
+        write_json(
+            &reply_dir.join("codemodel-v2-0123.json"),
+            json!({
+                "paths": { "build": build_dir, "source": build_dir.parent().unwrap() },
+                "configurations": configurations,
+            }),
+        );
+
+        // An older reply, from before the project was reconfigured
+        write_json(
+            &reply_dir.join("index-2024-01-01T00-00-00-0000.json"),
+            json!({ "reply": {} }),
+        );
+        write_json(
+            &reply_dir.join("index-2024-06-01T00-00-00-0000.json"),
+            json!({
+                "reply": {
+                    CLIENT_NAME: {
+                        "query.json": {
+                            "responses": [
+                                {
+                                    "kind": "codemodel",
+                                    "version": { "major": 2, "minor": 6 },
+                                    "jsonFile": "codemodel-v2-0123.json",
+                                }
+                            ]
+                        }
+                    }
+                }
+            }),
+        );
+    }
+
+    fn summary(artifacts: &[Artifact], build_dir: &Path) -> Vec<(String, TargetKind, PathBuf)> {
+        artifacts
+            .iter()
+            .map(|artifact| {
+                (
+                    artifact.target.clone(),
+                    artifact.kind,
+                    artifact.path.strip_prefix(build_dir).unwrap().to_path_buf(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_artifacts_of_the_requested_configuration() {
+        let build_dir = tempfile::tempdir().unwrap();
+        write_reply(build_dir.path());
+
+        let release = find_artifacts(build_dir.path(), "release").unwrap();
+
+        assert_eq!(
+            summary(&release, build_dir.path()),
+            [
+                (
+                    "app".to_string(),
+                    TargetKind::Executable,
+                    PathBuf::from("Release/app")
+                ),
+                (
+                    "core".to_string(),
+                    TargetKind::StaticLibrary,
+                    PathBuf::from("Release/libcore.a")
+                ),
+                (
+                    "z".to_string(),
+                    TargetKind::SharedLibrary,
+                    PathBuf::from("Release/libz.so")
+                ),
+            ]
+        );
+
+        let debug = find_artifacts(build_dir.path(), "Debug").unwrap();
+        assert_eq!(debug[0].path, build_dir.path().join("Debug").join("app"));
+
+        let error = find_artifacts(build_dir.path(), "MinSizeRel").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Possible values are: Debug, Release"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn finds_staged_executables() {
+        let build_dir = tempfile::tempdir().unwrap();
+        let target_dir = tempfile::tempdir().unwrap();
+        write_reply(build_dir.path());
+
+        let artifacts = find_artifacts(build_dir.path(), "Debug").unwrap();
+        for artifact in &artifacts {
+            fs::create_dir_all(artifact.path.parent().unwrap()).unwrap();
+            fs::write(&artifact.path, &artifact.target).unwrap();
+        }
+        stage_artifacts(&artifacts, target_dir.path(), StagingMode::Copy).unwrap();
+
+        let manifest = ArtifactManifest::load(target_dir.path()).unwrap();
+        assert_eq!(
+            manifest.find_executable("app").unwrap().path,
+            PathBuf::from("app")
+        );
+        assert!(manifest.find_executable("core").is_none());
+        assert!(manifest.find_executable("missing").is_none());
+    }
+
+    #[test]
+    fn reports_a_missing_reply() {
+        let build_dir = tempfile::tempdir().unwrap();
+
+        let error = find_artifacts(build_dir.path(), "Debug").unwrap_err();
+
+        assert!(
+            error.to_string().contains("reconfigure the project"),
+            "{}",
+            error
+        );
+    }
+}
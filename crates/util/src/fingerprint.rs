@@ -0,0 +1,248 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+use std::process::Command;
+use walkdir::WalkDir;
+
+/// Where the fingerprint of the last successful configure is kept, inside the build directory
+const FINGERPRINT_PATH: &str = "c3pm/configure.fingerprint";
+
+/// Directories whose CMake files never affect how the project is configured
+const IGNORED_DIRECTORIES: &[&str] = &[".git", "deps", "target"];
+
+/// Hashes everything the CMake configure step depends on: the `cmake` arguments (generator,
+/// compilers and definitions), `.cpppm.toml`, `cpppm.lock`, the generated `c3pm.cmake`, and every
+/// `CMakeLists.txt` and `.cmake` file of the project. Changes to the fetched sources in `deps/`
+/// are covered by the lockfile.
+pub fn configure_fingerprint(
+    project_root: &Path,
+    build_dir: &Path,
+    command: &Command,
+) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+
+    for argument in command.get_args() {
+        hash_entry(&mut hasher, "arg", argument.as_encoded_bytes());
+    }
+
+    for file_name in [".cpppm.toml", "cpppm.lock"] {
+        hash_file(&mut hasher, &project_root.join(file_name))?;
+    }
+
+    hash_file(&mut hasher, &build_dir.join("c3pm").join("c3pm.cmake"))?;
+
+    let entries = WalkDir::new(project_root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            // Build directories are full of generated CMake files, whatever they're called
+            let is_ignored = entry.file_type().is_dir()
+                && (entry.path() == build_dir
+                    || entry.path().join("CMakeCache.txt").exists()
+                    || IGNORED_DIRECTORIES
+                        .iter()
+                        .any(|name| entry.file_name() == *name));
+
+            entry.depth() == 0 || !is_ignored
+        });
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let file_name = entry.file_name().to_string_lossy();
+
+        if entry.file_type().is_file()
+            && (file_name == "CMakeLists.txt" || file_name.ends_with(".cmake"))
+        {
+            hash_file(&mut hasher, entry.path())?;
+        }
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Whether `build_dir` was last configured successfully with the inputs hashed to `fingerprint`
+pub fn is_up_to_date(build_dir: &Path, fingerprint: &str) -> bool {
+    build_dir.join("CMakeCache.txt").is_file()
+        && fs::read_to_string(build_dir.join(FINGERPRINT_PATH))
+            .is_ok_and(|saved| saved.trim() == fingerprint)
+}
+
+pub fn save_fingerprint(build_dir: &Path, fingerprint: &str) -> Result<(), Error> {
+    let fingerprint_path = build_dir.join(FINGERPRINT_PATH);
+
+    if let Some(parent) = fingerprint_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(fingerprint_path, format!("{}\n", fingerprint))
+}
+
+/// Forgets the last configure, so the next build configures the project again
+pub fn clear_fingerprint(build_dir: &Path) -> Result<(), Error> {
+    match fs::remove_file(build_dir.join(FINGERPRINT_PATH)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn hash_file(hasher: &mut Sha256, path: &Path) -> Result<(), Error> {
+    hash_entry(hasher, "file", path.as_os_str().as_encoded_bytes());
+
+    match fs::read(path) {
+        Ok(contents) => hash_entry(hasher, "contents", &contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => hash_entry(hasher, "missing", b""),
+        Err(e) => return Err(e),
+    }
+
+    Ok(())
+}
+
+/// Hashes a length-prefixed entry, so neighbouring entries can't run into each other
+fn hash_entry(hasher: &mut Sha256, label: &str, value: &[u8]) {
+    hasher.update(label.as_bytes());
+    hasher.update((value.len() as u64).to_le_bytes());
+    hasher.update(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn project() -> TempDir {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(
+            root.path().join(".cpppm.toml"),
+            "[project-details]\nname = \"app\"\n",
+        )
+        .unwrap();
+        fs::write(root.path().join("cpppm.lock"), "version = 1\n").unwrap();
+        fs::write(root.path().join("CMakeLists.txt"), "project(app)\n").unwrap();
+        fs::create_dir_all(root.path().join("src")).unwrap();
+        fs::write(root.path().join("src").join("main.cpp"), "int main() {}\n").unwrap();
+        fs::create_dir_all(root.path().join("build").join("c3pm")).unwrap();
+        fs::write(
+            root.path().join("build").join("c3pm").join("c3pm.cmake"),
+            "# debug\n",
+        )
+        .unwrap();
+
+        root
+    }
+
+    fn command(compiler: &str, build_type: &str) -> Command {
+        let mut command = Command::new("cmake");
+        command
+            .arg(format!("-DCMAKE_BUILD_TYPE={}", build_type))
+            .arg(format!("-DCMAKE_CXX_COMPILER={}", compiler));
+
+        command
+    }
+
+    fn fingerprint(root: &TempDir, command: &Command) -> String {
+        configure_fingerprint(root.path(), &root.path().join("build"), command).unwrap()
+    }
+
+    #[test]
+    fn fingerprint_stays_equal_for_unrelated_changes() {
+        let root = project();
+        let debug = command("g++", "Debug");
+        let original = fingerprint(&root, &debug);
+
+        assert_eq!(fingerprint(&root, &debug), original);
+
+        fs::write(
+            root.path().join("src").join("main.cpp"),
+            "int main() { return 1; }\n",
+        )
+        .unwrap();
+        fs::write(root.path().join("build").join("CMakeCache.txt"), "").unwrap();
+        fs::write(root.path().join("build").join("generated.cmake"), "").unwrap();
+        fs::create_dir_all(root.path().join("deps").join("fmt")).unwrap();
+        fs::write(
+            root.path().join("deps").join("fmt").join("CMakeLists.txt"),
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(fingerprint(&root, &debug), original);
+    }
+
+    #[test]
+    fn fingerprint_changes_with_the_configure_inputs() {
+        let root = project();
+        let debug = command("g++", "Debug");
+        let original = fingerprint(&root, &debug);
+
+        let changes: [(&str, &dyn Fn()); 4] = [
+            ("manifest", &|| {
+                fs::write(
+                    root.path().join(".cpppm.toml"),
+                    "[project-details]\nname = \"other\"\n",
+                )
+                .unwrap()
+            }),
+            ("lockfile", &|| {
+                fs::remove_file(root.path().join("cpppm.lock")).unwrap()
+            }),
+            ("CMakeLists.txt", &|| {
+                fs::write(root.path().join("CMakeLists.txt"), "project(app CXX)\n").unwrap()
+            }),
+            ("cmake module", &|| {
+                fs::create_dir_all(root.path().join("cmake")).unwrap();
+                fs::write(root.path().join("cmake").join("warnings.cmake"), "").unwrap()
+            }),
+        ];
+
+        let mut seen = vec![original];
+        for (input, change) in changes {
+            change();
+
+            let changed = fingerprint(&root, &debug);
+            assert!(
+                !seen.contains(&changed),
+                "changing the {} kept the fingerprint",
+                input
+            );
+            seen.push(changed);
+        }
+    }
+
+    #[test]
+    fn fingerprint_changes_with_the_toolchain_and_profile() {
+        let root = project();
+        let original = fingerprint(&root, &command("g++", "Debug"));
+
+        assert_ne!(fingerprint(&root, &command("clang++", "Debug")), original);
+        assert_ne!(fingerprint(&root, &command("g++", "Release")), original);
+
+        // Profile flags end up in the generated c3pm.cmake
+        fs::write(
+            root.path().join("build").join("c3pm").join("c3pm.cmake"),
+            "# -O3\n",
+        )
+        .unwrap();
+        assert_ne!(fingerprint(&root, &command("g++", "Debug")), original);
+    }
+
+    #[test]
+    fn saved_fingerprints_are_up_to_date_until_cleared() {
+        let root = project();
+        let build_dir = root.path().join("build");
+        let fingerprint = fingerprint(&root, &command("g++", "Debug"));
+
+        save_fingerprint(&build_dir, &fingerprint).unwrap();
+        assert!(
+            !is_up_to_date(&build_dir, &fingerprint),
+            "no CMakeCache.txt yet"
+        );
+
+        fs::write(build_dir.join("CMakeCache.txt"), "").unwrap();
+        assert!(is_up_to_date(&build_dir, &fingerprint));
+        assert!(!is_up_to_date(&build_dir, "other"));
+
+        clear_fingerprint(&build_dir).unwrap();
+        clear_fingerprint(&build_dir).unwrap();
+        assert!(!is_up_to_date(&build_dir, &fingerprint));
+    }
+}
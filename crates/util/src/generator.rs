@@ -1,12 +1,26 @@
-use crate::get_cmake_version;
+use crate::cfg::Platform;
+use crate::context::ProjectContext;
+use crate::file_api::write_codemodel_query;
+use crate::fingerprint::{self, configure_fingerprint};
 use crate::model::ProjectConfig;
-use crate::model::{Generator, Language};
+use crate::model::{
+    Dependency, FeatureSelection, Generator, Language, LibConfig, LibKind, ProjectOverrides,
+    ProjectTarget, ProjectTargetKind, ResolvedFeatures, TargetConfig, TestFramework,
+};
+use crate::{collect_dependencies, collect_dev_dependencies, get_cmake_version};
 use git2::Repository;
 use indoc::{formatdoc, indoc};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
 use std::io::Error;
+use std::path::Path;
 use std::process::{exit, ExitStatus};
 use std::{fmt::Display, fs, fs::File, io::Write, process::Command};
 
+/// Where `cpppm export-cmake` writes the standalone CMake file, which the generated CMakeLists
+/// includes when building without c3pm
+pub const EXPORTED_CMAKE_PATH: &str = "cmake/c3pm-dependencies.cmake";
+
 pub const EXAMPLE_C_PROGRAM: &'static str = indoc! {r#"
 #include <stdio.h>
 #include "example.h"
@@ -72,10 +86,12 @@ pub fn generate_project(
     project_name: String,
     generator: Option<Generator>,
     lang: Language,
+    test_framework: Option<TestFramework>,
+    overrides: &ProjectOverrides,
 ) -> Result<(), Error> {
     let src_path = format!("{}/src", path);
     let include_path = format!("{}/include", path);
-    let dependencies_path = format!("{}/dependencies", path);
+    let dependencies_path = format!("{}/deps", path);
     fs::create_dir_all(&src_path)?;
     fs::create_dir_all(&include_path)?;
     fs::create_dir_all(&dependencies_path)?;
@@ -176,19 +192,38 @@ pub fn generate_project(
         }
     }
 
-    let config = match generator {
-        Some(generator) => ProjectConfig::create_new_config(
-            &project_name,
-            Some(generator),
-            lang,
-            "src",
-            "include",
-            "build",
-        ),
-        None => {
-            ProjectConfig::create_new_config(&project_name, None, lang, "src", "include", "build")
+    let build_dir = overrides.build_dir.as_deref().unwrap_or("build");
+    let mut config = ProjectConfig::create_new_config(
+        &project_name,
+        generator,
+        lang,
+        "src",
+        "include",
+        build_dir,
+    );
+
+    // Record the compilers picked on the command line, so every later command uses them too
+    let compiler = &mut config.project_details.compiler;
+    if overrides.c_compiler.is_some() {
+        compiler.c_compiler = overrides.c_compiler.clone();
+    }
+    if overrides.cxx_compiler.is_some() {
+        compiler.cxx_compiler = overrides.cxx_compiler.clone();
+    }
+
+    if let Some(framework) = test_framework {
+        init_tests(&path, framework, lang)?;
+
+        // The tests link against the library to reach the project's code
+        config.lib = Some(LibConfig::default());
+
+        if let Some(dependency) = framework.dev_dependency() {
+            config
+                .dev_dependencies
+                .get_or_insert_with(Vec::new)
+                .push(dependency);
         }
-    };
+    }
 
     let config_path = format!("{}/.cpppm.toml", path);
     let mut config_file = File::create(config_path)?;
@@ -198,7 +233,9 @@ pub fn generate_project(
         eprintln!("Failed to initialize git repository: {}", e);
     }
 
-    let cmake_status = configure_cmake_project(&path, generator);
+    let cmake_status = ProjectContext::resolve(Path::new(&path), generator, overrides)
+        .map_err(Error::other)
+        .and_then(|context| configure_cmake_project(&context, &FeatureSelection::default()));
 
     if let Err(e) = cmake_status {
         eprintln!("Failed to configure CMake project: {}", e);
@@ -257,44 +294,660 @@ pub fn init_c_project(
     Ok(())
 }
 
-pub fn configure_cmake_project(
-    path: &String,
-    generator: Option<Generator>,
-) -> Result<ExitStatus, Error> {
-    let c_compiler = if Command::new("clang").output()?.status.success() {
-        "clang"
-    } else {
-        "gcc"
+/// Writes an example test calling the example implementation into `tests/`, which c3pm builds and
+/// registers with CTest
+pub fn init_tests(path: &str, framework: TestFramework, lang: Language) -> Result<(), Error> {
+    let tests_path = format!("{}/tests", path);
+    fs::create_dir_all(&tests_path)?;
+
+    let include = match lang.is_c() {
+        // The frameworks are C++, while the example implementation is compiled as C
+        true if framework != TestFramework::Ctest => "extern \"C\" {\n#include \"example.h\"\n}",
+        true => "#include \"example.h\"",
+        false => "#include \"example.hpp\"",
     };
 
-    let cxx_compiler = if Command::new("clang++").output()?.status.success() {
-        "clang++"
-    } else {
-        "g++"
+    let test = match framework {
+        TestFramework::Gtest => formatdoc! {r#"
+            #include <gtest/gtest.h>
+            {include}
+
+            TEST(Example, Runs) {{
+                example_function();
+                SUCCEED();
+            }}
+        "#},
+        TestFramework::Catch2 => formatdoc! {r#"
+            #include <catch2/catch_test_macros.hpp>
+            {include}
+
+            TEST_CASE("example_function runs") {{
+                example_function();
+                SUCCEED();
+            }}
+        "#},
+        TestFramework::Doctest => formatdoc! {r#"
+            #define DOCTEST_CONFIG_IMPLEMENT_WITH_MAIN
+            #include <doctest/doctest.h>
+            {include}
+
+            TEST_CASE("example_function runs") {{
+                example_function();
+                CHECK(true);
+            }}
+        "#},
+        TestFramework::Ctest => formatdoc! {r#"
+            {include}
+
+            int main() {{
+                example_function();
+                return 0;
+            }}
+        "#},
     };
 
-    let cmake_status = if generator.is_some() {
-        Command::new("cmake")
-            .arg("-S")
-            .arg(&path)
-            .arg("-B")
-            .arg(format!("{}/build", path))
-            .arg("-G")
-            .arg(generator.unwrap().to_string())
-            .arg(format!("-DCMAKE_C_COMPILER={}", c_compiler))
-            .arg(format!("-DCMAKE_CXX_COMPILER={}", cxx_compiler))
-            .status()
-    } else {
-        Command::new("cmake")
-            .arg("-S")
-            .arg(&path)
-            .arg("-B")
-            .arg(format!("{}/build", path))
-            .arg(format!("-DCMAKE_C_COMPILER={}", c_compiler))
-            .arg(format!("-DCMAKE_CXX_COMPILER={}", cxx_compiler))
-            .status()
+    let extension = match lang.is_c() && framework == TestFramework::Ctest {
+        true => "c",
+        false => "cpp",
     };
-    cmake_status
+
+    let mut test_file = File::create(format!("{}/example_test.{}", tests_path, extension))?;
+    test_file.write_all(test.as_bytes())
+}
+
+/// Configures the project's build directory with CMake, unless it was already configured with
+/// the same inputs (see [`configure_fingerprint`])
+pub fn configure_cmake_project(
+    context: &ProjectContext,
+    features: &FeatureSelection,
+) -> Result<ExitStatus, Error> {
+    let mut command = Command::new("cmake");
+    command
+        .arg("-S")
+        .arg(&context.root)
+        .arg("-B")
+        .arg(&context.build_dir);
+
+    // Lets `cpppm build` find the artifacts of each target once it's built
+    write_codemodel_query(&context.build_dir)?;
+
+    if let Some(generator) = context.generator {
+        command.arg("-G").arg(generator.to_string());
+    }
+
+    command
+        .arg(format!("-DCMAKE_BUILD_TYPE={}", context.profile.build_type))
+        .arg(format!("-DCMAKE_C_COMPILER={}", context.c_compiler))
+        .arg(format!("-DCMAKE_CXX_COMPILER={}", context.cxx_compiler));
+
+    let platform = context.platform();
+    let (config, mut target) = context
+        .config
+        .for_platform(&platform)
+        .map_err(Error::other)?;
+    let features = config.resolve_features(features).map_err(Error::other)?;
+    context.profile.apply_to(&mut target, &platform.compiler);
+
+    let c3pm_cmake_path = write_c3pm_cmake(
+        &context.root,
+        &context.build_dir,
+        &config,
+        &target,
+        &features,
+        &platform,
+    )?;
+
+    command.arg(format!("-DC3PM_CMAKE_FILE={}", c3pm_cmake_path.display()));
+
+    let fingerprint = configure_fingerprint(&context.root, &context.build_dir, &command)?;
+
+    if fingerprint::is_up_to_date(&context.build_dir, &fingerprint) {
+        return Ok(ExitStatus::default());
+    }
+
+    fingerprint::clear_fingerprint(&context.build_dir)?;
+
+    // CMake's progress goes to stderr, keeping stdout for what `cpppm run` runs
+    let status = command.stdout(std::io::stderr()).status()?;
+
+    if status.success() {
+        fingerprint::save_fingerprint(&context.build_dir, &fingerprint)?;
+    }
+
+    Ok(status)
+}
+
+/// Writes the c3pm-managed CMake file that the project's CMakeLists includes
+pub fn write_c3pm_cmake(
+    project_root: &Path,
+    build_dir: &Path,
+    config: &ProjectConfig,
+    target: &TargetConfig,
+    features: &ResolvedFeatures,
+    platform: &Platform,
+) -> Result<std::path::PathBuf, Error> {
+    let c3pm_dir = build_dir.join("c3pm");
+    fs::create_dir_all(&c3pm_dir)?;
+
+    // Written by depman when the binary cache is enabled, maps dependency names to install prefixes
+    let prebuilt_path = c3pm_dir.join("prebuilt.toml");
+    let prebuilt: BTreeMap<String, String> = match prebuilt_path.exists() {
+        true => toml::from_str(&fs::read_to_string(&prebuilt_path)?).map_err(Error::other)?,
+        false => BTreeMap::new(),
+    };
+
+    let dependencies = collect_dependencies(project_root, config, features, platform);
+    let dev_dependencies = collect_dev_dependencies(project_root, config, features, platform);
+    let c3pm_cmake_path = c3pm_dir.join("c3pm.cmake");
+    let c3pm_cmake = get_c3pm_cmake(
+        config,
+        &config.get_targets(project_root).map_err(Error::other)?,
+        target,
+        features,
+        &prebuilt,
+        &dependencies,
+        &dev_dependencies,
+    );
+
+    // Rewriting an unchanged file would still make the next `cmake --build` reconfigure
+    if fs::read_to_string(&c3pm_cmake_path).ok().as_deref() != Some(c3pm_cmake.as_str()) {
+        fs::write(&c3pm_cmake_path, c3pm_cmake)?;
+    }
+
+    Ok(c3pm_cmake_path)
+}
+
+/// Generates the CMake code that defines the project's targets, then adds the fetched
+/// dependencies and links them into those targets
+pub fn get_c3pm_cmake(
+    config: &ProjectConfig,
+    targets: &[ProjectTarget],
+    target: &TargetConfig,
+    features: &ResolvedFeatures,
+    prebuilt: &BTreeMap<String, String>,
+    dependencies: &[Dependency],
+    dev_dependencies: &[Dependency],
+) -> String {
+    let mut cmake = formatdoc! {r#"
+        # Generated by c3pm, do not edit
+
+        # Let dependency options set below override `option()` and `set(... CACHE ...)` defaults
+        set(CMAKE_POLICY_DEFAULT_CMP0077 NEW)
+        set(CMAKE_POLICY_DEFAULT_CMP0126 NEW)
+    "#};
+
+    cmake.push_str(&get_target_definitions(config, targets));
+    cmake.push_str(&get_target_settings(targets, target, features));
+
+    for dependency in dependencies {
+        let data = &dependency.dependency;
+
+        cmake.push('\n');
+
+        let link_targets = match prebuilt.get(&data.name) {
+            _ if data.system => {
+                cmake.push_str(&get_system_dependency(dependency, true));
+                get_dependency_link_targets(dependency)
+            }
+            Some(prefix) => {
+                let package = data.package.as_deref().unwrap_or(&data.name);
+                // Installed packages only export namespaced targets, so there is no plain
+                // target named after the dependency to fall back to
+                let link_targets = match &data.targets {
+                    Some(targets) => targets.clone(),
+                    None => vec![format!("{}::{}", package, package)],
+                };
+
+                cmake.push_str(&formatdoc! {r#"
+                    # {name} (prebuilt)
+                    list(APPEND CMAKE_PREFIX_PATH "{prefix}")
+                    find_package({package} CONFIG REQUIRED)
+                "#,
+                    name = data.name,
+                    prefix = prefix.replace('\\', "/"),
+                });
+
+                for link_target in &link_targets {
+                    cmake.push_str(&formatdoc! {r#"
+                        if(NOT TARGET {link_target})
+                            message(FATAL_ERROR "The prebuilt {name} package doesn't export the `{link_target}` target, set `targets` for {name} in .cpppm.toml")
+                        endif()
+                    "#, name = data.name});
+                }
+
+                link_targets.join(" ")
+            }
+            None => {
+                cmake.push_str(&get_dependency_function(
+                    dependency,
+                    &get_add_subdirectory(dependency, false),
+                ));
+                let _ = writeln!(cmake, "c3pm_add_{}()", data.cmake_identifier());
+                get_dependency_link_targets(dependency)
+            }
+        };
+
+        cmake.push_str(&get_link_libraries(targets, &link_targets));
+    }
+
+    if dev_dependencies.is_empty() {
+        return cmake;
+    }
+
+    // Dev-dependencies are only added once they have been fetched for a test or bench workflow,
+    // and are only linked into the targets listed in `C3PM_TEST_TARGETS`
+    cmake.push_str("\nset(C3PM_DEV_DEPENDENCY_TARGETS)\n");
+
+    for dependency in dev_dependencies {
+        let data = &dependency.dependency;
+
+        cmake.push('\n');
+
+        // System dev-dependencies are optional, so tests can be skipped where they are missing
+        if data.system {
+            cmake.push_str(&get_system_dependency(dependency, false));
+            cmake.push_str(&formatdoc! {r#"
+                if({package}_FOUND)
+                    list(APPEND C3PM_DEV_DEPENDENCY_TARGETS {targets})
+                endif()
+            "#,
+                package = data.package.as_deref().unwrap_or(&data.name),
+                targets = get_dependency_link_targets(dependency),
+            });
+            continue;
+        }
+
+        cmake.push_str(&get_dependency_function(
+            dependency,
+            &get_add_subdirectory(dependency, true),
+        ));
+        cmake.push_str(&formatdoc! {r#"
+            if(EXISTS ${{CMAKE_SOURCE_DIR}}/deps/{name})
+                c3pm_add_{identifier}()
+                list(APPEND C3PM_DEV_DEPENDENCY_TARGETS {targets})
+            endif()
+        "#,
+            name = data.name,
+            identifier = data.cmake_identifier(),
+            targets = get_dependency_link_targets(dependency),
+        });
+    }
+
+    cmake.push_str(&formatdoc! {r#"
+
+        function(c3pm_link_dev_dependencies target)
+            target_link_libraries(${{target}} PRIVATE ${{C3PM_DEV_DEPENDENCY_TARGETS}})
+        endfunction()
+
+        foreach(test_target IN LISTS C3PM_TEST_TARGETS)
+            c3pm_link_dev_dependencies(${{test_target}})
+        endforeach()
+    "#});
+
+    cmake
+}
+
+/// Where `cpppm export-cmake` has FetchContent take a dependency's sources from
+pub enum FetchContentSource {
+    Git {
+        repository: String,
+        commit: String,
+    },
+    Archive {
+        url: String,
+        checksum: Option<String>,
+    },
+    /// A directory, relative to the project root unless it is absolute
+    Directory(String),
+}
+
+/// Generates a standalone replacement for the c3pm-managed CMake file, which fetches the
+/// dependencies with FetchContent from `sources` instead of adding the copies c3pm fetched into
+/// `deps/`
+pub fn get_exported_cmake(
+    config: &ProjectConfig,
+    targets: &[ProjectTarget],
+    target: &TargetConfig,
+    features: &ResolvedFeatures,
+    dependencies: &[Dependency],
+    sources: &BTreeMap<String, FetchContentSource>,
+) -> String {
+    let project_name = config.get_name();
+    let mut cmake = formatdoc! {r#"
+        # Generated by `cpppm export-cmake`, do not edit
+        #
+        # Builds {project_name} without c3pm by fetching its dependencies with FetchContent, pinned
+        # to the sources recorded in cpppm.lock
+
+        include(FetchContent)
+
+        # Let dependency options set below override `option()` and `set(... CACHE ...)` defaults
+        set(CMAKE_POLICY_DEFAULT_CMP0077 NEW)
+        set(CMAKE_POLICY_DEFAULT_CMP0126 NEW)
+
+        # When {project_name} is itself a c3pm dependency, the consumer already added these
+        # dependencies from its deps/ directory, so they are only fetched without c3pm
+    "#};
+
+    cmake.push_str(&get_target_definitions(config, targets));
+    cmake.push_str(&get_target_settings(targets, target, features));
+
+    for dependency in dependencies {
+        let data = &dependency.dependency;
+
+        cmake.push('\n');
+
+        match sources.get(&data.name) {
+            _ if data.system => cmake.push_str(&get_system_dependency(dependency, true)),
+            Some(source) => {
+                let declaration = match source {
+                    FetchContentSource::Git { repository, commit } => {
+                        format!("GIT_REPOSITORY {}\n        GIT_TAG {}", repository, commit)
+                    }
+                    FetchContentSource::Archive { url, checksum } => match checksum {
+                        Some(checksum) => {
+                            format!("URL {}\n        URL_HASH SHA256={}", url, checksum)
+                        }
+                        None => format!("URL {}", url),
+                    },
+                    FetchContentSource::Directory(path) if Path::new(path).is_absolute() => {
+                        format!("SOURCE_DIR {}", path)
+                    }
+                    FetchContentSource::Directory(path) => {
+                        format!("SOURCE_DIR ${{PROJECT_SOURCE_DIR}}/{}", path)
+                    }
+                };
+
+                cmake.push_str(&get_dependency_function(
+                    dependency,
+                    &format!(
+                        "    FetchContent_Declare(\n        {name}\n        {declaration}\n    )\n    FetchContent_MakeAvailable({name})\n",
+                        name = data.name,
+                    ),
+                ));
+                let _ = writeln!(
+                    cmake,
+                    "if(NOT C3PM_CMAKE_FILE)\n    c3pm_add_{}()\nendif()",
+                    data.cmake_identifier()
+                );
+            }
+            None => continue,
+        }
+
+        cmake.push_str(&get_link_libraries(
+            targets,
+            &get_dependency_link_targets(dependency),
+        ));
+    }
+
+    cmake
+}
+
+/// Defines the project's targets. Each is skipped when the project's CMakeLists already defines
+/// it, as the ones generated before `[lib]` and `[[bin]]` existed do for the main binary.
+fn get_target_definitions(config: &ProjectConfig, targets: &[ProjectTarget]) -> String {
+    let headers_dir = config
+        .get_headers_dir()
+        .unwrap_or_else(|| "include".to_string());
+    let library = targets.iter().find(|target| !target.kind.is_executable());
+    let mut cmake = String::new();
+
+    if targets
+        .iter()
+        .any(|target| target.kind == ProjectTargetKind::Test)
+    {
+        cmake.push_str("\nenable_testing()\n");
+    }
+
+    for target in targets {
+        let name = &target.name;
+        let sources_var = format!("C3PM_{}_SOURCES", target.name);
+
+        cmake.push('\n');
+        let _ = writeln!(cmake, "if(NOT TARGET {})", name);
+
+        if !target.sources.is_empty() {
+            let globs = target
+                .sources
+                .iter()
+                .map(|glob| format!("\"${{PROJECT_SOURCE_DIR}}/{}\"", glob))
+                .collect::<Vec<String>>()
+                .join(" ");
+
+            let _ = writeln!(
+                cmake,
+                "    file(GLOB_RECURSE {} CONFIGURE_DEPENDS {})",
+                sources_var, globs
+            );
+
+            if let Some(exclude) = &target.exclude {
+                let _ = writeln!(
+                    cmake,
+                    "    list(FILTER {} EXCLUDE REGEX \"{}\")",
+                    sources_var, exclude
+                );
+            }
+        }
+
+        let include_scope = match target.kind {
+            ProjectTargetKind::Executable => {
+                let _ = writeln!(cmake, "    add_executable({} ${{{}}})", name, sources_var);
+                "PRIVATE"
+            }
+            // Examples, tests and benches are only built when asked for
+            ProjectTargetKind::Example | ProjectTargetKind::Test | ProjectTargetKind::Bench => {
+                let _ = writeln!(
+                    cmake,
+                    "    add_executable({} EXCLUDE_FROM_ALL ${{{}}})",
+                    name, sources_var
+                );
+                "PRIVATE"
+            }
+            ProjectTargetKind::Library(LibKind::HeaderOnly) => {
+                let _ = writeln!(cmake, "    add_library({} INTERFACE)", name);
+                "INTERFACE"
+            }
+            ProjectTargetKind::Library(kind) => {
+                let library_type = match kind {
+                    LibKind::Shared => "SHARED",
+                    LibKind::Object => "OBJECT",
+                    _ => "STATIC",
+                };
+
+                let _ = writeln!(
+                    cmake,
+                    "    add_library({} {} ${{{}}})",
+                    name, library_type, sources_var
+                );
+                "PUBLIC"
+            }
+        };
+
+        let _ = writeln!(
+            cmake,
+            "    target_include_directories({} {} ${{PROJECT_SOURCE_DIR}}/{})",
+            name, include_scope, headers_dir
+        );
+
+        if let Some(output_name) = &target.output_name {
+            let _ = writeln!(
+                cmake,
+                "    set_target_properties({} PROPERTIES OUTPUT_NAME {})",
+                name, output_name
+            );
+        }
+
+        // Shared libraries built with MSVC only export the symbols marked `__declspec(dllexport)`
+        if target.kind == ProjectTargetKind::Library(LibKind::Shared) {
+            let _ = writeln!(
+                cmake,
+                "    set_target_properties({} PROPERTIES WINDOWS_EXPORT_ALL_SYMBOLS ON)",
+                name
+            );
+        }
+
+        match library {
+            Some(library) if target.kind.is_executable() => {
+                let _ = writeln!(
+                    cmake,
+                    "    target_link_libraries({} PRIVATE {})",
+                    name, library.name
+                );
+            }
+            _ => {}
+        }
+
+        // Links the dev-dependencies into tests and benches
+        if matches!(
+            target.kind,
+            ProjectTargetKind::Test | ProjectTargetKind::Bench
+        ) {
+            let _ = writeln!(cmake, "    list(APPEND C3PM_TEST_TARGETS {})", name);
+        }
+
+        if target.kind == ProjectTargetKind::Test {
+            let _ = writeln!(cmake, "    add_test(NAME {} COMMAND {})", name, name);
+        }
+
+        cmake.push_str("endif()\n");
+    }
+
+    cmake
+}
+
+/// Links `libraries` into the project: into its library when it has one, which passes them on to
+/// the binaries, and into each binary otherwise
+fn get_link_libraries(targets: &[ProjectTarget], libraries: &str) -> String {
+    let has_library = targets.iter().any(|target| !target.kind.is_executable());
+    let mut cmake = String::new();
+
+    for target in targets {
+        let scope = match target.kind {
+            ProjectTargetKind::Library(LibKind::HeaderOnly) => "INTERFACE",
+            ProjectTargetKind::Library(_) => "PUBLIC",
+            _ if has_library => continue,
+            _ => "PRIVATE",
+        };
+
+        let _ = writeln!(
+            cmake,
+            "target_link_libraries({} {} {})",
+            target.name, scope, libraries
+        );
+    }
+
+    cmake
+}
+
+/// The compile definitions and flags of the selected features and target, which apply to every
+/// target of the project that compiles sources
+fn get_target_settings(
+    targets: &[ProjectTarget],
+    target: &TargetConfig,
+    features: &ResolvedFeatures,
+) -> String {
+    let mut cmake = String::new();
+    let compiled_targets = targets
+        .iter()
+        .filter(|target| target.kind != ProjectTargetKind::Library(LibKind::HeaderOnly))
+        .map(|target| target.name.as_str())
+        .collect::<Vec<&str>>();
+    let definitions = features
+        .definitions
+        .iter()
+        .chain(target.definitions.iter())
+        .cloned()
+        .collect::<Vec<String>>();
+
+    if !definitions.is_empty() || !target.compile_flags.is_empty() || !target.link_flags.is_empty()
+    {
+        cmake.push('\n');
+    }
+
+    for project_target in compiled_targets {
+        if !definitions.is_empty() {
+            let _ = writeln!(
+                cmake,
+                "target_compile_definitions({} PRIVATE {})",
+                project_target,
+                definitions.join(" ")
+            );
+        }
+
+        if !target.compile_flags.is_empty() {
+            let _ = writeln!(
+                cmake,
+                "target_compile_options({} PRIVATE {})",
+                project_target,
+                target.compile_flags.join(" ")
+            );
+        }
+
+        if !target.link_flags.is_empty() {
+            let _ = writeln!(
+                cmake,
+                "target_link_options({} PRIVATE {})",
+                project_target,
+                target.link_flags.join(" ")
+            );
+        }
+    }
+
+    cmake
+}
+
+fn get_add_subdirectory(dependency: &Dependency, exclude_from_all: bool) -> String {
+    format!(
+        "    add_subdirectory(${{CMAKE_SOURCE_DIR}}/deps/{name} ${{CMAKE_BINARY_DIR}}/deps/{name}{exclude})\n",
+        name = dependency.dependency.name,
+        exclude = if exclude_from_all { " EXCLUDE_FROM_ALL" } else { "" }
+    )
+}
+
+/// Wraps `add` in a function that first sets the dependency's options, so they only apply to it
+fn get_dependency_function(dependency: &Dependency, add: &str) -> String {
+    let data = &dependency.dependency;
+    let mut body = String::new();
+
+    // Variables set inside the function only exist while the dependency is being added
+    for (option, value) in &data.options {
+        let _ = writeln!(body, "    set({} {})", option, value);
+    }
+
+    if let Some(build_type) = data.build_type {
+        let _ = writeln!(body, "    set(CMAKE_BUILD_TYPE {})", build_type);
+    }
+
+    body.push_str(add);
+
+    formatdoc! {r#"
+        # {name}
+        function(c3pm_add_{identifier})
+        {body}endfunction()
+    "#, name = data.name, identifier = data.cmake_identifier()}
+}
+
+fn get_system_dependency(dependency: &Dependency, required: bool) -> String {
+    let data = &dependency.dependency;
+    let mut arguments = vec![data.package.as_deref().unwrap_or(&data.name)];
+
+    if let Some(version) = &data.version {
+        arguments.push(version);
+    }
+
+    arguments.push(if required { "REQUIRED" } else { "QUIET" });
+
+    formatdoc! {r#"
+        # {name} (system)
+        find_package({arguments})
+    "#, name = data.name, arguments = arguments.join(" ")}
+}
+
+fn get_dependency_link_targets(dependency: &Dependency) -> String {
+    match &dependency.dependency.targets {
+        Some(targets) => targets.join(" "),
+        None => format!("$<TARGET_NAME_IF_EXISTS:{}>", dependency.dependency.name),
+    }
 }
 
 fn get_c_cmakelists(project_name: &String, standard: Language) -> String {
@@ -343,14 +996,128 @@ fn get_common_cmakelists<Env: Display>(
         {env}
         set(CMAKE_EXPORT_COMPILE_COMMANDS ON)
 
-        file(GLOB_RECURSE {sources_var} CONFIGURE_DEPENDS "src/*.c" "src/*.cpp")
         file(GLOB_RECURSE {headers_var} CONFIGURE_DEPENDS "include/*.h" "include/*.hpp")
 
-        add_executable({project_name} ${{{sources_var}}})
-        target_include_directories({project_name} PUBLIC include)
-    "#}
+        # c3pm defines the targets of `[lib]` and `[[bin]]` and links their dependencies
+        if(C3PM_CMAKE_FILE AND CMAKE_SOURCE_DIR STREQUAL CMAKE_CURRENT_SOURCE_DIR)
+            include(${{C3PM_CMAKE_FILE}})
+        elseif(EXISTS ${{CMAKE_CURRENT_SOURCE_DIR}}/{exported_cmake_path})
+            include(${{CMAKE_CURRENT_SOURCE_DIR}}/{exported_cmake_path})
+        else()
+            file(GLOB_RECURSE {sources_var} CONFIGURE_DEPENDS "src/*.c" "src/*.cpp")
+
+            add_executable({project_name} ${{{sources_var}}})
+            target_include_directories({project_name} PUBLIC include)
+        endif()
+    "#, exported_cmake_path = EXPORTED_CMAKE_PATH}
 }
 
-// pub async fn get_latest_commit_on_remote() -> String {
-//     String::new()
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app_targets() -> Vec<ProjectTarget> {
+        vec![ProjectTarget {
+            name: "app".to_string(),
+            kind: ProjectTargetKind::Executable,
+            sources: vec!["src/*.cpp".to_string()],
+            exclude: None,
+            output_name: None,
+        }]
+    }
+
+    fn prebuilt_cmake(dependency: Dependency) -> String {
+        let config = ProjectConfig::default();
+        let targets = app_targets();
+        let prebuilt = BTreeMap::from([(
+            dependency.dependency.name.clone(),
+            "/cache/binary/0123".to_string(),
+        )]);
+
+        get_c3pm_cmake(
+            &config,
+            &targets,
+            &TargetConfig::default(),
+            &ResolvedFeatures::default(),
+            &prebuilt,
+            &[dependency],
+            &[],
+        )
+    }
+
+    #[test]
+    fn prebuilt_dependency_links_namespaced_target_by_default() {
+        let cmake = prebuilt_cmake(Dependency::new("fmt", "10.2.1", ("fmtlib", "fmt")));
+
+        assert!(cmake.contains("find_package(fmt CONFIG REQUIRED)"));
+        assert!(cmake.contains("if(NOT TARGET fmt::fmt)\n    message(FATAL_ERROR"));
+        assert!(cmake.contains("target_link_libraries(app PRIVATE fmt::fmt)"));
+        assert!(!cmake.contains("TARGET_NAME_IF_EXISTS"));
+    }
+
+    #[test]
+    fn prebuilt_dependency_links_configured_targets() {
+        let mut dependency = Dependency::new("spdlog", "v1.14.1", ("gabime", "spdlog"));
+        dependency.dependency.targets = Some(vec!["spdlog::spdlog_header_only".to_string()]);
+
+        let cmake = prebuilt_cmake(dependency);
+
+        assert!(cmake.contains("if(NOT TARGET spdlog::spdlog_header_only)"));
+        assert!(cmake.contains("target_link_libraries(app PRIVATE spdlog::spdlog_header_only)"));
+    }
+
+    #[test]
+    fn exported_directories_are_relative_to_the_project_unless_absolute() {
+        let dependencies = [
+            Dependency::new("geometry", "", ("", "")),
+            Dependency::new("shared", "", ("", "")),
+        ];
+        let sources = BTreeMap::from([
+            (
+                "geometry".to_string(),
+                FetchContentSource::Directory("libs/geometry".to_string()),
+            ),
+            (
+                "shared".to_string(),
+                FetchContentSource::Directory("/opt/src/shared".to_string()),
+            ),
+        ]);
+
+        let cmake = get_exported_cmake(
+            &ProjectConfig::default(),
+            &app_targets(),
+            &TargetConfig::default(),
+            &ResolvedFeatures::default(),
+            &dependencies,
+            &sources,
+        );
+
+        assert!(cmake.contains("SOURCE_DIR ${PROJECT_SOURCE_DIR}/libs/geometry\n"));
+        assert!(cmake.contains("SOURCE_DIR /opt/src/shared\n"));
+    }
+
+    #[test]
+    fn exported_dependencies_are_not_fetched_again_under_c3pm() {
+        let dependencies = [Dependency::new("fmt", "10.2.1", ("fmtlib", "fmt"))];
+        let sources = BTreeMap::from([(
+            "fmt".to_string(),
+            FetchContentSource::Git {
+                repository: "https://github.com/fmtlib/fmt".to_string(),
+                commit: "0123456789abcdef".to_string(),
+            },
+        )]);
+
+        let cmake = get_exported_cmake(
+            &ProjectConfig::default(),
+            &app_targets(),
+            &TargetConfig::default(),
+            &ResolvedFeatures::default(),
+            &dependencies,
+            &sources,
+        );
+
+        assert!(cmake.contains("if(NOT C3PM_CMAKE_FILE)\n    c3pm_add_fmt()\nendif()\n"));
+        assert!(!cmake.contains("\nc3pm_add_fmt()"));
+        assert!(cmake.contains("target_link_libraries(app PRIVATE"));
+    }
+}
@@ -0,0 +1,228 @@
+use crate::model::GithubConfig;
+use octocrab::Octocrab;
+
+/// The revision that resolves to the newest release of a GitHub repository
+pub const LATEST_RELEASE: &str = "latest-release";
+
+/// Overrides `[github] api-url`, e.g. to point c3pm at a mock server
+pub const API_URL_VARIABLE: &str = "C3PM_GITHUB_API_URL";
+
+/// A token to authenticate API requests with, which raises the rate limit and gives access to
+/// private repositories
+pub const TOKEN_VARIABLE: &str = "GITHUB_TOKEN";
+
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// The API base URL to query for a repository on `host`: `$C3PM_GITHUB_API_URL`, then
+/// `[github] api-url`, then GitHub Enterprise's `https://{host}/api/v3` for hosts other than
+/// github.com
+pub fn api_url(config: &GithubConfig, host: Option<&str>) -> String {
+    if let Ok(url) = std::env::var(API_URL_VARIABLE) {
+        return url;
+    }
+
+    if let Some(url) = &config.api_url {
+        return url.clone();
+    }
+
+    match host {
+        Some(host) if host != "github.com" => format!("https://{}/api/v3", host),
+        _ => DEFAULT_API_URL.to_string(),
+    }
+}
+
+/// Finds the tag of the newest published release of `owner/repo`, skipping drafts, and
+/// prereleases unless `prereleases` is set
+pub async fn get_latest_release_on_remote(
+    api_url: &str,
+    owner: &str,
+    repo: &str,
+    prereleases: bool,
+) -> Result<String, String> {
+    let mut builder = Octocrab::builder()
+        .base_uri(api_url)
+        .map_err(|e| format!("Invalid GitHub API URL {}: {}", api_url, e))?;
+
+    if let Ok(token) = std::env::var(TOKEN_VARIABLE) {
+        builder = builder.personal_token(token);
+    }
+
+    let releases = builder
+        .build()
+        .map_err(|e| e.to_string())?
+        .repos(owner, repo)
+        .releases()
+        .list()
+        .per_page(100)
+        .send()
+        .await
+        .map_err(|e| {
+            let reason = match e {
+                octocrab::Error::GitHub { source, .. } => source.message,
+                e => e.to_string(),
+            };
+
+            format!(
+                "Failed to list the releases of {}/{}: {}",
+                owner, repo, reason
+            )
+        })?;
+
+    releases
+        .items
+        .into_iter()
+        .filter(|release| !release.draft && (prereleases || !release.prerelease))
+        .max_by_key(|release| release.published_at.or(release.created_at))
+        .map(|release| release.tag_name)
+        .ok_or_else(|| match prereleases {
+            true => format!("{}/{} has no releases", owner, repo),
+            false => format!(
+                "{}/{} has no releases (set `prereleases = true` in `[github]` to include prereleases)",
+                owner, repo
+            ),
+        })
+}
+
+/// A blocking [`get_latest_release_on_remote`], run on a runtime of its own since the caller may
+/// already be inside one
+pub fn get_latest_release(
+    api_url: &str,
+    owner: &str,
+    repo: &str,
+    prereleases: bool,
+) -> Result<String, String> {
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map_err(|e| e.to_string())?
+                    .block_on(get_latest_release_on_remote(
+                        api_url,
+                        owner,
+                        repo,
+                        prereleases,
+                    ))
+            })
+            .join()
+            .map_err(|_| format!("Failed to list the releases of {}/{}", owner, repo))?
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::thread::JoinHandle;
+
+    fn release(
+        id: u64,
+        tag: &str,
+        draft: bool,
+        prerelease: bool,
+        published_at: &str,
+    ) -> serde_json::Value {
+        let url = format!("https://api.github.com/repos/fmtlib/fmt/releases/{}", id);
+
+        json!({
+            "url": url,
+            "html_url": format!("https://github.com/fmtlib/fmt/releases/tag/{}", tag),
+            "assets_url": format!("{}/assets", url),
+            "upload_url": format!("{}/assets{{?name,label}}", url),
+            "tarball_url": null,
+            "zipball_url": null,
+            "id": id,
+            "node_id": format!("RE_{}", id),
+            "tag_name": tag,
+            "target_commitish": "master",
+            "name": tag,
+            "body": null,
+            "draft": draft,
+            "prerelease": prerelease,
+            "created_at": published_at,
+            "published_at": published_at,
+            "author": null,
+            "assets": [],
+        })
+    }
+
+    /// Serves `releases` to a single request, returning the stub's URL and the requested path
+    fn serve_releases(releases: serde_json::Value) -> (String, JoinHandle<String>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+
+        let handle = std::thread::spawn(move || {
+            let request = server.recv().unwrap();
+            let path = request.url().to_string();
+            let header = tiny_http::Header::from_bytes("Content-Type", "application/json").unwrap();
+            request
+                .respond(tiny_http::Response::from_string(releases.to_string()).with_header(header))
+                .unwrap();
+
+            path
+        });
+
+        (url, handle)
+    }
+
+    // A single test, since the environment variable is shared by every test thread
+    #[test]
+    fn latest_release_comes_from_the_configured_api() {
+        let releases = json!([
+            release(1, "11.0.0-rc1", false, true, "2024-09-01T00:00:00Z"),
+            release(2, "11.0.0", true, false, "2024-08-01T00:00:00Z"),
+            release(3, "10.2.1", false, false, "2024-01-04T00:00:00Z"),
+            release(4, "9.1.0", false, false, "2022-08-27T00:00:00Z"),
+            release(5, "10.1.1", false, false, "2023-08-28T00:00:00Z"),
+        ]);
+
+        std::env::remove_var(API_URL_VARIABLE);
+        let enterprise = GithubConfig::default();
+        assert_eq!(
+            api_url(&enterprise, Some("github.example.com")),
+            "https://github.example.com/api/v3"
+        );
+        assert_eq!(api_url(&enterprise, Some("github.com")), DEFAULT_API_URL);
+        assert_eq!(api_url(&enterprise, None), DEFAULT_API_URL);
+
+        let (url, stub) = serve_releases(releases.clone());
+        let config = GithubConfig {
+            api_url: Some(url),
+            ..GithubConfig::default()
+        };
+        let latest = get_latest_release(
+            &api_url(&config, Some("github.example.com")),
+            "fmtlib",
+            "fmt",
+            false,
+        );
+        assert_eq!(latest.unwrap(), "10.2.1");
+        assert!(stub
+            .join()
+            .unwrap()
+            .starts_with("/repos/fmtlib/fmt/releases"));
+
+        let (url, stub) = serve_releases(releases);
+        std::env::set_var(API_URL_VARIABLE, &url);
+        let latest = get_latest_release(&api_url(&config, None), "fmtlib", "fmt", true);
+        std::env::remove_var(API_URL_VARIABLE);
+        assert_eq!(latest.unwrap(), "11.0.0-rc1");
+        stub.join().unwrap();
+
+        let (url, stub) = serve_releases(json!([release(
+            1,
+            "2.0.0-beta",
+            false,
+            true,
+            "2024-01-01T00:00:00Z"
+        )]));
+        let error = get_latest_release(&url, "fmtlib", "fmt", false).unwrap_err();
+        assert!(
+            error.contains("has no releases (set `prereleases = true`"),
+            "{}",
+            error
+        );
+        stub.join().unwrap();
+    }
+}
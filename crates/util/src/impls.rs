@@ -1,12 +1,17 @@
+use crate::cfg::{CfgExpr, Platform};
 use crate::model::{
-    BuildConfig, CompilerDetails, Dependency, DependencyData, Generator, Language, Project,
-    ProjectConfig,
+    BinConfig, BuildConfig, CompilerDetails, Dependency, DependencyData, FeatureSelection,
+    Generator, Language, LibKind, OptionValue, PackageRequirement, Profile, ProfileConfig, Project,
+    ProjectConfig, ProjectTarget, ProjectTargetKind, ResolvedFeatures, TargetConfig,
+    TargetSelection, TestFramework,
 };
 use crate::{model, select_compilers};
 use clap::builder::ValueParser;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::{Display, Formatter};
+use std::fs;
 use std::io::Error;
+use std::path::Path;
 use std::str::FromStr;
 
 impl DependencyData {
@@ -16,6 +21,19 @@ impl DependencyData {
             revision: None,
             host: Some("github.com".to_string()),
             repository: (String::new(), String::new()),
+            git: None,
+            url: None,
+            checksum: None,
+            version: None,
+            registry: None,
+            path: None,
+            options: BTreeMap::new(),
+            build_type: None,
+            targets: None,
+            optional: false,
+            package: None,
+            cache: None,
+            system: false,
         }
     }
 
@@ -30,8 +48,46 @@ impl DependencyData {
             revision: version,
             host,
             repository,
+            ..DependencyData::default()
         }
     }
+
+    /// Where the dependency's sources come from, e.g. `git+https://github.com/fmtlib/fmt.git`
+    pub fn source(&self) -> String {
+        if let Some(path) = &self.path {
+            return format!("path+{}", path);
+        }
+
+        if let Some(url) = &self.url {
+            return format!("archive+{}", url);
+        }
+
+        match &self.git {
+            Some(git) => format!("git+{}", git),
+            None => format!("git+{}", self.git_url()),
+        }
+    }
+
+    /// The URL to clone the dependency from
+    pub fn git_url(&self) -> String {
+        match &self.git {
+            Some(git) => git.clone(),
+            None => format!(
+                "https://{}/{}/{}.git",
+                self.host.as_deref().unwrap_or("github.com"),
+                self.repository.0,
+                self.repository.1
+            ),
+        }
+    }
+
+    /// A name that is safe to use inside CMake identifiers
+    pub fn cmake_identifier(&self) -> String {
+        self.name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect()
+    }
 }
 
 impl Dependency {
@@ -159,6 +215,125 @@ impl TryFrom<&str> for Generator {
     }
 }
 
+impl PackageRequirement {
+    /// The semver requirement, `*` if there is none
+    pub fn version_req(&self) -> &str {
+        match self {
+            PackageRequirement::Version(version) => version,
+            PackageRequirement::Detailed(details) => details.version.as_deref().unwrap_or("*"),
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            PackageRequirement::Version(_) => None,
+            PackageRequirement::Detailed(details) => details.path.as_deref(),
+        }
+    }
+
+    pub fn registry(&self) -> Option<&str> {
+        match self {
+            PackageRequirement::Version(_) => None,
+            PackageRequirement::Detailed(details) => details.registry.as_deref(),
+        }
+    }
+
+    /// The dependency as far as it is known before resolving, without a source
+    pub fn to_dependency(&self, name: &str) -> Dependency {
+        let mut data = DependencyData {
+            name: name.to_string(),
+            host: None,
+            ..DependencyData::default()
+        };
+
+        if let PackageRequirement::Detailed(details) = self {
+            data.path = details.path.clone();
+            data.optional = details.optional;
+            data.options = details.options.clone();
+            data.build_type = details.build_type;
+            data.targets = details.targets.clone();
+        }
+
+        Dependency { dependency: data }
+    }
+}
+
+impl ResolvedFeatures {
+    pub fn is_dependency_enabled(&self, dependency: &Dependency) -> bool {
+        !dependency.dependency.optional || self.dependencies.contains(&dependency.dependency.name)
+    }
+}
+
+impl Profile {
+    /// Adds the profile's definitions, and its flags in the syntax of `compiler` (a name as
+    /// used in `cfg(compiler = "...")`), to `target`
+    pub fn apply_to(&self, target: &mut TargetConfig, compiler: &str) {
+        let msvc = compiler == "msvc";
+
+        if let Some(opt_level) = &self.opt_level {
+            let opt_level = opt_level.cmake_value();
+            let flag = match (msvc, opt_level.as_str()) {
+                (true, "0") => "/Od".to_string(),
+                (true, "1" | "s" | "z") => "/O1".to_string(),
+                (true, _) => "/O2".to_string(),
+                (false, _) => format!("-O{}", opt_level),
+            };
+
+            target.compile_flags.push(flag);
+        }
+
+        match (msvc, self.debug) {
+            (true, Some(true)) => {
+                target.compile_flags.push("/Zi".to_string());
+                target.link_flags.push("/DEBUG".to_string());
+            }
+            (false, Some(true)) => target.compile_flags.push("-g".to_string()),
+            (false, Some(false)) => target.compile_flags.push("-g0".to_string()),
+            _ => {}
+        }
+
+        if self.lto {
+            match msvc {
+                true => {
+                    target.compile_flags.push("/GL".to_string());
+                    target.link_flags.push("/LTCG".to_string());
+                }
+                false => {
+                    target.compile_flags.push("-flto".to_string());
+                    target.link_flags.push("-flto".to_string());
+                }
+            }
+        }
+
+        target.definitions.extend(self.definitions.iter().cloned());
+        target
+            .compile_flags
+            .extend(self.compile_flags.iter().cloned());
+        target.link_flags.extend(self.link_flags.iter().cloned());
+    }
+}
+
+impl OptionValue {
+    /// The value as passed to `cmake -D`, without any quoting
+    pub fn cmake_value(&self) -> String {
+        match &self {
+            OptionValue::String(value) => value.clone(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl Display for OptionValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            OptionValue::Bool(true) => f.write_str("ON"),
+            OptionValue::Bool(false) => f.write_str("OFF"),
+            OptionValue::Integer(value) => write!(f, "{}", value),
+            OptionValue::String(value) => write!(f, "\"{}\"", value.replace('"', "\\\"")),
+        }
+    }
+}
+
 impl Display for BuildConfig {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match &self {
@@ -250,10 +425,10 @@ impl FromStr for BuildConfig {
 
     fn from_str(input: &str) -> Result<Self, Self::Err> {
         match input.to_lowercase().as_str() {
-			"Debug" => Ok(BuildConfig::Debug),
-			"RelWithDebInfo" => Ok(BuildConfig::RelWithDebInfo),
-			"Release" => Ok(BuildConfig::Release),
-			"MinSizeRel" => Ok(BuildConfig::MinSizeRel),
+			"debug" => Ok(BuildConfig::Debug),
+			"relwithdebinfo" => Ok(BuildConfig::RelWithDebInfo),
+			"release" => Ok(BuildConfig::Release),
+			"minsizerel" => Ok(BuildConfig::MinSizeRel),
 			_ => Err("Invalid BuildConfig. Possible values are: Debug, RelWithDebInfo, Release, MinSizeRel".to_string()),
 		}
     }
@@ -277,12 +452,17 @@ impl Default for Project {
 
         Self {
             name: String::new(),
+            version: Some("0.1.0".to_string()),
+            description: None,
+            license: None,
             generator: None,
             language: Language::Cpp23,
             compiler: CompilerDetails {
                 c_compiler: Some(compilers.0),
                 cxx_compiler: Some(compilers.1),
             },
+            include: None,
+            exclude: None,
         }
     }
 }
@@ -298,10 +478,145 @@ impl Default for ProjectConfig {
             project_details: Project::default(),
             dirs,
             dependencies: Some(vec![Dependency::default()]),
+            dev_dependencies: None,
+            features: BTreeMap::new(),
+            target: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            cache: None,
+            packages: BTreeMap::new(),
+            registries: BTreeMap::new(),
+            verify: None,
+            github: None,
+            licenses: None,
+            audit: None,
+            artifacts: None,
+            lib: None,
+            bins: Vec::new(),
         }
     }
 }
 
+impl TestFramework {
+    /// The dev-dependency providing the framework, linked into every test
+    pub fn dev_dependency(&self) -> Option<Dependency> {
+        let (mut dependency, target) = match self {
+            TestFramework::Gtest => (
+                Dependency::new("googletest", "v1.14.0", ("google", "googletest")),
+                "GTest::gtest_main",
+            ),
+            TestFramework::Catch2 => (
+                Dependency::new("Catch2", "v3.5.2", ("catchorg", "Catch2")),
+                "Catch2::Catch2WithMain",
+            ),
+            TestFramework::Doctest => (
+                Dependency::new("doctest", "v2.4.11", ("doctest", "doctest")),
+                "doctest::doctest",
+            ),
+            TestFramework::Ctest => return None,
+        };
+
+        dependency.dependency.targets = Some(vec![target.to_string()]);
+
+        if *self == TestFramework::Gtest {
+            let options = &mut dependency.dependency.options;
+            options.insert("INSTALL_GTEST".to_string(), OptionValue::Bool(false));
+            // Links against the same C runtime as the tests with MSVC
+            options.insert(
+                "gtest_force_shared_crt".to_string(),
+                OptionValue::Bool(true),
+            );
+        }
+
+        Some(dependency)
+    }
+}
+
+impl ProjectTargetKind {
+    pub fn is_executable(&self) -> bool {
+        !matches!(self, ProjectTargetKind::Library(_))
+    }
+
+    /// How the kind is called in messages
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ProjectTargetKind::Executable => "binary",
+            ProjectTargetKind::Example => "example",
+            ProjectTargetKind::Test => "test",
+            ProjectTargetKind::Bench => "benchmark",
+            ProjectTargetKind::Library(_) => "library",
+        }
+    }
+}
+
+/// The profiles every project has, named after the CMake build type they select
+const BUILTIN_PROFILES: [(&str, BuildConfig); 4] = [
+    ("debug", BuildConfig::Debug),
+    ("release", BuildConfig::Release),
+    ("relwithdebinfo", BuildConfig::RelWithDebInfo),
+    ("minsizerel", BuildConfig::MinSizeRel),
+];
+
+fn builtin_profile(name: &str) -> Option<ProfileConfig> {
+    BUILTIN_PROFILES
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, build_type)| ProfileConfig {
+            build_type: Some(*build_type),
+            ..ProfileConfig::default()
+        })
+}
+
+/// The targets found by convention in `dir`: one per `<dir>/<name>.c(pp)` file, and one per
+/// `<dir>/<name>/` directory with a `main.c(pp)`, built from every source in it
+fn discover_targets(project_root: &Path, dir: &str, kind: ProjectTargetKind) -> Vec<ProjectTarget> {
+    let entries = match fs::read_dir(project_root.join(dir)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut targets = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+
+            let (name, sources) = if path.is_dir() {
+                let has_main = ["main.c", "main.cpp"]
+                    .iter()
+                    .any(|main| path.join(main).is_file());
+
+                if !has_main {
+                    return None;
+                }
+
+                let sources = vec![
+                    format!("{}/{}/*.c", dir, file_name),
+                    format!("{}/{}/*.cpp", dir, file_name),
+                ];
+                (file_name, sources)
+            } else {
+                match file_name.rsplit_once('.') {
+                    Some((name, "c" | "cpp")) => {
+                        (name.to_string(), vec![format!("{}/{}", dir, file_name)])
+                    }
+                    _ => return None,
+                }
+            };
+
+            Some(ProjectTarget {
+                name,
+                kind,
+                sources,
+                exclude: None,
+                output_name: None,
+            })
+        })
+        .collect::<Vec<ProjectTarget>>();
+
+    targets.sort_by(|a, b| a.name.cmp(&b.name));
+    targets
+}
+
 impl ProjectConfig {
     pub fn get_name(&self) -> &str {
         &self.project_details.name
@@ -359,16 +674,481 @@ impl ProjectConfig {
         ProjectConfig {
             project_details: Project {
                 name: name.to_string(),
+                version: Some("0.1.0".to_string()),
+                description: None,
+                license: None,
                 generator,
                 language,
                 compiler: CompilerDetails {
                     c_compiler: Some(compilers.0),
                     cxx_compiler: Some(compilers.1),
                 },
+                include: None,
+                exclude: None,
             },
             dirs,
             dependencies: None,
+            dev_dependencies: None,
+            features: BTreeMap::new(),
+            target: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            cache: None,
+            packages: BTreeMap::new(),
+            registries: BTreeMap::new(),
+            verify: None,
+            github: None,
+            licenses: None,
+            audit: None,
+            artifacts: None,
+            lib: None,
+            bins: Vec::new(),
+        }
+    }
+
+    pub fn resolve_features(
+        &self,
+        selection: &FeatureSelection,
+    ) -> Result<ResolvedFeatures, String> {
+        let dependencies = self.get_dependencies();
+        let dependencies = dependencies
+            .iter()
+            .chain(self.dev_dependencies.iter().flatten())
+            .collect::<Vec<&Dependency>>();
+
+        ProjectConfig::resolve_feature_table(&self.features, &dependencies, selection)
+    }
+
+    /// Enables the features in `selection`, and `default` unless it is turned off, along with
+    /// everything they enable. Shared with the dependency manifests depman only reads in part.
+    pub fn resolve_feature_table(
+        features: &BTreeMap<String, Vec<String>>,
+        dependencies: &[&Dependency],
+        selection: &FeatureSelection,
+    ) -> Result<ResolvedFeatures, String> {
+        let mut resolved = ResolvedFeatures::default();
+        let mut pending: Vec<String> = selection.features.clone();
+
+        if !selection.no_default_features && features.contains_key("default") {
+            pending.push("default".to_string());
+        }
+
+        while let Some(feature) = pending.pop() {
+            if !resolved.enabled.insert(feature.clone()) {
+                continue;
+            }
+
+            let Some(enables) = features.get(&feature) else {
+                let formatted_possible_values =
+                    features.keys().cloned().collect::<Vec<_>>().join(", ");
+
+                return Err(format!(
+                    "Unknown feature `{}`! Possible values are: {}",
+                    feature, formatted_possible_values
+                ));
+            };
+
+            if feature != "default" {
+                resolved.definitions.push(format!(
+                    "C3PM_FEATURE_{}",
+                    feature.to_uppercase().replace(['-', '.'], "_")
+                ));
+            }
+
+            for entry in enables {
+                if let Some(dependency) = entry.strip_prefix("dep:") {
+                    let is_optional = dependencies
+                        .iter()
+                        .any(|d| d.dependency.name == dependency && d.dependency.optional);
+
+                    if !is_optional {
+                        return Err(format!(
+                            "Feature `{}` enables `{}`, which is not an optional dependency",
+                            feature, dependency
+                        ));
+                    }
+
+                    resolved.dependencies.insert(dependency.to_string());
+                } else if let Some(definition) = entry.strip_prefix("define:") {
+                    resolved.definitions.push(definition.to_string());
+                } else {
+                    pending.push(entry.clone());
+                }
+            }
         }
+
+        resolved.definitions.sort();
+        resolved.definitions.dedup();
+
+        Ok(resolved)
+    }
+
+    /// Resolves the profile `name` from the `[profile.<name>]` tables and the built-in profiles.
+    /// Settings of a profile override the ones it inherits, and its definitions and flags are
+    /// added after the inherited ones.
+    pub fn resolve_profile(&self, name: &str) -> Result<Profile, String> {
+        let profiles = &self.profiles;
+
+        // Built-in profiles may be selected by their build type's name, like `RelWithDebInfo`
+        let name = match profiles.contains_key(name) {
+            true => name.to_string(),
+            false => name.to_lowercase(),
+        };
+
+        let mut chain: Vec<ProfileConfig> = Vec::new();
+        let mut visiting: Vec<String> = Vec::new();
+        let mut next = Some(name.clone());
+
+        while let Some(current) = next.take() {
+            if visiting.contains(&current) {
+                visiting.push(current);
+
+                return Err(format!(
+                    "Profile `{}` inherits from itself: {}",
+                    name,
+                    visiting.join(" -> ")
+                ));
+            }
+
+            match (profiles.get(&current), builtin_profile(&current)) {
+                (Some(config), builtin) => {
+                    chain.push(config.clone());
+                    next = config.inherits.clone();
+
+                    if next.is_none() {
+                        chain.extend(builtin);
+                    }
+                }
+                (None, Some(builtin)) => chain.push(builtin),
+                (None, None) => {
+                    let formatted_possible_values = BUILTIN_PROFILES
+                        .iter()
+                        .map(|(builtin, _)| builtin.to_string())
+                        .chain(profiles.keys().cloned())
+                        .collect::<BTreeSet<String>>()
+                        .into_iter()
+                        .collect::<Vec<String>>()
+                        .join(", ");
+
+                    return Err(format!(
+                        "Unknown profile `{}`! Possible values are: {}",
+                        current, formatted_possible_values
+                    ));
+                }
+            }
+
+            visiting.push(current);
+        }
+
+        let mut profile = Profile {
+            name,
+            build_type: BuildConfig::Debug,
+            opt_level: None,
+            debug: None,
+            lto: false,
+            definitions: Vec::new(),
+            compile_flags: Vec::new(),
+            link_flags: Vec::new(),
+        };
+
+        for config in chain.iter().rev() {
+            profile.build_type = config.build_type.unwrap_or(profile.build_type);
+            profile.opt_level = config.opt_level.clone().or(profile.opt_level);
+            profile.debug = config.debug.or(profile.debug);
+            profile.lto = config.lto.unwrap_or(profile.lto);
+            profile
+                .definitions
+                .extend(config.definitions.iter().cloned());
+            profile
+                .compile_flags
+                .extend(config.compile_flags.iter().cloned());
+            profile.link_flags.extend(config.link_flags.iter().cloned());
+        }
+
+        Ok(profile)
+    }
+
+    /// The targets the project builds: the `[lib]` and `[[bin]]` tables, or a single executable
+    /// named after the project, built from every source, when there are neither. The binaries,
+    /// examples, tests and benchmarks found in `src/bin/`, `examples/`, `tests/` and `benches/`
+    /// are added to them, failing when two targets would share a name.
+    pub fn get_targets(&self, project_root: &Path) -> Result<Vec<ProjectTarget>, String> {
+        let name = self.get_name();
+        let sources_dir = self
+            .get_sources_dir()
+            .map(|dir| dir.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| "src".to_string());
+        let every_source = vec![
+            format!("{}/*.c", sources_dir),
+            format!("{}/*.cpp", sources_dir),
+        ];
+        let main_sources = vec![
+            format!("{}/main.c", sources_dir),
+            format!("{}/main.cpp", sources_dir),
+        ];
+        let mut targets = Vec::new();
+
+        if let Some(lib) = &self.lib {
+            let (sources, exclude) = match (lib.kind, &lib.sources) {
+                (LibKind::HeaderOnly, _) => (Vec::new(), None),
+                (_, Some(sources)) => (sources.clone(), None),
+                // `main` and `src/bin/` belong to the binaries
+                (_, None) => (
+                    every_source.clone(),
+                    Some(format!("/{}/(main[.](c|cpp)|bin/.*)$", sources_dir)),
+                ),
+            };
+
+            targets.push(ProjectTarget {
+                name: lib.name.clone().unwrap_or_else(|| format!("{}_lib", name)),
+                kind: ProjectTargetKind::Library(lib.kind),
+                sources,
+                exclude,
+                // The default target name only avoids clashing with the main binary's
+                output_name: lib.name.is_none().then(|| name.to_string()),
+            });
+        }
+
+        let has_main = main_sources
+            .iter()
+            .any(|source| project_root.join(source).is_file());
+        let implicit_bins = match self.lib.is_none() || has_main {
+            true => vec![BinConfig {
+                name: name.to_string(),
+                sources: None,
+            }],
+            false => Vec::new(),
+        };
+        let bins = match self.bins.is_empty() {
+            true => &implicit_bins,
+            false => &self.bins,
+        };
+
+        for bin in bins {
+            let (sources, exclude) = match &bin.sources {
+                Some(sources) => (sources.clone(), None),
+                None if bin.name == name && self.lib.is_some() => (main_sources.clone(), None),
+                // Without a library, the main binary is built from every source but the other
+                // binaries'
+                None if bin.name == name => (
+                    every_source.clone(),
+                    Some(format!("/{}/bin/.*$", sources_dir)),
+                ),
+                None => (
+                    vec![
+                        format!("{}/bin/{}.c", sources_dir, bin.name),
+                        format!("{}/bin/{}.cpp", sources_dir, bin.name),
+                    ],
+                    None,
+                ),
+            };
+
+            targets.push(ProjectTarget {
+                name: bin.name.clone(),
+                kind: ProjectTargetKind::Executable,
+                sources,
+                exclude,
+                output_name: None,
+            });
+        }
+
+        let conventional_dirs = [
+            (
+                format!("{}/bin", sources_dir),
+                ProjectTargetKind::Executable,
+            ),
+            ("examples".to_string(), ProjectTargetKind::Example),
+            ("tests".to_string(), ProjectTargetKind::Test),
+            ("benches".to_string(), ProjectTargetKind::Bench),
+        ];
+
+        let declared = targets.len();
+
+        for (dir, kind) in conventional_dirs {
+            for target in discover_targets(project_root, &dir, kind) {
+                match targets
+                    .iter()
+                    .position(|existing| existing.name == target.name)
+                {
+                    None => targets.push(target),
+                    // `[[bin]]` entries take precedence over the binaries found in `src/bin/`
+                    Some(index) if index < declared && targets[index].kind == kind => {}
+                    // Every target shares CMake's namespace
+                    Some(index) => {
+                        return Err(format!(
+                            "The {} `{}` in {}/ clashes with the {} of the same name, rename one of them!",
+                            kind.describe(),
+                            target.name,
+                            dir,
+                            targets[index].kind.describe(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(targets)
+    }
+
+    /// The names of the targets `selection` picks, an empty list meaning every target
+    pub fn select_targets(
+        &self,
+        project_root: &Path,
+        selection: &TargetSelection,
+    ) -> Result<Vec<String>, String> {
+        let targets = self.get_targets(project_root)?;
+        let mut selected = Vec::new();
+
+        if selection.lib {
+            match targets.iter().find_map(|target| match target.kind {
+                ProjectTargetKind::Library(kind) => Some((target, kind)),
+                _ => None,
+            }) {
+                Some((target, LibKind::HeaderOnly)) => {
+                    return Err(format!(
+                        "The `{}` library is header-only, there is nothing to build!",
+                        target.name
+                    ));
+                }
+                Some((target, _)) => selected.push(target.name.clone()),
+                None => return Err("The project has no `[lib]` target!".to_string()),
+            }
+        }
+
+        let names_of = |kind: ProjectTargetKind| {
+            targets
+                .iter()
+                .filter(|target| target.kind == kind)
+                .map(|target| target.name.clone())
+                .collect::<Vec<String>>()
+        };
+        let bins = names_of(ProjectTargetKind::Executable);
+        let examples = names_of(ProjectTargetKind::Example);
+
+        for bin in &selection.bins {
+            if !bins.contains(bin) {
+                return Err(format!(
+                    "Unknown binary `{}`! Possible values are: {}",
+                    bin,
+                    bins.join(", ")
+                ));
+            }
+
+            selected.push(bin.clone());
+        }
+
+        let tests = names_of(ProjectTargetKind::Test);
+
+        for test in &selection.tests {
+            if !tests.contains(test) {
+                return Err(format!(
+                    "Unknown test `{}`! Possible values are: {}",
+                    test,
+                    tests.join(", ")
+                ));
+            }
+
+            selected.push(test.clone());
+        }
+
+        for example in &selection.examples {
+            if !examples.contains(example) {
+                return Err(format!(
+                    "Unknown example `{}`! Possible values are: {}",
+                    example,
+                    examples.join(", ")
+                ));
+            }
+
+            selected.push(example.clone());
+        }
+
+        if selection.all_examples {
+            if examples.is_empty() {
+                return Err("The project has no examples!".to_string());
+            }
+
+            selected.extend(examples);
+        }
+
+        Ok(selected)
+    }
+
+    /// The git dependencies followed by the registry packages
+    pub fn get_dependencies(&self) -> Vec<Dependency> {
+        let mut dependencies = self.dependencies.clone().unwrap_or_default();
+
+        for (name, requirement) in &self.packages {
+            dependencies.push(requirement.to_dependency(name));
+        }
+
+        dependencies
+    }
+
+    /// Merges every `[target.'cfg(...)']` table of `target` that matches `platform`, in key
+    /// order. Shared with the dependency manifests depman only reads in part.
+    pub fn merge_target_tables(
+        target: &BTreeMap<String, TargetConfig>,
+        platform: &Platform,
+    ) -> Result<TargetConfig, String> {
+        let mut resolved = TargetConfig::default();
+
+        for (key, config) in target {
+            if !CfgExpr::from_str(key)?.matches(platform) {
+                continue;
+            }
+
+            if let Some(dependencies) = &config.dependencies {
+                resolved
+                    .dependencies
+                    .get_or_insert_with(Vec::new)
+                    .extend(dependencies.iter().cloned());
+            }
+
+            if let Some(dev_dependencies) = &config.dev_dependencies {
+                resolved
+                    .dev_dependencies
+                    .get_or_insert_with(Vec::new)
+                    .extend(dev_dependencies.iter().cloned());
+            }
+
+            resolved
+                .definitions
+                .extend(config.definitions.iter().cloned());
+            resolved
+                .compile_flags
+                .extend(config.compile_flags.iter().cloned());
+            resolved
+                .link_flags
+                .extend(config.link_flags.iter().cloned());
+        }
+
+        Ok(resolved)
+    }
+
+    /// Returns a copy of the config with the dependencies of every matching
+    /// `[target.'cfg(...)']` table merged in, along with the merged table itself
+    pub fn for_platform(
+        &self,
+        platform: &Platform,
+    ) -> Result<(ProjectConfig, TargetConfig), String> {
+        let target = ProjectConfig::merge_target_tables(&self.target, platform)?;
+        let mut config = self.clone();
+
+        if let Some(dependencies) = &target.dependencies {
+            config
+                .dependencies
+                .get_or_insert_with(Vec::new)
+                .extend(dependencies.iter().cloned());
+        }
+
+        if let Some(dev_dependencies) = &target.dev_dependencies {
+            config
+                .dev_dependencies
+                .get_or_insert_with(Vec::new)
+                .extend(dev_dependencies.iter().cloned());
+        }
+
+        Ok((config, target))
     }
 
     pub fn serialize_config(config: &ProjectConfig) -> Result<String, Error> {
@@ -379,3 +1159,329 @@ impl ProjectConfig {
         toml::from_str(config_str).map_err(|e| Error::new(std::io::ErrorKind::Other, e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::LibConfig;
+
+    fn project(files: &[&str]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+
+        for file in files {
+            let path = root.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "int main() { return 0; }\n").unwrap();
+        }
+
+        root
+    }
+
+    fn config(name: &str) -> ProjectConfig {
+        let mut config = ProjectConfig::default();
+        config.project_details.name = name.to_string();
+        config
+    }
+
+    fn names(targets: &[ProjectTarget]) -> Vec<(&str, ProjectTargetKind)> {
+        targets
+            .iter()
+            .map(|target| (target.name.as_str(), target.kind))
+            .collect()
+    }
+
+    #[test]
+    fn get_targets_discovers_conventional_directories() {
+        let root = project(&[
+            "src/main.cpp",
+            "src/bin/tool.cpp",
+            "examples/basic.cpp",
+            "examples/gui/main.cpp",
+            "examples/gui/window.cpp",
+            "examples/README.md",
+            "examples/assets/logo.png",
+            "tests/parser.c",
+            "benches/throughput.cpp",
+        ]);
+
+        let targets = config("app").get_targets(root.path()).unwrap();
+
+        assert_eq!(
+            names(&targets),
+            [
+                ("app", ProjectTargetKind::Executable),
+                ("tool", ProjectTargetKind::Executable),
+                ("basic", ProjectTargetKind::Example),
+                ("gui", ProjectTargetKind::Example),
+                ("parser", ProjectTargetKind::Test),
+                ("throughput", ProjectTargetKind::Bench),
+            ]
+        );
+        assert_eq!(
+            targets[3].sources,
+            ["examples/gui/*.c", "examples/gui/*.cpp"]
+        );
+        assert_eq!(targets[4].sources, ["tests/parser.c"]);
+    }
+
+    #[test]
+    fn get_targets_builds_a_library_and_keeps_declared_binaries() {
+        let root = project(&["src/lib.cpp", "src/main.cpp", "src/bin/tool.cpp"]);
+        let mut config = config("app");
+        config.lib = Some(LibConfig::default());
+        config.bins = vec![BinConfig {
+            name: "tool".to_string(),
+            sources: Some(vec!["tools/tool.cpp".to_string()]),
+        }];
+
+        let targets = config.get_targets(root.path()).unwrap();
+
+        assert_eq!(
+            names(&targets),
+            [
+                ("app_lib", ProjectTargetKind::Library(LibKind::Static)),
+                ("tool", ProjectTargetKind::Executable),
+            ]
+        );
+        assert_eq!(targets[0].output_name.as_deref(), Some("app"));
+        assert_eq!(targets[1].sources, ["tools/tool.cpp"]);
+    }
+
+    #[test]
+    fn get_targets_rejects_name_clashes() {
+        let root = project(&["src/main.cpp", "examples/basic.cpp", "tests/basic.cpp"]);
+
+        assert_eq!(
+            config("app").get_targets(root.path()).unwrap_err(),
+            "The test `basic` in tests/ clashes with the example of the same name, rename one of them!"
+        );
+
+        let root = project(&["src/main.cpp", "examples/app.cpp"]);
+
+        assert_eq!(
+            config("app").get_targets(root.path()).unwrap_err(),
+            "The example `app` in examples/ clashes with the binary of the same name, rename one of them!"
+        );
+    }
+
+    #[test]
+    fn get_targets_excludes_binary_sources_from_the_default_sources_dir() {
+        let root = project(&["src/lib.cpp", "src/main.cpp", "src/bin/tool.cpp"]);
+        let mut config = config("app");
+        assert_eq!(config.get_sources_dir().as_deref(), Some("src/"));
+
+        let targets = config.get_targets(root.path()).unwrap();
+        assert_eq!(targets[0].sources, ["src/*.c", "src/*.cpp"]);
+        assert_eq!(targets[0].exclude.as_deref(), Some("/src/bin/.*$"));
+
+        config.lib = Some(LibConfig::default());
+        let targets = config.get_targets(root.path()).unwrap();
+
+        assert_eq!(
+            names(&targets),
+            [
+                ("app_lib", ProjectTargetKind::Library(LibKind::Static)),
+                ("app", ProjectTargetKind::Executable),
+                ("tool", ProjectTargetKind::Executable),
+            ]
+        );
+        assert_eq!(
+            targets[0].exclude.as_deref(),
+            Some("/src/(main[.](c|cpp)|bin/.*)$")
+        );
+        assert_eq!(targets[1].sources, ["src/main.c", "src/main.cpp"]);
+        assert_eq!(targets[2].sources, ["src/bin/tool.cpp"]);
+    }
+
+    fn profiles(manifest: &str) -> ProjectConfig {
+        let mut config = config("app");
+        config.profiles = toml::from_str(manifest).unwrap();
+        config
+    }
+
+    #[test]
+    fn resolve_profile_selects_builtin_profiles_by_build_type() {
+        let config = config("app");
+
+        let profile = config.resolve_profile("RelWithDebInfo").unwrap();
+        assert_eq!(profile.name, "relwithdebinfo");
+        assert_eq!(profile.build_type.to_string(), "RelWithDebInfo");
+        assert!(!profile.lto);
+
+        assert_eq!(
+            config.resolve_profile("fast").unwrap_err(),
+            "Unknown profile `fast`! Possible values are: debug, minsizerel, release, relwithdebinfo"
+        );
+    }
+
+    #[test]
+    fn resolve_profile_merges_inherited_profiles() {
+        let config = profiles(
+            r#"
+            [release]
+            lto = true
+            definitions = ["NDEBUG_CHECKS=0"]
+
+            [dist]
+            inherits = "release"
+            debug = true
+            definitions = ["DIST"]
+            link-flags = ["-s"]
+
+            [asan]
+            compile-flags = ["-fsanitize=address"]
+            "#,
+        );
+
+        let dist = config.resolve_profile("dist").unwrap();
+        assert_eq!(dist.build_type.to_string(), "Release");
+        assert!(dist.lto);
+        assert_eq!(dist.debug, Some(true));
+        assert_eq!(dist.definitions, ["NDEBUG_CHECKS=0", "DIST"]);
+        assert_eq!(dist.link_flags, ["-s"]);
+
+        // Profiles that inherit nothing start from `Debug`
+        let asan = config.resolve_profile("asan").unwrap();
+        assert_eq!(asan.build_type.to_string(), "Debug");
+        assert_eq!(asan.compile_flags, ["-fsanitize=address"]);
+    }
+
+    #[test]
+    fn resolve_profile_rejects_inheritance_cycles() {
+        let config = profiles(
+            r#"
+            a = { inherits = "b" }
+            b = { inherits = "c" }
+            c = { inherits = "a" }
+            self = { inherits = "self" }
+            "#,
+        );
+
+        assert_eq!(
+            config.resolve_profile("a").unwrap_err(),
+            "Profile `a` inherits from itself: a -> b -> c -> a"
+        );
+        assert_eq!(
+            config.resolve_profile("self").unwrap_err(),
+            "Profile `self` inherits from itself: self -> self"
+        );
+    }
+
+    #[test]
+    fn resolve_profile_reports_unknown_parents() {
+        let config = profiles(r#"ci = { inherits = "fast" }"#);
+
+        assert_eq!(
+            config.resolve_profile("ci").unwrap_err(),
+            "Unknown profile `fast`! Possible values are: ci, debug, minsizerel, release, relwithdebinfo"
+        );
+    }
+
+    #[test]
+    fn merge_target_tables_applies_matching_tables_in_key_order() {
+        let config: BTreeMap<String, TargetConfig> = toml::from_str(
+            r#"
+            ['cfg(unix)']
+            definitions = ["UNIX"]
+            deps = [{ dependency = { name = "pthreads", system = true } }]
+
+            ['cfg(all(os = "linux", compiler = "gcc"))']
+            compile-flags = ["-fno-plt"]
+
+            ['cfg(windows)']
+            definitions = ["WIN32_LEAN_AND_MEAN"]
+            "#,
+        )
+        .unwrap();
+        let linux = Platform {
+            os: "linux".to_string(),
+            family: "unix".to_string(),
+            arch: "x86_64".to_string(),
+            compiler: "gcc".to_string(),
+        };
+
+        let merged = ProjectConfig::merge_target_tables(&config, &linux).unwrap();
+
+        assert_eq!(merged.definitions, ["UNIX"]);
+        assert_eq!(merged.compile_flags, ["-fno-plt"]);
+        assert_eq!(merged.dependencies.unwrap()[0].dependency.name, "pthreads");
+
+        let invalid = BTreeMap::from([("cfg(os)".to_string(), TargetConfig::default())]);
+        assert!(ProjectConfig::merge_target_tables(&invalid, &linux).is_err());
+    }
+
+    fn features(manifest: &str) -> ProjectConfig {
+        let mut config = config("app");
+        config.features = toml::from_str(manifest).unwrap();
+        config.dependencies = Some(vec![Dependency {
+            dependency: DependencyData {
+                name: "zlib".to_string(),
+                optional: true,
+                ..DependencyData::default()
+            },
+        }]);
+        config
+    }
+
+    fn selection(features: &[&str], no_default_features: bool) -> FeatureSelection {
+        FeatureSelection {
+            features: features.iter().map(|feature| feature.to_string()).collect(),
+            no_default_features,
+        }
+    }
+
+    #[test]
+    fn resolve_features_enables_defaults_and_what_features_enable() {
+        let config = features(
+            r#"
+            default = ["compression"]
+            compression = ["dep:zlib", "define:USE_ZLIB=1"]
+            tls = ["compression"]
+            "#,
+        );
+
+        let resolved = config.resolve_features(&selection(&[], false)).unwrap();
+        assert_eq!(
+            resolved.enabled,
+            BTreeSet::from(["compression".to_string(), "default".to_string()])
+        );
+        assert_eq!(resolved.dependencies, BTreeSet::from(["zlib".to_string()]));
+        assert_eq!(
+            resolved.definitions,
+            ["C3PM_FEATURE_COMPRESSION", "USE_ZLIB=1"]
+        );
+
+        let resolved = config.resolve_features(&selection(&[], true)).unwrap();
+        assert!(resolved.enabled.is_empty());
+        assert!(resolved.dependencies.is_empty());
+
+        let resolved = config.resolve_features(&selection(&["tls"], true)).unwrap();
+        assert_eq!(
+            resolved.definitions,
+            ["C3PM_FEATURE_COMPRESSION", "C3PM_FEATURE_TLS", "USE_ZLIB=1"]
+        );
+    }
+
+    #[test]
+    fn resolve_features_rejects_unknown_features_and_required_dependencies() {
+        let config = features(
+            r#"
+            json = ["dep:nlohmann_json"]
+            tls = []
+            "#,
+        );
+
+        assert_eq!(
+            config
+                .resolve_features(&selection(&["gui"], false))
+                .unwrap_err(),
+            "Unknown feature `gui`! Possible values are: json, tls"
+        );
+        assert_eq!(
+            config
+                .resolve_features(&selection(&["json"], false))
+                .unwrap_err(),
+            "Feature `json` enables `nlohmann_json`, which is not an optional dependency"
+        );
+    }
+}
@@ -0,0 +1,1262 @@
+use crate::github::LATEST_RELEASE;
+use crate::model::{Dependency, DependencyData, OptionValue};
+use crate::{append_dependencies, load_project_config};
+use git2::Repository;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// A dependency declared in one of the project's other manifests
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportedDependency {
+    /// Where the dependency was declared, e.g. `vcpkg.json` or `submodule external/fmt`
+    pub origin: String,
+    pub dev: bool,
+    pub dependency: DependencyData,
+}
+
+/// A declared dependency that has no c3pm equivalent
+#[derive(Debug, Clone, Serialize)]
+pub struct UnmappedDependency {
+    pub origin: String,
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub imported: Vec<ImportedDependency>,
+    /// Dependencies that were found but that the project (or an earlier manifest) already
+    /// declares under the same name
+    pub existing: Vec<ImportedDependency>,
+    pub unmapped: Vec<UnmappedDependency>,
+    /// Manifests that couldn't be read
+    pub warnings: Vec<String>,
+}
+
+/// A vcpkg port or Conan package built from a GitHub repository
+struct KnownPackage {
+    /// The port and package names, lowercase and with `-` instead of `_`
+    names: &'static [&'static str],
+    repository: (&'static str, &'static str),
+    /// What the repository's release tags put in front of the version
+    tag_prefix: &'static str,
+    targets: &'static [&'static str],
+}
+
+const KNOWN_PACKAGES: &[KnownPackage] = &[
+    KnownPackage {
+        names: &["abseil"],
+        repository: ("abseil", "abseil-cpp"),
+        tag_prefix: "",
+        targets: &[],
+    },
+    KnownPackage {
+        names: &["benchmark"],
+        repository: ("google", "benchmark"),
+        tag_prefix: "v",
+        targets: &["benchmark::benchmark"],
+    },
+    KnownPackage {
+        names: &["catch2"],
+        repository: ("catchorg", "Catch2"),
+        tag_prefix: "v",
+        targets: &["Catch2::Catch2WithMain"],
+    },
+    KnownPackage {
+        names: &["cli11"],
+        repository: ("CLIUtils", "CLI11"),
+        tag_prefix: "v",
+        targets: &["CLI11::CLI11"],
+    },
+    KnownPackage {
+        names: &["cxxopts"],
+        repository: ("jarro2783", "cxxopts"),
+        tag_prefix: "v",
+        targets: &["cxxopts::cxxopts"],
+    },
+    KnownPackage {
+        names: &["doctest"],
+        repository: ("doctest", "doctest"),
+        tag_prefix: "v",
+        targets: &["doctest::doctest"],
+    },
+    KnownPackage {
+        names: &["entt"],
+        repository: ("skypjack", "entt"),
+        tag_prefix: "v",
+        targets: &["EnTT::EnTT"],
+    },
+    KnownPackage {
+        names: &["expected-lite"],
+        repository: ("martinmoene", "expected-lite"),
+        tag_prefix: "v",
+        targets: &["nonstd::expected-lite"],
+    },
+    KnownPackage {
+        names: &["fmt"],
+        repository: ("fmtlib", "fmt"),
+        tag_prefix: "",
+        targets: &["fmt::fmt"],
+    },
+    KnownPackage {
+        names: &["glfw3", "glfw"],
+        repository: ("glfw", "glfw"),
+        tag_prefix: "",
+        targets: &["glfw"],
+    },
+    KnownPackage {
+        names: &["glm"],
+        repository: ("g-truc", "glm"),
+        tag_prefix: "",
+        targets: &["glm::glm"],
+    },
+    KnownPackage {
+        names: &["gtest", "googletest"],
+        repository: ("google", "googletest"),
+        tag_prefix: "v",
+        targets: &["GTest::gtest", "GTest::gtest_main"],
+    },
+    KnownPackage {
+        names: &["magic-enum"],
+        repository: ("Neargye", "magic_enum"),
+        tag_prefix: "v",
+        targets: &["magic_enum::magic_enum"],
+    },
+    KnownPackage {
+        names: &["nlohmann-json"],
+        repository: ("nlohmann", "json"),
+        tag_prefix: "v",
+        targets: &["nlohmann_json::nlohmann_json"],
+    },
+    KnownPackage {
+        names: &["pybind11"],
+        repository: ("pybind", "pybind11"),
+        tag_prefix: "v",
+        targets: &["pybind11::headers"],
+    },
+    KnownPackage {
+        names: &["range-v3"],
+        repository: ("ericniebler", "range-v3"),
+        tag_prefix: "",
+        targets: &["range-v3"],
+    },
+    KnownPackage {
+        names: &["spdlog"],
+        repository: ("gabime", "spdlog"),
+        tag_prefix: "v",
+        targets: &["spdlog::spdlog"],
+    },
+    KnownPackage {
+        names: &["tl-expected"],
+        repository: ("TartanLlama", "expected"),
+        tag_prefix: "v",
+        targets: &["tl::expected"],
+    },
+    KnownPackage {
+        names: &["tomlplusplus"],
+        repository: ("marzer", "tomlplusplus"),
+        tag_prefix: "v",
+        targets: &["tomlplusplus::tomlplusplus"],
+    },
+    KnownPackage {
+        names: &["yaml-cpp"],
+        repository: ("jbeder", "yaml-cpp"),
+        tag_prefix: "",
+        targets: &["yaml-cpp::yaml-cpp"],
+    },
+    KnownPackage {
+        names: &["zlib"],
+        repository: ("madler", "zlib"),
+        tag_prefix: "v",
+        targets: &[],
+    },
+];
+
+/// vcpkg ports and Conan packages that are best left to the system, with the name to pass to
+/// `find_package`
+const SYSTEM_PACKAGES: &[(&[&str], &str)] = &[
+    (&["boost"], "Boost"),
+    (&["curl", "libcurl"], "CURL"),
+    (&["libjpeg-turbo"], "JPEG"),
+    (&["libpng"], "PNG"),
+    (&["openssl"], "OpenSSL"),
+    (&["protobuf"], "Protobuf"),
+    (&["qtbase", "qt"], "Qt6"),
+    (&["sqlite3"], "SQLite3"),
+];
+
+/// Test and benchmark frameworks, imported as dev-dependencies
+const DEV_PACKAGES: &[&str] = &["benchmark", "catch2", "doctest", "googletest", "gtest"];
+
+/// Directories that never hold the project's own CMake files
+const IGNORED_DIRECTORIES: &[&str] = &["build", "deps", "out", "target"];
+
+const CPM_KEYWORDS: &[&str] = &[
+    "BITBUCKET_REPOSITORY",
+    "CUSTOM_CACHE_KEY",
+    "DOWNLOAD_COMMAND",
+    "DOWNLOAD_ONLY",
+    "EXCLUDE_FROM_ALL",
+    "FIND_PACKAGE_ARGUMENTS",
+    "FORCE",
+    "GITHUB_REPOSITORY",
+    "GITLAB_REPOSITORY",
+    "GIT_REPOSITORY",
+    "GIT_SHALLOW",
+    "GIT_TAG",
+    "NAME",
+    "NO_CACHE",
+    "OPTIONS",
+    "PATCHES",
+    "SOURCE_DIR",
+    "SOURCE_SUBDIR",
+    "SYSTEM",
+    "URI",
+    "URL",
+    "URL_HASH",
+    "URL_MD5",
+    "VERSION",
+];
+
+const FETCH_CONTENT_KEYWORDS: &[&str] = &[
+    "BINARY_DIR",
+    "DOWNLOAD_EXTRACT_TIMESTAMP",
+    "DOWNLOAD_NO_EXTRACT",
+    "EXCLUDE_FROM_ALL",
+    "FIND_PACKAGE_ARGS",
+    "GIT_PROGRESS",
+    "GIT_REMOTE_NAME",
+    "GIT_REPOSITORY",
+    "GIT_SHALLOW",
+    "GIT_SUBMODULES",
+    "GIT_TAG",
+    "HG_REPOSITORY",
+    "OVERRIDE_FIND_PACKAGE",
+    "PATCH_COMMAND",
+    "SOURCE_DIR",
+    "SOURCE_SUBDIR",
+    "SVN_REPOSITORY",
+    "SYSTEM",
+    "UPDATE_COMMAND",
+    "UPDATE_DISCONNECTED",
+    "URL",
+    "URL_HASH",
+    "URL_MD5",
+];
+
+/// Finds the dependencies declared in the project's `vcpkg.json`, `conanfile.txt`,
+/// `CPMAddPackage`/`FetchContent_Declare` calls and git submodules, and appends the ones the
+/// project doesn't declare yet to its `.cpppm.toml` (unless `dry_run` is set)
+pub fn import_dependencies(project_root: &Path, dry_run: bool) -> Result<ImportReport, String> {
+    let config_path = project_root.join(".cpppm.toml");
+    let config = load_project_config(&config_path)?;
+    let mut report = ImportReport::default();
+
+    import_vcpkg_manifest(project_root, &mut report);
+    import_conanfile(project_root, &mut report);
+    // Submodules are read first so their own CMake files can be skipped, but reported last
+    let mut submodules = ImportReport::default();
+    let submodule_paths = import_submodules(project_root, &mut submodules);
+    import_cmake_files(project_root, &submodule_paths, &mut report);
+    report.imported.append(&mut submodules.imported);
+    report.unmapped.append(&mut submodules.unmapped);
+    report.warnings.append(&mut submodules.warnings);
+
+    let mut declared = config
+        .dependencies
+        .iter()
+        .chain(config.dev_dependencies.iter())
+        .flatten()
+        .map(|dependency| dependency.dependency.name.clone())
+        .chain(config.packages.keys().cloned())
+        .collect::<BTreeSet<String>>();
+
+    let (imported, existing) = std::mem::take(&mut report.imported)
+        .into_iter()
+        .partition(|imported| declared.insert(imported.dependency.name.clone()));
+    report.imported = imported;
+    report.existing = existing;
+
+    if !dry_run {
+        let (dev_dependencies, dependencies): (Vec<&ImportedDependency>, _) =
+            report.imported.iter().partition(|imported| imported.dev);
+        let to_dependencies = |imported: Vec<&ImportedDependency>| {
+            imported
+                .into_iter()
+                .map(|imported| Dependency {
+                    dependency: imported.dependency.clone(),
+                })
+                .collect::<Vec<Dependency>>()
+        };
+
+        append_dependencies(
+            &config_path,
+            &to_dependencies(dependencies),
+            &to_dependencies(dev_dependencies),
+        )?;
+    }
+
+    Ok(report)
+}
+
+impl ImportReport {
+    fn import(&mut self, origin: &str, dependency: DependencyData, dev: bool) {
+        self.imported.push(ImportedDependency {
+            origin: origin.to_string(),
+            dev: dev || DEV_PACKAGES.contains(&normalize_name(&dependency.name).as_str()),
+            dependency,
+        });
+    }
+
+    fn unmapped(&mut self, origin: &str, name: &str, reason: impl Into<String>) {
+        self.unmapped.push(UnmappedDependency {
+            origin: origin.to_string(),
+            name: name.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    /// Imports a vcpkg port or Conan package through the tables of well-known packages
+    fn import_known(
+        &mut self,
+        origin: &str,
+        name: &str,
+        version: Option<&str>,
+        dev: bool,
+        kind: &str,
+    ) {
+        let normalized = normalize_name(name);
+
+        if let Some(package) = KNOWN_PACKAGES
+            .iter()
+            .find(|package| package.names.contains(&normalized.as_str()))
+        {
+            let mut data = DependencyData::new(
+                name.to_string(),
+                Some(match version {
+                    Some(version) => format!("{}{}", package.tag_prefix, version),
+                    None => LATEST_RELEASE.to_string(),
+                }),
+                Some("github.com".to_string()),
+                (
+                    package.repository.0.to_string(),
+                    package.repository.1.to_string(),
+                ),
+            );
+            if !package.targets.is_empty() {
+                data.targets = Some(package.targets.iter().map(|t| t.to_string()).collect());
+            }
+
+            return self.import(origin, data, dev);
+        }
+
+        if let Some((_, package)) = SYSTEM_PACKAGES
+            .iter()
+            .find(|(names, _)| names.contains(&normalized.as_str()))
+        {
+            let mut data = DependencyData::default();
+            data.name = name.to_string();
+            data.host = None;
+            data.system = true;
+            data.package = Some(package.to_string());
+            data.version = version.map(str::to_string);
+
+            return self.import(origin, data, dev);
+        }
+
+        self.unmapped(
+            origin,
+            name,
+            format!("unknown {}, add its repository with `cpppm add`", kind),
+        );
+    }
+}
+
+/// Reads the `dependencies` of `vcpkg.json`, taking versions from `version>=` constraints and
+/// `overrides`
+fn import_vcpkg_manifest(project_root: &Path, report: &mut ImportReport) {
+    let origin = "vcpkg.json";
+    let Ok(contents) = fs::read_to_string(project_root.join(origin)) else {
+        return;
+    };
+    let manifest = match serde_json::from_str::<Value>(&contents) {
+        Ok(manifest) => manifest,
+        Err(e) => return report.warnings.push(format!("Invalid {}: {}", origin, e)),
+    };
+
+    let overrides = manifest["overrides"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some((entry["name"].as_str()?, entry["version"].as_str()?)))
+        .collect::<BTreeMap<&str, &str>>();
+
+    for entry in manifest["dependencies"].as_array().into_iter().flatten() {
+        let Some(name) = entry.as_str().or_else(|| entry["name"].as_str()) else {
+            continue;
+        };
+
+        if entry["host"].as_bool().unwrap_or(false) {
+            report.unmapped(origin, name, "a host tool, not a library");
+            continue;
+        }
+
+        // Port versions (`1.2.3#2`) only differ in how vcpkg builds the port
+        let version = overrides
+            .get(name)
+            .copied()
+            .or_else(|| entry["version>="].as_str())
+            .map(|version| version.split('#').next().unwrap_or(version));
+
+        report.import_known(origin, name, version, false, "vcpkg port");
+    }
+}
+
+/// Reads the `[requires]` and `[test_requires]` of `conanfile.txt`, version ranges resolving to
+/// the latest release
+fn import_conanfile(project_root: &Path, report: &mut ImportReport) {
+    let origin = "conanfile.txt";
+    let Ok(contents) = fs::read_to_string(project_root.join(origin)) else {
+        if project_root.join("conanfile.py").exists() {
+            report
+                .warnings
+                .push("conanfile.py can't be imported, only conanfile.txt".to_string());
+        }
+
+        return;
+    };
+
+    let mut section = "";
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim();
+            continue;
+        }
+
+        let dev = match section {
+            "requires" => false,
+            "test_requires" => true,
+            "tool_requires" | "build_requires" => {
+                let name = line.split('/').next().unwrap_or(line);
+                report.unmapped(origin, name, "a build tool, not a library");
+                continue;
+            }
+            _ => continue,
+        };
+
+        // `name/version[@user/channel][#recipe-revision]`
+        let (name, reference) = line.split_once('/').unwrap_or((line, ""));
+        let version = reference
+            .split(['@', '#'])
+            .next()
+            .filter(|version| !version.is_empty() && !version.starts_with('['));
+
+        report.import_known(origin, name, version, dev, "Conan package");
+    }
+}
+
+/// Maps the project's git submodules to git dependencies pinned to their recorded commits, and
+/// returns their paths
+fn import_submodules(project_root: &Path, report: &mut ImportReport) -> Vec<PathBuf> {
+    let Ok(repo) = Repository::open(project_root) else {
+        return Vec::new();
+    };
+    let submodules = match repo.submodules() {
+        Ok(submodules) => submodules,
+        Err(e) => {
+            report
+                .warnings
+                .push(format!("Failed to read the submodules: {}", e.message()));
+            return Vec::new();
+        }
+    };
+
+    let mut paths = Vec::new();
+    for submodule in submodules {
+        let path = submodule.path().to_path_buf();
+        let origin = format!("submodule {}", path.display());
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.display().to_string());
+        paths.push(project_root.join(&path));
+
+        let Some(url) = submodule.url() else {
+            report.unmapped(&origin, &name, "has no URL");
+            continue;
+        };
+        if url.starts_with("./") || url.starts_with("../") {
+            report.unmapped(&origin, &name, "has a relative URL");
+            continue;
+        }
+
+        let commit = submodule
+            .head_id()
+            .or_else(|| submodule.index_id())
+            .map(|commit| commit.to_string());
+        match git_dependency(&name, url, commit) {
+            Ok(dependency) => report.import(&origin, dependency, false),
+            Err(reason) => report.unmapped(&origin, &name, reason),
+        }
+    }
+
+    paths
+}
+
+/// Reads the `CPMAddPackage`, `CPMFindPackage` and `FetchContent_Declare` calls in every
+/// `CMakeLists.txt` and `.cmake` file of the project, outside of `excluded`
+fn import_cmake_files(project_root: &Path, excluded: &[PathBuf], report: &mut ImportReport) {
+    let entries = WalkDir::new(project_root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            entry.depth() == 0
+                || !entry.file_type().is_dir()
+                || !(name.starts_with('.')
+                    || name.starts_with("cmake-build-")
+                    || IGNORED_DIRECTORIES.contains(&name.as_ref())
+                    || excluded.iter().any(|path| path == entry.path()))
+        })
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_lowercase();
+            // CPM's own scripts call `CPMAddPackage` with whatever they were given
+            entry.file_type().is_file()
+                && (name == "cmakelists.txt" || name.ends_with(".cmake"))
+                && name != "cpm.cmake"
+                && name != "get_cpm.cmake"
+        });
+
+    for entry in entries {
+        let origin = entry
+            .path()
+            .strip_prefix(project_root)
+            .unwrap_or(entry.path())
+            .display()
+            .to_string();
+
+        match fs::read_to_string(entry.path()) {
+            Ok(source) => import_cmake_file(&origin, &source, report),
+            Err(e) => report
+                .warnings
+                .push(format!("Failed to read {}: {}", origin, e)),
+        }
+    }
+}
+
+fn import_cmake_file(origin: &str, source: &str, report: &mut ImportReport) {
+    let commands = match read_cmake_commands(source) {
+        Ok(commands) => commands,
+        Err(e) => {
+            return report
+                .warnings
+                .push(format!("Failed to parse {}: {}", origin, e))
+        }
+    };
+
+    for command in commands {
+        match command.name.as_str() {
+            "cpmaddpackage" | "cpmfindpackage" => {
+                import_cpm_package(origin, &command.arguments, report)
+            }
+            "fetchcontent_declare" => import_fetch_content(origin, &command.arguments, report),
+            _ => {}
+        }
+    }
+}
+
+fn import_cpm_package(origin: &str, arguments: &[CMakeArgument], report: &mut ImportReport) {
+    let origin = format!("{} (CPMAddPackage)", origin);
+    let mut arguments = get_arguments(arguments, CPM_KEYWORDS);
+
+    // `CPMAddPackage("gh:owner/repo@version#tag")`, or the same as `URI`
+    if let Some(uri) = arguments
+        .remove("URI")
+        .or_else(|| arguments.remove(""))
+        .and_then(|values| values.into_iter().next())
+    {
+        expand_cpm_uri(&uri, &mut arguments);
+    }
+
+    let first = |keyword: &str| {
+        arguments
+            .get(keyword)
+            .and_then(|values| values.first())
+            .cloned()
+    };
+
+    let git = first("GITHUB_REPOSITORY")
+        .map(|repository| format!("https://github.com/{}", repository))
+        .or_else(|| first("GITLAB_REPOSITORY").map(|r| format!("https://gitlab.com/{}", r)))
+        .or_else(|| first("BITBUCKET_REPOSITORY").map(|r| format!("https://bitbucket.org/{}", r)))
+        .or_else(|| first("GIT_REPOSITORY"));
+    let url = first("URL");
+    let name = first("NAME")
+        .or_else(|| git.as_deref().and_then(get_repository_name))
+        .unwrap_or_else(|| "<unnamed>".to_string());
+
+    // CPM checks out `v{VERSION}` unless told otherwise
+    let revision = first("GIT_TAG").or_else(|| first("VERSION").map(|v| format!("v{}", v)));
+
+    let dependency = match (git, url) {
+        (Some(git), _) => git_dependency(&name, &git, revision),
+        (None, Some(url)) => archive_dependency(&name, &url, first("URL_HASH")),
+        (None, None) => Err("has no repository or URL".to_string()),
+    };
+
+    match dependency {
+        Ok(mut dependency) => {
+            dependency.options = get_options(arguments.get("OPTIONS").into_iter().flatten());
+            report.import(&origin, dependency, false);
+        }
+        Err(reason) => report.unmapped(&origin, &name, reason),
+    }
+}
+
+fn import_fetch_content(origin: &str, arguments: &[CMakeArgument], report: &mut ImportReport) {
+    let origin = format!("{} (FetchContent_Declare)", origin);
+    let arguments = get_arguments(arguments, FETCH_CONTENT_KEYWORDS);
+    let first = |keyword: &str| {
+        arguments
+            .get(keyword)
+            .and_then(|values| values.first())
+            .cloned()
+    };
+
+    let name = first("").unwrap_or_else(|| "<unnamed>".to_string());
+    let dependency = match (first("GIT_REPOSITORY"), first("URL")) {
+        (Some(git), _) => git_dependency(&name, &git, first("GIT_TAG")),
+        (None, Some(url)) => archive_dependency(&name, &url, first("URL_HASH")),
+        _ if arguments.contains_key("SVN_REPOSITORY")
+            || arguments.contains_key("HG_REPOSITORY") =>
+        {
+            Err("only git repositories and archives are supported".to_string())
+        }
+        (None, None) => Err("has no repository or URL".to_string()),
+    };
+
+    match dependency {
+        Ok(dependency) => report.import(&origin, dependency, false),
+        Err(reason) => report.unmapped(&origin, &name, reason),
+    }
+}
+
+/// Groups a call's arguments by the keyword they follow, the ones before any keyword under `""`
+fn get_arguments(arguments: &[CMakeArgument], keywords: &[&str]) -> BTreeMap<String, Vec<String>> {
+    let mut grouped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut keyword = String::new();
+
+    for argument in arguments {
+        if !argument.quoted && keywords.contains(&argument.text.as_str()) {
+            grouped.entry(argument.text.clone()).or_default();
+            keyword = argument.text.clone();
+            continue;
+        }
+
+        grouped
+            .entry(keyword.clone())
+            .or_default()
+            .push(argument.text.clone());
+    }
+
+    grouped
+}
+
+/// Expands CPM's shorthands (`gh:`, `gl:` and `bb:` repositories, or a git or archive URL,
+/// followed by `@version` and/or `#tag`) into the arguments they stand for
+fn expand_cpm_uri(uri: &str, arguments: &mut BTreeMap<String, Vec<String>>) {
+    let (uri, tag) = match uri.split_once('#') {
+        Some((uri, tag)) => (uri, Some(tag)),
+        None => (uri, None),
+    };
+    // The `@` of `git@host:` isn't followed by a version
+    let (location, version) = match uri.rsplit_once('@') {
+        Some((location, version)) if !version.contains(['/', ':']) => (location, Some(version)),
+        _ => (uri, None),
+    };
+
+    let (keyword, location) = if let Some(repository) = location.strip_prefix("gh:") {
+        ("GITHUB_REPOSITORY", repository)
+    } else if let Some(repository) = location.strip_prefix("gl:") {
+        ("GITLAB_REPOSITORY", repository)
+    } else if let Some(repository) = location.strip_prefix("bb:") {
+        ("BITBUCKET_REPOSITORY", repository)
+    } else if location.ends_with(".git") {
+        ("GIT_REPOSITORY", location)
+    } else {
+        ("URL", location)
+    };
+
+    let mut set = |keyword: &str, value: &str| {
+        arguments
+            .entry(keyword.to_string())
+            .or_insert_with(|| vec![value.to_string()]);
+    };
+    set(keyword, location);
+    if let Some(version) = version {
+        set("VERSION", version);
+    }
+    if let Some(tag) = tag {
+        set("GIT_TAG", tag);
+    }
+}
+
+/// A dependency on a git repository, through `host` and `repository` when the URL has the
+/// usual `https://{host}/{owner}/{repo}` shape
+fn git_dependency(
+    name: &str,
+    url: &str,
+    revision: Option<String>,
+) -> Result<DependencyData, String> {
+    if [name, url, revision.as_deref().unwrap_or_default()]
+        .iter()
+        .any(|value| value.contains("${"))
+    {
+        return Err("uses CMake variables".to_string());
+    }
+
+    let mut data = DependencyData::default();
+    data.name = name.to_string();
+
+    match get_repository(url) {
+        Some((host, owner, repo)) => {
+            // Without a tag, CPM and FetchContent take the default branch, which is best
+            // approximated by the newest release on GitHub
+            data.revision = match host.as_str() {
+                "github.com" => revision.or_else(|| Some(LATEST_RELEASE.to_string())),
+                _ => revision,
+            };
+            data.host = Some(host);
+            data.repository = (owner, repo);
+        }
+        None => {
+            data.host = None;
+            data.git = Some(url.to_string());
+            data.revision = revision;
+        }
+    }
+
+    Ok(data)
+}
+
+/// A dependency on a `.tar.gz` archive, checked against a `SHA256=` hash when there is one
+fn archive_dependency(
+    name: &str,
+    url: &str,
+    hash: Option<String>,
+) -> Result<DependencyData, String> {
+    if name.contains("${") || url.contains("${") {
+        return Err("uses CMake variables".to_string());
+    }
+    if !url.ends_with(".tar.gz") && !url.ends_with(".tgz") {
+        return Err("only .tar.gz archives are supported".to_string());
+    }
+
+    let mut data = DependencyData::default();
+    data.name = name.to_string();
+    data.host = None;
+    data.url = Some(url.to_string());
+    data.checksum = hash
+        .as_deref()
+        .and_then(|hash| hash.strip_prefix("SHA256="))
+        .map(str::to_lowercase);
+
+    Ok(data)
+}
+
+/// Reads CPM's `OPTIONS "NAME VALUE"` pairs
+fn get_options<'a>(options: impl Iterator<Item = &'a String>) -> BTreeMap<String, OptionValue> {
+    options
+        .filter_map(|option| option.split_once(' '))
+        .map(|(name, value)| {
+            let value = value.trim();
+            let value = match value.to_uppercase().as_str() {
+                "ON" | "TRUE" | "YES" => OptionValue::Bool(true),
+                "OFF" | "FALSE" | "NO" => OptionValue::Bool(false),
+                _ => match value.parse::<i64>() {
+                    Ok(value) => OptionValue::Integer(value),
+                    Err(_) => OptionValue::String(value.to_string()),
+                },
+            };
+
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// Splits `https://{host}/{owner}/{repo}[.git]` into its host, owner and repository name
+fn get_repository(url: &str) -> Option<(String, String, String)> {
+    let (host, path) = url.strip_prefix("https://")?.split_once('/')?;
+    let path = path.trim_end_matches('/');
+    let (owner, repo) = path.strip_suffix(".git").unwrap_or(path).split_once('/')?;
+
+    if owner.is_empty() || repo.is_empty() || repo.contains('/') {
+        return None;
+    }
+
+    Some((host.to_string(), owner.to_string(), repo.to_string()))
+}
+
+/// The last path component of a repository URL, without `.git`
+fn get_repository_name(url: &str) -> Option<String> {
+    let name = url.trim_end_matches('/').rsplit(['/', ':']).next()?;
+    let name = name.strip_suffix(".git").unwrap_or(name);
+
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// An argument of a CMake command invocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CMakeArgument {
+    text: String,
+    /// Quoted and bracket arguments are never keywords
+    quoted: bool,
+}
+
+/// A command invocation, named in lowercase since CMake matches command names case-insensitively
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CMakeCommand {
+    name: String,
+    arguments: Vec<CMakeArgument>,
+}
+
+/// Reads every command invocation of a CMake file along with its raw arguments, whether or not
+/// the command is a built-in one
+fn read_cmake_commands(source: &str) -> Result<Vec<CMakeCommand>, String> {
+    let chars = source.chars().collect::<Vec<char>>();
+    let line = |i: usize| chars[..i].iter().filter(|c| **c == '\n').count() + 1;
+    let mut commands = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' {
+            i = skip_cmake_comment(&chars, i).map_err(|e| format!("line {}: {}", line(i), e))?;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let name = chars[start..i].iter().collect::<String>().to_lowercase();
+
+            while i < chars.len() && matches!(chars[i], ' ' | '\t') {
+                i += 1;
+            }
+            if chars.get(i) != Some(&'(') {
+                return Err(format!(
+                    "line {}: expected `(` after `{}`",
+                    line(start),
+                    name
+                ));
+            }
+
+            let (arguments, end) = read_cmake_arguments(&chars, i + 1)
+                .map_err(|e| format!("line {}: {}", line(start), e))?;
+            commands.push(CMakeCommand { name, arguments });
+            i = end;
+        } else {
+            return Err(format!("line {}: unexpected `{}`", line(i), c));
+        }
+    }
+
+    Ok(commands)
+}
+
+/// Reads the arguments of a command invocation starting after its `(`, returning them with the
+/// index after the closing `)`. Nested parentheses are kept as arguments of their own, like
+/// CMake passes them to `if()`.
+fn read_cmake_arguments(
+    chars: &[char],
+    mut i: usize,
+) -> Result<(Vec<CMakeArgument>, usize), String> {
+    let mut arguments = Vec::new();
+    let mut depth = 0;
+
+    loop {
+        let Some(&c) = chars.get(i) else {
+            return Err("missing `)`".to_string());
+        };
+
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '#' => i = skip_cmake_comment(chars, i)?,
+            ')' if depth == 0 => return Ok((arguments, i + 1)),
+            '(' | ')' => {
+                depth = if c == '(' { depth + 1 } else { depth - 1 };
+                arguments.push(CMakeArgument {
+                    text: c.to_string(),
+                    quoted: false,
+                });
+                i += 1;
+            }
+            '"' => {
+                let mut text = String::new();
+                i += 1;
+
+                loop {
+                    match chars.get(i) {
+                        None => return Err("unterminated quoted argument".to_string()),
+                        Some('"') => break,
+                        // A backslash at the end of a line continues the argument on the next
+                        Some('\\') if chars.get(i + 1) == Some(&'\n') => i += 2,
+                        Some('\\') => {
+                            text.push(unescape(chars.get(i + 1).copied())?);
+                            i += 2;
+                        }
+                        Some(&c) => {
+                            text.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+
+                arguments.push(CMakeArgument { text, quoted: true });
+                i += 1;
+            }
+            '[' if bracket_level(chars, i).is_some() => {
+                let (text, end) = read_bracket(chars, i)?;
+                arguments.push(CMakeArgument {
+                    text: text.strip_prefix('\n').unwrap_or(&text).to_string(),
+                    quoted: true,
+                });
+                i = end;
+            }
+            _ => {
+                let mut text = String::new();
+
+                while let Some(&c) = chars.get(i) {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '#' | '"') {
+                        break;
+                    }
+
+                    if c == '\\' {
+                        text.push(unescape(chars.get(i + 1).copied())?);
+                        i += 2;
+                    } else {
+                        text.push(c);
+                        i += 1;
+                    }
+                }
+
+                arguments.push(CMakeArgument {
+                    text,
+                    quoted: false,
+                });
+            }
+        }
+    }
+}
+
+/// Skips the `#` comment starting at `i`, either a bracket comment or the rest of the line
+fn skip_cmake_comment(chars: &[char], i: usize) -> Result<usize, String> {
+    if bracket_level(chars, i + 1).is_some() {
+        return read_bracket(chars, i + 1).map(|(_, end)| end);
+    }
+
+    let end = chars[i..]
+        .iter()
+        .position(|c| *c == '\n')
+        .map_or(chars.len(), |offset| i + offset);
+
+    Ok(end)
+}
+
+/// The number of `=` of the bracket opening `[==[` at `i`, if there is one
+fn bracket_level(chars: &[char], i: usize) -> Option<usize> {
+    if chars.get(i) != Some(&'[') {
+        return None;
+    }
+
+    let level = chars[i + 1..].iter().take_while(|c| **c == '=').count();
+    (chars.get(i + 1 + level) == Some(&'[')).then_some(level)
+}
+
+/// Reads the bracket argument or comment opening at `i`, returning its content and the index
+/// after its closing `]==]`
+fn read_bracket(chars: &[char], i: usize) -> Result<(String, usize), String> {
+    let level = bracket_level(chars, i).unwrap_or_default();
+    let start = i + level + 2;
+    let close = format!("]{}]", "=".repeat(level))
+        .chars()
+        .collect::<Vec<char>>();
+
+    chars[start..]
+        .windows(close.len())
+        .position(|window| window == close.as_slice())
+        .map(|offset| {
+            let content = chars[start..start + offset].iter().collect::<String>();
+            (content, start + offset + close.len())
+        })
+        .ok_or_else(|| "unterminated bracket".to_string())
+}
+
+fn unescape(c: Option<char>) -> Result<char, String> {
+    match c {
+        Some('n') => Ok('\n'),
+        Some('t') => Ok('\t'),
+        Some('r') => Ok('\r'),
+        Some(c) => Ok(c),
+        None => Err("unterminated escape sequence".to_string()),
+    }
+}
+
+/// Lowercases a package name and spells `_` as `-`, so vcpkg's and Conan's names match
+fn normalize_name(name: &str) -> String {
+    name.to_lowercase().replace('_', "-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unquoted(text: &str) -> CMakeArgument {
+        CMakeArgument {
+            text: text.to_string(),
+            quoted: false,
+        }
+    }
+
+    fn quoted(text: &str) -> CMakeArgument {
+        CMakeArgument {
+            text: text.to_string(),
+            quoted: true,
+        }
+    }
+
+    fn arguments(values: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        values
+            .iter()
+            .map(|(keyword, values)| {
+                (
+                    keyword.to_string(),
+                    values.iter().map(|value| value.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn read_cmake_commands_keeps_raw_arguments() {
+        let commands = read_cmake_commands(
+            r#"
+            # CPMAddPackage(commented out)
+            cmake_minimum_required(VERSION 3.21)
+            CPMAddPackage (
+                NAME fmt  #[[ a bracket comment ]]
+                GITHUB_REPOSITORY fmtlib/fmt
+                OPTIONS "FMT_INSTALL ON" [=[FMT_TEST OFF]=]
+                GIT_TAG 10.2.1\;stable
+            )
+            if(NOT (A OR B))
+            endif()
+            "#,
+        )
+        .unwrap();
+
+        let names = commands
+            .iter()
+            .map(|command| command.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            names,
+            ["cmake_minimum_required", "cpmaddpackage", "if", "endif"]
+        );
+        assert_eq!(
+            commands[1].arguments,
+            [
+                unquoted("NAME"),
+                unquoted("fmt"),
+                unquoted("GITHUB_REPOSITORY"),
+                unquoted("fmtlib/fmt"),
+                unquoted("OPTIONS"),
+                quoted("FMT_INSTALL ON"),
+                quoted("FMT_TEST OFF"),
+                unquoted("GIT_TAG"),
+                unquoted("10.2.1;stable"),
+            ]
+        );
+        assert_eq!(
+            commands[2].arguments,
+            [
+                unquoted("NOT"),
+                unquoted("("),
+                unquoted("A"),
+                unquoted("OR"),
+                unquoted("B"),
+                unquoted(")"),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_cmake_commands_reports_syntax_errors() {
+        assert_eq!(
+            read_cmake_commands("project(app)\nadd_library(app\n").unwrap_err(),
+            "line 2: missing `)`"
+        );
+        assert_eq!(
+            read_cmake_commands("message(\"unterminated)").unwrap_err(),
+            "line 1: unterminated quoted argument"
+        );
+        assert_eq!(
+            read_cmake_commands("set VALUE").unwrap_err(),
+            "line 1: expected `(` after `set`"
+        );
+    }
+
+    #[test]
+    fn get_arguments_groups_by_unquoted_keywords() {
+        let grouped = get_arguments(
+            &[
+                unquoted("fmt"),
+                unquoted("GIT_REPOSITORY"),
+                unquoted("https://github.com/fmtlib/fmt.git"),
+                quoted("GIT_TAG"),
+                unquoted("GIT_TAG"),
+                unquoted("10.2.1"),
+                unquoted("GIT_SHALLOW"),
+            ],
+            FETCH_CONTENT_KEYWORDS,
+        );
+
+        assert_eq!(
+            grouped,
+            arguments(&[
+                ("", &["fmt"]),
+                (
+                    "GIT_REPOSITORY",
+                    &["https://github.com/fmtlib/fmt.git", "GIT_TAG"]
+                ),
+                ("GIT_TAG", &["10.2.1"]),
+                ("GIT_SHALLOW", &[]),
+            ])
+        );
+    }
+
+    #[test]
+    fn expand_cpm_uri_expands_shorthands() {
+        let expand = |uri: &str| {
+            let mut arguments = BTreeMap::new();
+            expand_cpm_uri(uri, &mut arguments);
+            arguments
+        };
+
+        assert_eq!(
+            expand("gh:fmtlib/fmt@10.2.1"),
+            arguments(&[
+                ("GITHUB_REPOSITORY", &["fmtlib/fmt"]),
+                ("VERSION", &["10.2.1"])
+            ])
+        );
+        assert_eq!(
+            expand("gl:group/project#main"),
+            arguments(&[
+                ("GITLAB_REPOSITORY", &["group/project"]),
+                ("GIT_TAG", &["main"])
+            ])
+        );
+        assert_eq!(
+            expand("git@example.com:team/lib.git@1.0#v1.0.1"),
+            arguments(&[
+                ("GIT_REPOSITORY", &["git@example.com:team/lib.git"]),
+                ("GIT_TAG", &["v1.0.1"]),
+                ("VERSION", &["1.0"]),
+            ])
+        );
+        assert_eq!(
+            expand("https://example.com/zlib-1.3.tar.gz"),
+            arguments(&[("URL", &["https://example.com/zlib-1.3.tar.gz"])])
+        );
+    }
+
+    #[test]
+    fn expand_cpm_uri_keeps_explicit_arguments() {
+        let mut explicit = arguments(&[("VERSION", &["11.0.0"])]);
+        expand_cpm_uri("gh:fmtlib/fmt@10.2.1", &mut explicit);
+
+        assert_eq!(explicit["VERSION"], ["11.0.0"]);
+    }
+
+    #[test]
+    fn import_cmake_file_imports_cpm_and_fetch_content_calls() {
+        let mut report = ImportReport::default();
+        import_cmake_file(
+            "CMakeLists.txt",
+            r#"
+            add_compile_options(-Wall)
+            cpmaddpackage("gh:fmtlib/fmt#10.2.1")
+            CPMFindPackage(NAME spdlog GITHUB_REPOSITORY gabime/spdlog VERSION 1.14.1
+                OPTIONS "SPDLOG_FMT_EXTERNAL ON")
+            FetchContent_Declare(
+                json
+                URL https://github.com/nlohmann/json/releases/download/v3.11.3/json.tar.xz
+            )
+            FetchContent_Declare(zlib SVN_REPOSITORY svn://example.com/zlib)
+            "#,
+            &mut report,
+        );
+
+        let imported = report
+            .imported
+            .iter()
+            .map(|imported| {
+                let data = &imported.dependency;
+                (
+                    imported.origin.as_str(),
+                    data.name.as_str(),
+                    data.repository.1.as_str(),
+                    data.revision.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            imported,
+            [
+                (
+                    "CMakeLists.txt (CPMAddPackage)",
+                    "fmt",
+                    "fmt",
+                    Some("10.2.1")
+                ),
+                (
+                    "CMakeLists.txt (CPMAddPackage)",
+                    "spdlog",
+                    "spdlog",
+                    Some("v1.14.1")
+                ),
+            ]
+        );
+        assert_eq!(
+            report.imported[1].dependency.options.len(),
+            1,
+            "{:?}",
+            report.imported[1].dependency.options
+        );
+
+        let unmapped = report
+            .unmapped
+            .iter()
+            .map(|unmapped| (unmapped.name.as_str(), unmapped.reason.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            unmapped,
+            [
+                ("json", "only .tar.gz archives are supported"),
+                ("zlib", "only git repositories and archives are supported"),
+            ]
+        );
+        assert!(report.warnings.is_empty());
+    }
+}
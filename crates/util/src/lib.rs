@@ -1,12 +1,22 @@
+use crate::cfg::Platform;
+use crate::context::ProjectContext;
 use crate::generator::{configure_cmake_project, generate_project};
 use crate::model::Generator;
-use crate::model::{Language, ProjectConfig};
-use inflector::Inflector;
+use crate::model::{
+    Dependency, DependencyData, FeatureSelection, Language, ProjectConfig, ProjectOverrides,
+    ProjectTargetKind, ResolvedFeatures, TargetSelection, TestFramework,
+};
+use crate::staging::ArtifactManifest;
+use crate::testing::{TestOptions, TestStatus};
 use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::process::Command as ACommand;
+use std::process::ExitStatus;
 use std::str::FromStr;
 use std::{
+    env,
     env::current_dir,
     fs,
     fs::read_to_string,
@@ -17,9 +27,17 @@ use std::{
     thread,
 };
 
+pub mod cfg;
+pub mod context;
+pub mod file_api;
+pub mod fingerprint;
 pub mod generator;
+pub mod github;
 pub mod impls;
+pub mod import;
 pub mod model;
+pub mod staging;
+pub mod testing;
 
 lazy_static! {
     pub static ref AVAILABLE_THREADS: usize = {
@@ -75,6 +93,57 @@ pub fn get_cmake_version() -> Result<String, Error> {
         .ok_or_else(|| Error::new(ErrorKind::Other, "Failed to get CMake version"))
 }
 
+pub fn get_compiler_version(compiler: &str) -> Result<String, Error> {
+    let output = Command::new(compiler).arg("--version").output()?;
+    let output_str = String::from_utf8_lossy(&output.stdout);
+
+    output_str
+        .lines()
+        .next()
+        .map(|version| version.trim().to_string())
+        .ok_or_else(|| Error::other(format!("Failed to get {} version", compiler)))
+}
+
+/// The directory c3pm keeps caches shared between projects in
+pub fn get_cache_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("C3PM_CACHE_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    if let Some(dir) = env::var_os("XDG_CACHE_HOME") {
+        return PathBuf::from(dir).join("c3pm");
+    }
+
+    if let Some(dir) = env::var_os("LOCALAPPDATA") {
+        return PathBuf::from(dir).join("c3pm");
+    }
+
+    match env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".cache").join("c3pm"),
+        None => env::temp_dir().join("c3pm"),
+    }
+}
+
+/// The directory c3pm keeps user configuration (like signing keys) in
+pub fn get_config_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("C3PM_CONFIG_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        return PathBuf::from(dir).join("c3pm");
+    }
+
+    if let Some(dir) = env::var_os("APPDATA") {
+        return PathBuf::from(dir).join("c3pm");
+    }
+
+    match env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".config").join("c3pm"),
+        None => env::temp_dir().join("c3pm"),
+    }
+}
+
 pub fn is_c3pm_project(path: &str) -> io::Result<bool> {
     let c3pm_project_base_files: Vec<String> = vec![
         ".cpppm.toml".to_string(),
@@ -112,6 +181,8 @@ pub fn create_new_project(
     generator: Option<Generator>,
     language: Language,
     folder: Option<String>,
+    test_framework: Option<TestFramework>,
+    overrides: &ProjectOverrides,
 ) -> Result<Result<(), String>, String> {
     let folder_name = match folder {
         Some(folder) => folder,
@@ -121,115 +192,230 @@ pub fn create_new_project(
     let current_dir = get_current_path().map_err(|e| e.to_string())?;
     let dir = create_dir(&current_dir, &folder_name).map_err(|e| e.to_string())?;
 
-    match generator {
-        Some(generator) => {
-            generate_project(dir, name, Some(generator), language).map_err(|e| e.to_string())?;
-        }
-        None => generate_project(dir, name, None, language).map_err(|e| e.to_string())?,
-    }
+    generate_project(dir, name, generator, language, test_framework, overrides)
+        .map_err(|e| e.to_string())?;
 
     Ok(Ok(()))
 }
 
 pub fn build_project(
+    context: &ProjectContext,
     jobs: &usize,
-    config: &String,
-    generator: Option<Generator>,
+    features: &FeatureSelection,
+    selection: &TargetSelection,
 ) -> Result<(), String> {
-    let current_dir = get_current_path().map_err(|e| e.to_string())?;
-    let config_path = Path::new(&current_dir).join(".cpppm.toml");
-
-    configure_cmake_project(&current_dir, generator).expect("Failed to configure cmake project");
-
-    let project_config = load_project_config(&config_path)?;
-    let build_dir = project_config
-        .get_build_dir()
-        .unwrap_or_else(|| "build".to_string());
-    let build_dir_str = build_dir.as_str();
-
-    let output = Command::new("cmake")
-        .args(&[
-            "--build",
-            build_dir_str,
-            "--parallel",
-            &jobs.to_string(),
-            "--config",
-            &config.to_string(),
-        ])
-        .output()
-        .map_err(|e| e.to_string())?;
+    let targets = context.config.select_targets(&context.root, selection)?;
+    let status = configure_cmake_project(context, features)
+        .map_err(|e| format!("Failed to configure cmake project: {}", e))?;
+
+    if !status.success() {
+        return Err(format!("Failed to configure cmake project: {}", status));
+    }
+
+    let mut command = Command::new("cmake");
+    command.arg("--build").arg(&context.build_dir).args([
+        "--parallel",
+        &jobs.to_string(),
+        "--config",
+        &context.profile.build_type.to_string(),
+    ]);
+
+    for target in &targets {
+        command.arg("--target").arg(target);
+    }
+
+    let output = command.output().map_err(|e| e.to_string())?;
 
     if !output.status.success() {
         return Err(String::from_utf8_lossy(&output.stderr).to_string());
     }
 
-    println!("{}", String::from_utf8_lossy(&output.stdout));
+    // Like the configure step's, so the stdout of `cpppm run` is only the program's
+    eprintln!("{}", String::from_utf8_lossy(&output.stdout));
 
-    move_built_object_files(config, &current_dir, &build_dir_str.to_string())?;
+    stage_build_artifacts(context)?;
 
     Ok(())
 }
 
-pub fn clean_project() -> io::Result<()> {
-    let current_dir = get_current_path()?;
-    let config_path = Path::new(&current_dir).join(".cpppm.toml");
-    let project_cfg = load_project_config(&config_path).unwrap();
-    let build_dir = project_cfg.get_build_dir().expect("Fuck");
-    let target_path = Path::new(&"target");
-    let build_path = Path::new(&build_dir);
+/// Builds the selected executable, which is the project's only binary when neither `bin` nor
+/// `example` is given, then runs it with the terminal's stdin, stdout and stderr, from
+/// `working_dir` or the current directory
+pub fn run_project(
+    context: &ProjectContext,
+    jobs: &usize,
+    features: &FeatureSelection,
+    bin: Option<String>,
+    example: Option<String>,
+    working_dir: Option<&Path>,
+    args: &[String],
+) -> Result<ExitStatus, String> {
+    let selection = match (bin, example) {
+        (_, Some(example)) => TargetSelection {
+            examples: vec![example],
+            ..TargetSelection::default()
+        },
+        (Some(bin), None) => TargetSelection {
+            bins: vec![bin],
+            ..TargetSelection::default()
+        },
+        (None, None) => TargetSelection {
+            bins: vec![default_binary(context)?],
+            ..TargetSelection::default()
+        },
+    };
 
-    if build_path.exists() {
-        fs::remove_dir_all(build_path)?;
-    }
+    build_project(context, jobs, features, &selection)?;
 
-    if target_path.exists() {
-        fs::remove_dir_all(target_path)?;
+    let target = selection
+        .bins
+        .first()
+        .or(selection.examples.first())
+        .ok_or("No executable to run")?;
+    let target_dir = context.target_dir();
+    let manifest = ArtifactManifest::load(&target_dir).map_err(|e| e.to_string())?;
+    let executable = manifest
+        .find_executable(target)
+        .ok_or_else(|| format!("`{}` didn't produce an executable", target))?;
+
+    let mut command = Command::new(target_dir.join(&executable.path));
+    command.args(args);
+
+    if let Some(working_dir) = working_dir {
+        command.current_dir(working_dir);
     }
 
-    Ok(())
+    command
+        .status()
+        .map_err(|e| format!("Failed to run `{}`: {}", target, e))
 }
 
-pub fn move_built_object_files(
-    config: &String,
-    current_dir: &String,
-    build_dir: &String,
+/// Builds the tests in `tests/` and runs them with CTest, then sums up how they did along with
+/// the output of the failed ones
+pub fn test_project(
+    context: &ProjectContext,
+    features: &FeatureSelection,
+    options: &TestOptions,
 ) -> Result<(), String> {
-    let valid_extensions = vec!["exe", "so", "dll", "pdb", "a", "o", "lib", "dylib"];
-    let config_snake_case = config.to_string().to_pascal_case();
-    let _build_config_dir = Path::new(&build_dir);
-    let target_dir = Path::new(&current_dir)
-        .join("target")
-        .join(config_snake_case);
+    let tests = context
+        .config
+        .get_targets(&context.root)?
+        .into_iter()
+        .filter(|target| target.kind == ProjectTargetKind::Test)
+        .map(|target| target.name)
+        .collect::<Vec<String>>();
+
+    if tests.is_empty() {
+        return Err("The project has no tests! Add them to `tests/`".to_string());
+    }
 
-    fs::create_dir_all(&target_dir).map_err(|e| e.to_string())?;
+    let selection = TargetSelection {
+        tests,
+        ..TargetSelection::default()
+    };
 
-    let mut moved_files: Vec<PathBuf> = Vec::new();
-    let entries = walkdir::WalkDir::new(build_dir.clone());
+    build_project(context, &options.jobs, features, &selection)?;
 
-    for entry in entries.into_iter().filter_map(|e| e.ok()) {
-        let file_name = entry.file_name();
+    let build_type = context.profile.build_type.to_string();
+    let report = testing::run_ctest(&context.build_dir, &build_type, options)
+        .map_err(|e| format!("Failed to run tests: {}", e))?;
 
-        // if file_name.to_str().unwrap().contains(current_dir.as_str()) {
-        if let Some(extension) = entry.clone().path().extension() {
-            if valid_extensions.contains(&extension.to_str().unwrap()) {
-                let source_path = entry.path();
-                let dest_path = target_dir.join(&file_name);
+    if report.tests.is_empty() {
+        println!("No tests matched.");
+        return Ok(());
+    }
 
-                fs::rename(&source_path, &dest_path)
-                    .map_err(|e| format!("Failed to move file: {}", e))?;
+    for test in &report.tests {
+        let status = match test.status {
+            TestStatus::Passed => "ok",
+            TestStatus::Failed => "FAILED",
+            TestStatus::Timeout => "TIMEOUT",
+            TestStatus::Skipped => "skipped",
+        };
 
-                moved_files.push(dest_path);
-            }
-        }
-        // }
+        println!("test {} ... {}", test.name, status);
+    }
+
+    for test in report.failures() {
+        println!("\n---- {} output ----\n{}", test.name, test.output);
+    }
+
+    let failed = report.failures().count();
+
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} skipped",
+        if failed == 0 { "ok" } else { "FAILED" },
+        report.count(TestStatus::Passed),
+        failed,
+        report.count(TestStatus::Skipped)
+    );
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} of {} tests failed", failed, report.tests.len())),
+    }
+}
+
+/// The binary `cpppm run` runs when none is picked: the only one, or the one named after the
+/// project
+fn default_binary(context: &ProjectContext) -> Result<String, String> {
+    let name = context.config.get_name();
+    let bins = context
+        .config
+        .get_targets(&context.root)?
+        .into_iter()
+        .filter(|target| target.kind == ProjectTargetKind::Executable)
+        .map(|target| target.name)
+        .collect::<Vec<String>>();
+
+    match bins.as_slice() {
+        [bin] => Ok(bin.clone()),
+        [] => Err("The project has no binary to run!".to_string()),
+        _ if bins.iter().any(|bin| bin == name) => Ok(name.to_string()),
+        _ => Err(format!(
+            "The project has several binaries, pick one with `--bin`! Possible values are: {}",
+            bins.join(", ")
+        )),
+    }
+}
+
+/// Removes the build directories and staged artifacts of every profile
+pub fn clean_project(context: &ProjectContext) -> io::Result<()> {
+    let target_path = context.root.join("target");
+
+    if context.build_root.exists() {
+        fs::remove_dir_all(&context.build_root)?;
+    }
+
+    if target_path.exists() {
+        fs::remove_dir_all(target_path)?;
     }
 
-    if moved_files.is_empty() {
-        return Err("No valid output files found to move.".to_string());
+    Ok(())
+}
+
+/// Stages the artifacts of the project's targets into `target/<profile>`, as listed by the CMake
+/// File API codemodel of the profile's build directory
+pub fn stage_build_artifacts(context: &ProjectContext) -> Result<(), String> {
+    let target_dir = context.target_dir();
+    let mode = context.config.artifacts.clone().unwrap_or_default().staging;
+    let build_type = context.profile.build_type.to_string();
+
+    let artifacts =
+        file_api::find_artifacts(&context.build_dir, &build_type).map_err(|e| e.to_string())?;
+    let report =
+        staging::stage_artifacts(&artifacts, &target_dir, mode).map_err(|e| e.to_string())?;
+
+    if report.manifest.artifacts.is_empty() {
+        return Err("No build artifacts found to stage.".to_string());
     }
 
-    for moved_file in moved_files {
-        println!("Moved file: {}", moved_file.display());
+    for staged_file in &report.staged {
+        eprintln!("Staged file: {}", staged_file.display());
+    }
+
+    for removed_file in &report.removed {
+        eprintln!("Removed stale file: {}", removed_file.display());
     }
 
     Ok(())
@@ -244,10 +430,237 @@ pub fn load_project_config(cfg_path: &Path) -> Result<ProjectConfig, String> {
     toml::de::from_str(&cfg_str).map_err(|e| format!("Error parsing config file: {}", e))
 }
 
+/// Appends a `[[deps]]` (or `[[dev-deps]]`) entry for the GitHub repository `spec`
+/// (`owner/repo[@revision]`) to the project's config, pinned to the newest release when no
+/// revision is given. The config is appended to rather than rewritten to keep its formatting.
+pub fn add_dependency(
+    project_root: &Path,
+    spec: &str,
+    name: Option<String>,
+    host: Option<String>,
+    dev: bool,
+) -> Result<DependencyData, String> {
+    let config_path = project_root.join(".cpppm.toml");
+    let config = load_project_config(&config_path)?;
+
+    let (repository, revision) = match spec.split_once('@') {
+        Some((repository, revision)) => (repository, Some(revision.to_string())),
+        None => (spec, None),
+    };
+    let (owner, repo) = repository
+        .split_once('/')
+        .filter(|(owner, repo)| !owner.is_empty() && !repo.is_empty() && !repo.contains('/'))
+        .ok_or_else(|| format!("Expected `owner/repo[@revision]`, got `{}`", spec))?;
+
+    let name = name.unwrap_or_else(|| repo.to_string());
+    let existing = config
+        .dependencies
+        .iter()
+        .chain(config.dev_dependencies.iter())
+        .flatten()
+        .any(|dependency| dependency.dependency.name == name);
+    if existing || config.packages.contains_key(&name) {
+        return Err(format!("The project already depends on `{}`", name));
+    }
+
+    let host = host.unwrap_or_else(|| "github.com".to_string());
+    let revision = match revision {
+        Some(revision) => revision,
+        None => {
+            let github = config.github.clone().unwrap_or_default();
+            let api_url = github::api_url(&github, Some(&host));
+
+            github::get_latest_release(&api_url, owner, repo, github.prereleases)?
+        }
+    };
+
+    let data = DependencyData::new(
+        name,
+        Some(revision),
+        Some(host),
+        (owner.to_string(), repo.to_string()),
+    );
+    let dependency = Dependency {
+        dependency: data.clone(),
+    };
+    match dev {
+        true => append_dependencies(&config_path, &[], &[dependency])?,
+        false => append_dependencies(&config_path, &[dependency], &[])?,
+    }
+
+    Ok(data)
+}
+
+/// Appends `[[deps]]` and `[[dev-deps]]` entries to the end of the config at `config_path`,
+/// leaving the rest of the file (and its formatting) untouched
+pub fn append_dependencies(
+    config_path: &Path,
+    dependencies: &[Dependency],
+    dev_dependencies: &[Dependency],
+) -> Result<(), String> {
+    let table = BTreeMap::from([("deps", dependencies), ("dev-deps", dev_dependencies)])
+        .into_iter()
+        .filter(|(_, dependencies)| !dependencies.is_empty())
+        .collect::<BTreeMap<&str, &[Dependency]>>();
+    if table.is_empty() {
+        return Ok(());
+    }
+
+    let entries = toml::to_string(&table).map_err(|e| e.to_string())?;
+
+    let mut contents = read_to_string(config_path).map_err(|e| e.to_string())?;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push('\n');
+    contents.push_str(&entries);
+
+    fs::write(config_path, contents).map_err(|e| e.to_string())
+}
+
+/// Collects the project's enabled dependencies and the dependencies of every fetched c3pm
+/// dependency, ordered so that each dependency comes after the dependencies it needs
+pub fn collect_dependencies(
+    project_root: &Path,
+    config: &ProjectConfig,
+    features: &ResolvedFeatures,
+    platform: &Platform,
+) -> Vec<Dependency> {
+    let mut collected: Vec<Dependency> = Vec::new();
+    let mut visiting: Vec<String> = Vec::new();
+    let deps_dir = project_root.join("deps");
+    let locked = load_locked_dependencies(project_root);
+
+    for dependency in &config.get_dependencies() {
+        if features.is_dependency_enabled(dependency) {
+            collect_dependency(
+                &deps_dir,
+                dependency,
+                platform,
+                &locked,
+                &mut visiting,
+                &mut collected,
+            );
+        }
+    }
+
+    collected
+}
+
+/// Collects the project's dev-dependencies and any of their dependencies that the regular
+/// dependencies don't already pull in. Dev-dependencies of dependencies are never collected.
+pub fn collect_dev_dependencies(
+    project_root: &Path,
+    config: &ProjectConfig,
+    features: &ResolvedFeatures,
+    platform: &Platform,
+) -> Vec<Dependency> {
+    let mut collected = collect_dependencies(project_root, config, features, platform);
+    let regular_count = collected.len();
+    let mut visiting: Vec<String> = Vec::new();
+    let deps_dir = project_root.join("deps");
+    let locked = load_locked_dependencies(project_root);
+
+    for dependency in config.dev_dependencies.iter().flatten() {
+        if features.is_dependency_enabled(dependency) {
+            collect_dependency(
+                &deps_dir,
+                dependency,
+                platform,
+                &locked,
+                &mut visiting,
+                &mut collected,
+            );
+        }
+    }
+
+    collected.split_off(regular_count)
+}
+
+#[derive(Deserialize)]
+struct LockedDependencies {
+    #[serde(default)]
+    package: Vec<LockedDependency>,
+}
+
+#[derive(Deserialize)]
+struct LockedDependency {
+    name: String,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+/// Reads the dependency edges recorded in `cpppm.lock`, which also cover the dependencies of
+/// registry packages that only their index declares
+fn load_locked_dependencies(project_root: &Path) -> BTreeMap<String, Vec<String>> {
+    let lockfile = read_to_string(project_root.join("cpppm.lock"))
+        .ok()
+        .and_then(|contents| toml::de::from_str::<LockedDependencies>(&contents).ok());
+
+    lockfile
+        .map(|lockfile| {
+            lockfile
+                .package
+                .into_iter()
+                .map(|package| (package.name, package.dependencies))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn collect_dependency(
+    deps_dir: &Path,
+    dependency: &Dependency,
+    platform: &Platform,
+    locked: &BTreeMap<String, Vec<String>>,
+    visiting: &mut Vec<String>,
+    collected: &mut Vec<Dependency>,
+) {
+    let name = &dependency.dependency.name;
+
+    if visiting.contains(name) || collected.iter().any(|d| &d.dependency.name == name) {
+        return;
+    }
+
+    visiting.push(name.clone());
+
+    // Dependencies of dependencies are built with their default features
+    let dep_config = load_project_config(&deps_dir.join(name).join(".cpppm.toml"))
+        .and_then(|dep_config| dep_config.for_platform(platform));
+
+    if let Ok((dep_config, _)) = dep_config {
+        let dep_features = dep_config
+            .resolve_features(&FeatureSelection::default())
+            .unwrap_or_default();
+
+        for transitive in &dep_config.get_dependencies() {
+            if dep_features.is_dependency_enabled(transitive) {
+                collect_dependency(deps_dir, transitive, platform, locked, visiting, collected);
+            }
+        }
+    }
+
+    for locked_name in locked.get(name).into_iter().flatten() {
+        let transitive = Dependency {
+            dependency: DependencyData {
+                name: locked_name.clone(),
+                ..DependencyData::default()
+            },
+        };
+
+        collect_dependency(deps_dir, &transitive, platform, locked, visiting, collected);
+    }
+
+    visiting.pop();
+    collected.push(dependency.clone());
+}
+
 pub fn init_project_subcommand(
     name: Option<String>,
     generator: Option<Generator>,
     language: Language,
+    test_framework: Option<TestFramework>,
+    overrides: &ProjectOverrides,
 ) -> Result<(), String> {
     let current_dir =
         PathBuf::from_str(get_current_path().map_err(|e| e.to_string())?.as_str()).unwrap();
@@ -267,18 +680,30 @@ pub fn init_project_subcommand(
         generator,
         language,
         Some(current_dir.to_str().unwrap().to_string()),
+        test_framework,
+        overrides,
     )??;
     Ok(())
 }
 
-pub fn reconfigure_project_subcommand(generator: Option<Generator>) {
-    clean_project().expect("fuck");
+/// Removes the selected profile's build directory and staged artifacts, and configures it from
+/// scratch
+pub fn reconfigure_project_subcommand(
+    context: &ProjectContext,
+    features: &FeatureSelection,
+) -> Result<(), String> {
+    for dir in [&context.build_dir, &context.target_dir()] {
+        if dir.exists() {
+            fs::remove_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+    }
+
+    let status = configure_cmake_project(context, features).map_err(|e| e.to_string())?;
 
-    match generator {
-        Some(gen) => configure_cmake_project(&get_current_path().expect("fuck"), Some(gen)),
-        None => configure_cmake_project(&get_current_path().unwrap(), None),
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("Failed to configure cmake project: {}", status)),
     }
-    .expect("owo");
 }
 
 pub fn parse_language(lang: &str) -> Result<Language, String> {
@@ -1,6 +1,6 @@
-use clap::ValueEnum;
+use clap::{Args, ValueEnum};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Formatter;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -82,8 +82,86 @@ pub struct Dependency {
 pub struct DependencyData {
     pub name: String,
     pub host: Option<String>,
+    #[serde(default, skip_serializing_if = "is_unset_repository")]
     pub repository: (String, String),
     pub revision: Option<String>,
+    /// A full git URL, used instead of `host` and `repository`
+    pub git: Option<String>,
+    /// A source archive (`.tar.gz`) to download instead of cloning a repository
+    pub url: Option<String>,
+    /// The SHA-256 of the source archive
+    pub checksum: Option<String>,
+    /// The registry version this dependency was resolved to, or the minimum version of a system
+    /// dependency
+    pub version: Option<String>,
+    /// The index URL of the registry this dependency was resolved from
+    pub registry: Option<String>,
+    /// A local directory to use as the dependency's sources, relative to the project root
+    pub path: Option<String>,
+    /// CMake options set only while the dependency's `add_subdirectory` is processed
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, OptionValue>,
+    /// Overrides the build type the dependency is configured with
+    #[serde(rename = "build-type")]
+    pub build_type: Option<BuildConfig>,
+    /// The CMake targets to link the project against (defaults to a target named after the dependency)
+    pub targets: Option<Vec<String>>,
+    /// Optional dependencies are only fetched and linked when a selected feature enables them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+    /// The name passed to `find_package` when the dependency comes from the binary cache or the
+    /// system (defaults to the dependency name)
+    pub package: Option<String>,
+    /// Set to `false` to always build this dependency from source, even with the binary cache on
+    pub cache: Option<bool>,
+    /// Provided by the system and found with `find_package` instead of being fetched
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub system: bool,
+}
+
+/// A `[packages]` entry, either just a semver requirement (`fmt = "^10"`) or a table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PackageRequirement {
+    Version(String),
+    Detailed(PackageDetails),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackageDetails {
+    /// The semver requirement, which may be left out for path packages until they are packaged
+    pub version: Option<String>,
+    /// The name of the registry in `[registries]` to resolve the package from
+    pub registry: Option<String>,
+    /// A local directory to use instead of the registry, relative to the project root
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, OptionValue>,
+    pub build_type: Option<BuildConfig>,
+    pub targets: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OptionValue {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+fn is_unset_repository(repository: &(String, String)) -> bool {
+    repository.0.is_empty() && repository.1.is_empty()
+}
+
+/// Writes a map's entries in key order, so saved configs don't change from run to run
+fn serialize_sorted<S: serde::Serializer>(
+    map: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
 }
 
 pub fn format_display(f: &mut Formatter<'_>, string: &str) -> std::fmt::Result {
@@ -138,30 +216,367 @@ pub fn parse_generator<T: Copy>(value: &str, pairs: &[(T, &'static str)]) -> Res
     ))
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Project {
     pub name: String,
+    /// The package version, required to package or publish the project
+    pub version: Option<String>,
+    pub description: Option<String>,
+    /// An SPDX license expression
+    pub license: Option<String>,
     pub generator: Option<Generator>,
     pub language: Language,
     pub compiler: CompilerDetails,
+    /// Globs of the files to package, relative to the project root (defaults to the sources,
+    /// headers, CMake files and manifest)
+    pub include: Option<Vec<String>>,
+    /// Globs of files to leave out of the package
+    pub exclude: Option<Vec<String>>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct CompilerDetails {
     pub c_compiler: Option<String>,
     pub cxx_compiler: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ProjectConfig {
     pub project_details: Project,
-    #[serde(default)]
+    #[serde(default, serialize_with = "serialize_sorted")]
     pub dirs: HashMap<String, String>,
     #[serde(rename = "deps")]
     pub dependencies: Option<Vec<Dependency>>,
+    /// Dependencies only used by tests and benchmarks, never propagated to consumers
+    #[serde(rename = "dev-deps")]
+    pub dev_dependencies: Option<Vec<Dependency>>,
+    /// Maps a feature to the features, optional dependencies (`dep:name`) and compile
+    /// definitions (`define:NAME=VALUE`) it enables
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, Vec<String>>,
+    /// Settings that only apply when their `cfg(...)` key matches the host and toolchain
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub target: BTreeMap<String, TargetConfig>,
+    /// Named build profiles, on top of the built-in `debug`, `release`, `relwithdebinfo` and
+    /// `minsizerel` ones
+    #[serde(
+        default,
+        rename = "profile",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+    pub cache: Option<CacheConfig>,
+    /// Dependencies resolved by name and semver requirement from a registry
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub packages: BTreeMap<String, PackageRequirement>,
+    /// Maps registry names to index URLs (a directory, `file://` URL or git repository)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub registries: BTreeMap<String, String>,
+    pub verify: Option<VerifyConfig>,
+    pub github: Option<GithubConfig>,
+    pub licenses: Option<LicensePolicy>,
+    pub audit: Option<AuditConfig>,
+    pub artifacts: Option<ArtifactsConfig>,
+    pub lib: Option<LibConfig>,
+    #[serde(default, rename = "bin", skip_serializing_if = "Vec::is_empty")]
+    pub bins: Vec<BinConfig>,
+}
+
+/// The `[lib]` table, turning the project's sources (except `main` and `src/bin/`) into a
+/// library that every binary links against
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LibConfig {
+    /// The CMake target name (defaults to `<project>_lib`, with the project's name as file name)
+    pub name: Option<String>,
+    #[serde(default)]
+    pub kind: LibKind,
+    /// `file(GLOB_RECURSE)` patterns of the library's sources, relative to the project root
+    pub sources: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LibKind {
+    #[default]
+    Static,
+    Shared,
+    HeaderOnly,
+    Object,
+}
+
+/// A `[[bin]]` table, adding an executable
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BinConfig {
+    pub name: String,
+    /// `file(GLOB_RECURSE)` patterns of the binary's sources, relative to the project root
+    /// (defaults to `src/main.c(pp)` for the binary named after the project, and to
+    /// `src/bin/<name>.c(pp)` for the others)
+    pub sources: Option<Vec<String>>,
+}
+
+/// A CMake target of the project, as defined by `[lib]` and `[[bin]]` or discovered in
+/// `src/bin/`, `examples/`, `tests/` and `benches/`
+#[derive(Debug, Clone)]
+pub struct ProjectTarget {
+    pub name: String,
+    pub kind: ProjectTargetKind,
+    pub sources: Vec<String>,
+    /// A regex of sources matched by `sources` to leave out
+    pub exclude: Option<String>,
+    /// The file name of the artifact, when it differs from the target name
+    pub output_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectTargetKind {
+    Executable,
+    /// Executables only built on demand, like the ones below
+    Example,
+    /// Executables linked with the dev-dependencies
+    Test,
+    Bench,
+    Library(LibKind),
+}
+
+/// The `[artifacts]` table, configuring how built artifacts are staged into `target/<config>`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ArtifactsConfig {
+    #[serde(default)]
+    pub staging: StagingMode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StagingMode {
+    /// Hardlink artifacts out of the build directory, copying them where that isn't possible
+    #[default]
+    Hardlink,
+    Copy,
+}
+
+/// The `[audit]` table, configuring `cpppm audit`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuditConfig {
+    /// The advisory database, a directory or a git repository (`git+<url>` or a URL ending in
+    /// `.git`)
+    pub database: Option<String>,
+    /// The ids of advisories that don't apply to the project
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+/// The `[licenses]` table, checked against the detected license of every fetched dependency
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LicensePolicy {
+    /// SPDX license ids (or globs like `BSD-*`) dependencies may use, anything that isn't denied
+    /// is allowed when empty
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// SPDX license ids (or globs like `GPL-*`) no dependency may use
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// What to do with dependencies whose license can't be detected
+    #[serde(default)]
+    pub unknown: UnknownLicensePolicy,
+    /// Dependencies the policy doesn't apply to, e.g. after their license was reviewed by hand
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnknownLicensePolicy {
+    Allow,
+    /// Accept them with a warning
+    #[default]
+    Warn,
+    Deny,
+}
+
+/// The `[github]` table, configuring how `revision = "latest-release"` is resolved
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GithubConfig {
+    /// The REST API base URL, e.g. `https://github.example.com/api/v3` for GitHub Enterprise
+    pub api_url: Option<String>,
+    /// Whether prereleases count as the latest release
+    #[serde(default)]
+    pub prereleases: bool,
+}
+
+/// The `[verify]` table, configuring the checks depman runs on fetched packages
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct VerifyConfig {
+    /// What to do with registry packages that aren't signed by one of their publishers
+    #[serde(default)]
+    pub signatures: SignaturePolicy,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignaturePolicy {
+    /// Refuse to fetch unsigned packages or packages with invalid signatures
+    Require,
+    /// Fetch them anyway, with a warning
+    #[default]
+    Warn,
+    /// Don't check signatures
+    Off,
+}
+
+/// The `[cache]` table, configuring the binary cache for prebuilt dependencies
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// The local cache directory (defaults to `$C3PM_CACHE_DIR` or the user's cache directory)
+    pub dir: Option<String>,
+    /// A shared cache, either a directory (`file://` or a plain path) or an `http(s)://` URL
+    pub remote: Option<String>,
+    /// Upload dependencies built locally to the remote cache
+    #[serde(default)]
+    pub push: bool,
+}
+
+/// A `[target.'cfg(...)']` table
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TargetConfig {
+    #[serde(rename = "deps")]
+    pub dependencies: Option<Vec<Dependency>>,
+    #[serde(rename = "dev-deps")]
+    pub dev_dependencies: Option<Vec<Dependency>>,
+    #[serde(default)]
+    pub definitions: Vec<String>,
+    #[serde(default)]
+    pub compile_flags: Vec<String>,
+    #[serde(default)]
+    pub link_flags: Vec<String>,
+}
+
+/// The profile used when none is selected
+pub const DEFAULT_PROFILE: &str = "relwithdebinfo";
+
+/// A `[profile.<name>]` table
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProfileConfig {
+    /// The profile this one starts from, a redefined built-in profile starts from the built-in
+    pub inherits: Option<String>,
+    /// The CMake build type (defaults to `Debug` for profiles that don't inherit one)
+    pub build_type: Option<BuildConfig>,
+    /// The optimisation level, `0` to `3`, `s` or `z`
+    pub opt_level: Option<OptionValue>,
+    /// Whether to generate debug info
+    pub debug: Option<bool>,
+    /// Whether to enable link-time optimisation
+    pub lto: Option<bool>,
+    #[serde(default)]
+    pub definitions: Vec<String>,
+    #[serde(default)]
+    pub compile_flags: Vec<String>,
+    #[serde(default)]
+    pub link_flags: Vec<String>,
+}
+
+/// A profile with everything it inherits merged in
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub build_type: BuildConfig,
+    pub opt_level: Option<OptionValue>,
+    pub debug: Option<bool>,
+    pub lto: bool,
+    pub definitions: Vec<String>,
+    pub compile_flags: Vec<String>,
+    pub link_flags: Vec<String>,
+}
+
+/// The features selected on the command line
+#[derive(Debug, Clone, Default, Args)]
+pub struct FeatureSelection {
+    /// Comma-separated list of features to enable
+    #[arg(long, value_delimiter = ',')]
+    pub features: Vec<String>,
+
+    /// Don't enable the `default` feature
+    #[arg(long)]
+    pub no_default_features: bool,
+}
+
+/// Command-line overrides of the build directory, compilers and profile set in the environment
+/// or `.cpppm.toml`
+#[derive(Debug, Clone, Default, Args)]
+pub struct ProjectOverrides {
+    /// The build directory, relative to the project root (overrides `$C3PM_BUILD_DIR` and `build`
+    /// in `[dirs]`)
+    #[arg(long)]
+    pub build_dir: Option<String>,
+
+    /// The C compiler (overrides `$CC` and `c-compiler` in `[project-details.compiler]`)
+    #[arg(long)]
+    pub c_compiler: Option<String>,
+
+    /// The C++ compiler (overrides `$CXX` and `cxx-compiler` in `[project-details.compiler]`)
+    #[arg(long)]
+    pub cxx_compiler: Option<String>,
+
+    /// The build profile, a `[profile.<name>]` table or one of `debug`, `release`,
+    /// `relwithdebinfo` (the default) and `minsizerel` (overrides `$C3PM_PROFILE`)
+    #[arg(long, alias = "config")]
+    pub profile: Option<String>,
+}
+
+/// The targets selected on the command line, all of them when nothing is selected
+#[derive(Debug, Clone, Default, Args)]
+pub struct TargetSelection {
+    /// Only build the binary with this name (may be repeated)
+    #[arg(long = "bin", value_name = "NAME")]
+    pub bins: Vec<String>,
+
+    /// Only build the library
+    #[arg(long)]
+    pub lib: bool,
+
+    /// Only build the example with this name (may be repeated)
+    #[arg(long = "example", value_name = "NAME")]
+    pub examples: Vec<String>,
+
+    /// Only build the examples
+    #[arg(long = "examples")]
+    pub all_examples: bool,
+
+    /// Only build the test with this name (may be repeated)
+    #[arg(long = "test", value_name = "NAME")]
+    pub tests: Vec<String>,
+}
+
+/// The test framework `cpppm new --test-framework` scaffolds `tests/` with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TestFramework {
+    Gtest,
+    Catch2,
+    Doctest,
+    /// Plain executables that fail by returning a non-zero exit code
+    Ctest,
+}
+
+/// The result of resolving a [`FeatureSelection`] against a project's `[features]` table
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedFeatures {
+    pub enabled: BTreeSet<String>,
+    pub dependencies: BTreeSet<String>,
+    pub definitions: Vec<String>,
 }
 
 // pub struct LanguageData {
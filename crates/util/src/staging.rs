@@ -0,0 +1,263 @@
+use crate::file_api::{Artifact, TargetKind};
+use crate::model::StagingMode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+/// The manifest `cpppm build` writes next to the staged artifacts
+pub const MANIFEST_FILE: &str = "artifacts.json";
+
+/// The artifacts staged into a `target/<config>` directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArtifactManifest {
+    pub artifacts: Vec<StagedArtifact>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StagedArtifact {
+    pub target: String,
+    pub kind: TargetKind,
+    /// The staged file, relative to the directory holding the manifest
+    pub path: PathBuf,
+    /// The hex encoded SHA-256 of the file
+    pub sha256: String,
+}
+
+/// What [`stage_artifacts`] did to a `target/<config>` directory
+#[derive(Debug, Default)]
+pub struct StagingReport {
+    pub manifest: ArtifactManifest,
+    /// The artifacts that were staged, because they were new or changed since the last build
+    pub staged: Vec<PathBuf>,
+    /// Artifacts of the last build that no target produces anymore, which were removed
+    pub removed: Vec<PathBuf>,
+}
+
+impl ArtifactManifest {
+    /// Reads the manifest of `target_dir`, which is empty if nothing has been staged there yet
+    pub fn load(target_dir: &Path) -> Result<ArtifactManifest, Error> {
+        let manifest_path = target_dir.join(MANIFEST_FILE);
+
+        if !manifest_path.exists() {
+            return Ok(ArtifactManifest::default());
+        }
+
+        serde_json::from_str(&fs::read_to_string(&manifest_path)?).map_err(|e| {
+            Error::other(format!(
+                "Invalid manifest {}: {}",
+                manifest_path.display(),
+                e
+            ))
+        })
+    }
+
+    /// The staged executable of `target`
+    pub fn find_executable(&self, target: &str) -> Option<&StagedArtifact> {
+        self.artifacts
+            .iter()
+            .find(|artifact| artifact.target == target && artifact.kind == TargetKind::Executable)
+    }
+
+    pub fn save(&self, target_dir: &Path) -> Result<(), Error> {
+        let manifest = serde_json::to_string_pretty(self).map_err(Error::other)?;
+
+        fs::write(target_dir.join(MANIFEST_FILE), format!("{}\n", manifest))
+    }
+}
+
+/// Stages `artifacts` into `target_dir` without touching the build directory, so the next build
+/// stays incremental. Artifacts whose contents haven't changed since the last build are left
+/// alone, and the manifest is rewritten to list the staged files.
+pub fn stage_artifacts(
+    artifacts: &[Artifact],
+    target_dir: &Path,
+    mode: StagingMode,
+) -> Result<StagingReport, Error> {
+    fs::create_dir_all(target_dir)?;
+
+    let previous = ArtifactManifest::load(target_dir)?
+        .artifacts
+        .into_iter()
+        .map(|artifact| (artifact.path.clone(), artifact))
+        .collect::<BTreeMap<PathBuf, StagedArtifact>>();
+    let mut report = StagingReport::default();
+
+    for artifact in artifacts {
+        // Artifacts of targets that weren't built (e.g. `EXCLUDE_FROM_ALL`) don't exist
+        if !artifact.path.is_file() {
+            continue;
+        }
+
+        let path = PathBuf::from(artifact.path.file_name().unwrap_or_default());
+        let dest_path = target_dir.join(&path);
+        let sha256 = sha256_file(&artifact.path)?;
+
+        let is_unchanged = dest_path.is_file()
+            && previous
+                .get(&path)
+                .is_some_and(|staged| staged.sha256 == sha256);
+
+        if !is_unchanged {
+            stage_file(&artifact.path, &dest_path, mode)?;
+            report.staged.push(dest_path);
+        }
+
+        report.manifest.artifacts.push(StagedArtifact {
+            target: artifact.target.clone(),
+            kind: artifact.kind,
+            path,
+            sha256,
+        });
+    }
+
+    for path in previous.keys() {
+        let is_stale = !report
+            .manifest
+            .artifacts
+            .iter()
+            .any(|artifact| &artifact.path == path);
+
+        if is_stale && target_dir.join(path).is_file() {
+            fs::remove_file(target_dir.join(path))?;
+            report.removed.push(target_dir.join(path));
+        }
+    }
+
+    report.manifest.save(target_dir)?;
+
+    Ok(report)
+}
+
+fn stage_file(source: &Path, dest: &Path, mode: StagingMode) -> Result<(), Error> {
+    // Never write through an old hardlink, that would change the file in the build directory
+    if dest.exists() {
+        fs::remove_file(dest)?;
+    }
+
+    // Hardlinks can't cross filesystems, so fall back to copying
+    if mode == StagingMode::Hardlink && fs::hard_link(source, dest).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(source, dest).map(|_| ())
+}
+
+/// The hex encoded SHA-256 of the file at `path`
+pub fn sha256_file(path: &Path) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn artifact(build_dir: &Path, target: &str, file_name: &str, contents: &str) -> Artifact {
+        let path = build_dir.join(file_name);
+        fs::write(&path, contents).unwrap();
+
+        Artifact {
+            target: target.to_string(),
+            kind: TargetKind::Executable,
+            path,
+        }
+    }
+
+    fn modified(path: &Path) -> SystemTime {
+        fs::metadata(path).unwrap().modified().unwrap()
+    }
+
+    #[test]
+    fn unchanged_artifacts_are_not_staged_again() {
+        let build_dir = tempfile::tempdir().unwrap();
+        let target_dir = tempfile::tempdir().unwrap();
+        let app = artifact(build_dir.path(), "app", "app", "v1");
+        let tool = artifact(build_dir.path(), "tool", "tool", "v1");
+
+        let first = stage_artifacts(
+            &[app.clone(), tool.clone()],
+            target_dir.path(),
+            StagingMode::Copy,
+        )
+        .unwrap();
+        assert_eq!(first.staged.len(), 2);
+
+        // Backdate the staged files, so a rewrite would be noticed
+        let staged_at = SystemTime::now() - Duration::from_secs(3600);
+        for name in ["app", "tool"] {
+            File::options()
+                .write(true)
+                .open(target_dir.path().join(name))
+                .unwrap()
+                .set_modified(staged_at)
+                .unwrap();
+        }
+        fs::write(&tool.path, "v2").unwrap();
+
+        let second = stage_artifacts(&[app, tool], target_dir.path(), StagingMode::Copy).unwrap();
+
+        assert_eq!(second.staged, [target_dir.path().join("tool")]);
+        assert!(second.removed.is_empty());
+        assert_eq!(modified(&target_dir.path().join("app")), staged_at);
+        assert_eq!(
+            fs::read_to_string(target_dir.path().join("tool")).unwrap(),
+            "v2"
+        );
+        assert_eq!(
+            ArtifactManifest::load(target_dir.path()).unwrap().artifacts[1].sha256,
+            sha256_file(&target_dir.path().join("tool")).unwrap()
+        );
+    }
+
+    #[test]
+    fn artifacts_no_target_produces_anymore_are_removed() {
+        let build_dir = tempfile::tempdir().unwrap();
+        let target_dir = tempfile::tempdir().unwrap();
+        let app = artifact(build_dir.path(), "app", "app", "app");
+        let old = artifact(build_dir.path(), "old", "old", "old");
+        fs::write(target_dir.path().join("notes.txt"), "kept").unwrap();
+
+        stage_artifacts(
+            &[app.clone(), old],
+            target_dir.path(),
+            StagingMode::Hardlink,
+        )
+        .unwrap();
+        let report = stage_artifacts(&[app], target_dir.path(), StagingMode::Hardlink).unwrap();
+
+        assert_eq!(report.removed, [target_dir.path().join("old")]);
+        assert!(!target_dir.path().join("old").exists());
+        assert!(target_dir.path().join("notes.txt").exists());
+
+        let manifest = ArtifactManifest::load(target_dir.path()).unwrap();
+        assert_eq!(manifest.artifacts.len(), 1);
+        assert_eq!(
+            manifest.find_executable("app").unwrap().path,
+            PathBuf::from("app")
+        );
+    }
+
+    #[test]
+    fn missing_artifacts_are_skipped() {
+        let build_dir = tempfile::tempdir().unwrap();
+        let target_dir = tempfile::tempdir().unwrap();
+        let missing = Artifact {
+            target: "excluded".to_string(),
+            kind: TargetKind::Executable,
+            path: build_dir.path().join("excluded"),
+        };
+
+        let report = stage_artifacts(&[missing], target_dir.path(), StagingMode::Copy).unwrap();
+
+        assert!(report.staged.is_empty());
+        assert!(report.manifest.artifacts.is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+use std::process::Command;
+
+/// How a test registered with CTest ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Timeout,
+    /// The test returned its `SKIP_RETURN_CODE` or a required fixture failed
+    Skipped,
+}
+
+#[derive(Debug, Clone)]
+pub struct TestOutcome {
+    pub name: String,
+    pub status: TestStatus,
+    /// What the test printed
+    pub output: String,
+}
+
+/// What `ctest` ran and how each test ended
+#[derive(Debug, Default)]
+pub struct TestReport {
+    pub tests: Vec<TestOutcome>,
+}
+
+/// The `ctest` options of `cpppm test`
+#[derive(Debug, Clone, Default)]
+pub struct TestOptions {
+    /// A regex of the names of the tests to run
+    pub filter: Option<String>,
+    pub jobs: usize,
+    /// The time in seconds after which a test is stopped and counted as failed
+    pub timeout: Option<u64>,
+}
+
+impl TestReport {
+    pub fn count(&self, status: TestStatus) -> usize {
+        self.tests
+            .iter()
+            .filter(|test| test.status == status)
+            .count()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &TestOutcome> {
+        self.tests
+            .iter()
+            .filter(|test| matches!(test.status, TestStatus::Failed | TestStatus::Timeout))
+    }
+}
+
+/// Runs the tests registered in `build_dir` with CTest, for the `config` configuration of
+/// multi-config generators. The results are read from CTest's JUnit report, which needs
+/// CMake 3.21 or newer.
+pub fn run_ctest(
+    build_dir: &Path,
+    config: &str,
+    options: &TestOptions,
+) -> Result<TestReport, Error> {
+    let junit_path = std::path::absolute(build_dir.join("c3pm").join("ctest-results.xml"))?;
+
+    // Never read the results of an earlier run
+    if junit_path.exists() {
+        fs::remove_file(&junit_path)?;
+    }
+
+    let mut command = Command::new("ctest");
+    command
+        .current_dir(build_dir)
+        .args(["-C", config, "--output-junit"])
+        .arg(&junit_path)
+        .arg("--parallel")
+        .arg(options.jobs.max(1).to_string());
+
+    if let Some(filter) = &options.filter {
+        command.arg("-R").arg(filter);
+    }
+
+    if let Some(timeout) = options.timeout {
+        command.arg("--timeout").arg(timeout.to_string());
+    }
+
+    let output = command.output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if !junit_path.exists() {
+        if stdout.contains("No tests were found") || stderr.contains("No tests were found") {
+            return Ok(TestReport::default());
+        }
+
+        return Err(Error::other(format!(
+            "ctest didn't write its results ({}), it needs CMake 3.21 or newer: {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+
+    let report = parse_junit_report(&fs::read_to_string(&junit_path)?).map_err(Error::other)?;
+
+    // Failing tests make `ctest` fail too, which only is an error when nothing ran
+    if !output.status.success() && report.tests.is_empty() {
+        return Err(Error::other(format!("ctest failed: {}", stderr.trim())));
+    }
+
+    Ok(report)
+}
+
+/// Reads the results out of a `ctest --output-junit` report, which has a
+/// `<testcase name=".." status="run|fail|notrun|disabled">` per test, holding a `<failure>`
+/// whose message is `Failed` or `Timeout` when it failed, and its output in `<system-out>`
+pub fn parse_junit_report(xml: &str) -> Result<TestReport, String> {
+    let document = roxmltree::Document::parse(xml)
+        .map_err(|e| format!("Invalid CTest JUnit report: {}", e))?;
+    let mut report = TestReport::default();
+
+    for testcase in document
+        .descendants()
+        .filter(|node| node.has_tag_name("testcase"))
+    {
+        let name = testcase
+            .attribute("name")
+            .ok_or("A test of the CTest JUnit report has no name")?;
+        let child = |tag: &str| testcase.children().find(|node| node.has_tag_name(tag));
+
+        let status = match (testcase.attribute("status"), child("failure")) {
+            (_, Some(failure)) if failure.attribute("message") == Some("Timeout") => {
+                TestStatus::Timeout
+            }
+            (_, Some(_)) | (Some("fail"), None) => TestStatus::Failed,
+            (Some("notrun" | "disabled"), None) => TestStatus::Skipped,
+            _ if child("skipped").is_some() => TestStatus::Skipped,
+            _ => TestStatus::Passed,
+        };
+
+        let output = child("system-out")
+            .and_then(|system_out| system_out.text())
+            .unwrap_or_default();
+
+        report.tests.push(TestOutcome {
+            name: name.to_string(),
+            status,
+            output: output.trim_end().to_string(),
+        });
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="app" tests="4" failures="2" disabled="1" skipped="0" hostname="" time="2" timestamp="2026-10-18T10:00:00">
+	<testcase name="parser" classname="parser" time="0.01" status="run">
+		<system-out>[==========] 3 tests ran.
+[  PASSED  ] 3 tests.
+</system-out>
+	</testcase>
+	<testcase name="lexer" classname="lexer" time="0.02" status="fail">
+		<failure message="Failed"/>
+		<system-out>lexer.cpp:12: Failure
+Expected equality of these values:
+  tokens.size()
+    Which is: 2
+  3
+</system-out>
+	</testcase>
+	<testcase name="network" classname="network" time="1.5" status="fail">
+		<failure message="Timeout"/>
+		<system-out>connecting...
+</system-out>
+	</testcase>
+	<testcase name="gpu" classname="gpu" time="0" status="disabled">
+		<skipped message="Disabled"/>
+		<system-out>Disabled</system-out>
+	</testcase>
+</testsuite>
+"#;
+
+    #[test]
+    fn parse_junit_report_reads_every_test() {
+        let report = parse_junit_report(REPORT).unwrap();
+        let statuses = report
+            .tests
+            .iter()
+            .map(|test| (test.name.as_str(), test.status))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            statuses,
+            [
+                ("parser", TestStatus::Passed),
+                ("lexer", TestStatus::Failed),
+                ("network", TestStatus::Timeout),
+                ("gpu", TestStatus::Skipped),
+            ]
+        );
+        assert_eq!(report.count(TestStatus::Passed), 1);
+        assert_eq!(report.failures().count(), 2);
+    }
+
+    #[test]
+    fn parse_junit_report_keeps_the_output_of_each_test() {
+        let report = parse_junit_report(REPORT).unwrap();
+
+        assert_eq!(
+            report.tests[1].output,
+            "lexer.cpp:12: Failure\nExpected equality of these values:\n  tokens.size()\n    Which is: 2\n  3"
+        );
+        assert_eq!(report.tests[2].output, "connecting...");
+    }
+
+    #[test]
+    fn parse_junit_report_handles_empty_and_invalid_reports() {
+        let empty = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="app" tests="0" failures="0" disabled="0" skipped="0" hostname="" time="0" timestamp="2026-10-18T10:00:00">
+</testsuite>
+"#;
+
+        assert!(parse_junit_report(empty).unwrap().tests.is_empty());
+        assert!(parse_junit_report("<testsuite>")
+            .unwrap_err()
+            .starts_with("Invalid CTest JUnit report"));
+    }
+}